@@ -1,8 +1,19 @@
-use std::{io::{Read, self}, path::Path};
+use std::{
+    fmt::Display,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
 
-use prettytable::{Table, row};
+use prettytable::{row, Table};
 
-use crate::bytestream::{Readable, ByteStream, SECTOR_SIZE};
+use crate::{
+    bytestream::{image_size_in_sectors, write_sectors_to_file, ByteStream, Readable, SECTOR_SIZE},
+    checksum::AppleChecksum,
+    gpt::valid_invalid,
+};
+
+// The fields of the driver descriptor map followed by its 8 driver entries, the rest of block 0 is unused
+const DRIVER_DESCRIPTOR_MAP_SIZE: usize = 18 + 8 * 8;
 
 #[derive(Debug, Copy, Clone)]
 struct DriverDescriptorEntry {
@@ -25,7 +36,7 @@ impl Readable for DriverDescriptorEntry {
 }
 
 #[derive(Debug)]
-struct DriverDescriptorMap {
+pub struct DriverDescriptorMap {
     // 2 bytes
     signature: [u8; 2],
     block_size: u16,
@@ -55,22 +66,180 @@ impl Readable for DriverDescriptorMap {
     }
 }
 
-pub fn is_apm_disk(path: &str) -> io::Result<bool> {
-    let mut stream = ByteStream::new(&Path::new(path), SECTOR_SIZE, 0)?;
-    let driver_descriptor_map = stream.read::<DriverDescriptorMap>()?;
-    // println!("Block Size: {}", driver_descriptor_map.block_size);
-    // println!("Block Count: {}", driver_descriptor_map.block_count);
-    Ok(driver_descriptor_map.signature == *b"ER")
+impl DriverDescriptorMap {
+    pub fn new(block_size: u16, block_count: u32) -> Self {
+        Self {
+            signature: *b"ER",
+            block_size,
+            block_count,
+            device_type: 0,
+            device_id: 0,
+            driver_data: 0,
+            driver_descriptor_count: 0,
+            driver_descriptor_map: [DriverDescriptorEntry {
+                start_lba: 0,
+                size_in_sectors: 0,
+                sys_type: 0,
+            }; 8],
+        }
+    }
+
+    /// The drivers listed in the map, `driver_descriptor_count` is capped at the 8 entries that are read.
+    fn drivers(&self) -> &[DriverDescriptorEntry] {
+        let count = (self.driver_descriptor_count as usize).min(self.driver_descriptor_map.len());
+        &self.driver_descriptor_map[..count]
+    }
+
+    /// Lists the driver starting at block `start_lba`, replacing an earlier entry for the same block.
+    pub fn add_driver(
+        &mut self,
+        start_lba: u32,
+        size_in_sectors: u16,
+        sys_type: u16,
+    ) -> io::Result<()> {
+        self.remove_driver(start_lba);
+        let count = self.drivers().len();
+        if count == self.driver_descriptor_map.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The driver descriptor map is full",
+            ));
+        }
+        self.driver_descriptor_map[count] = DriverDescriptorEntry {
+            start_lba,
+            size_in_sectors,
+            sys_type,
+        };
+        self.driver_descriptor_count = count as u16 + 1;
+        Ok(())
+    }
+
+    pub fn set_block_size(&mut self, block_size: u16) {
+        self.block_size = block_size;
+    }
+
+    pub fn remove_driver(&mut self, start_lba: u32) {
+        let mut drivers: Vec<DriverDescriptorEntry> = self.drivers().to_vec();
+        drivers.retain(|driver| driver.start_lba != start_lba);
+        self.driver_descriptor_count = drivers.len() as u16;
+        for (index, entry) in self.driver_descriptor_map.iter_mut().enumerate() {
+            *entry = drivers
+                .get(index)
+                .copied()
+                .unwrap_or(DriverDescriptorEntry {
+                    start_lba: 0,
+                    size_in_sectors: 0,
+                    sys_type: 0,
+                });
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; SECTOR_SIZE] {
+        let mut bytes = [0u8; SECTOR_SIZE];
+        bytes[0..2].copy_from_slice(&self.signature);
+        bytes[2..4].copy_from_slice(&self.block_size.to_be_bytes());
+        bytes[4..8].copy_from_slice(&self.block_count.to_be_bytes());
+        bytes[8..10].copy_from_slice(&self.device_type.to_be_bytes());
+        bytes[10..12].copy_from_slice(&self.device_id.to_be_bytes());
+        bytes[12..16].copy_from_slice(&self.driver_data.to_be_bytes());
+        bytes[16..18].copy_from_slice(&self.driver_descriptor_count.to_be_bytes());
+        for (index, driver) in self.driver_descriptor_map.iter().enumerate() {
+            let offset = 18 + index * 8;
+            bytes[offset..offset + 4].copy_from_slice(&driver.start_lba.to_be_bytes());
+            bytes[offset + 4..offset + 6].copy_from_slice(&driver.size_in_sectors.to_be_bytes());
+            bytes[offset + 6..offset + 8].copy_from_slice(&driver.sys_type.to_be_bytes());
+        }
+        bytes
+    }
+}
+
+/// Name of the operating system a driver is for.
+fn driver_os_type(sys_type: u16) -> &'static str {
+    match sys_type {
+        0x0001 => "Mac OS",
+        0x0701 => "Mac OS (native)",
+        0xF8FF => "Mac OS (ATAPI)",
+        _ => "Unknown",
+    }
 }
 
-#[derive(Debug)]
+pub fn is_apm_disk(path: &Path) -> io::Result<bool> {
+    let mut stream = ByteStream::new(path, SECTOR_SIZE, 0)?;
+    Ok(stream.read_byte_array::<2>()? == *b"ER")
+}
+
+// Unicode characters of the Mac OS Roman bytes 0x80-0xFF, the lower half is ASCII
+// https://www.unicode.org/Public/MAPPINGS/VENDORS/APPLE/ROMAN.TXT
+const MAC_ROMAN_HIGH: [char; 128] = [
+    '\u{00C4}', '\u{00C5}', '\u{00C7}', '\u{00C9}', '\u{00D1}', '\u{00D6}', '\u{00DC}', '\u{00E1}',
+    '\u{00E0}', '\u{00E2}', '\u{00E4}', '\u{00E3}', '\u{00E5}', '\u{00E7}', '\u{00E9}', '\u{00E8}',
+    '\u{00EA}', '\u{00EB}', '\u{00ED}', '\u{00EC}', '\u{00EE}', '\u{00EF}', '\u{00F1}', '\u{00F3}',
+    '\u{00F2}', '\u{00F4}', '\u{00F6}', '\u{00F5}', '\u{00FA}', '\u{00F9}', '\u{00FB}', '\u{00FC}',
+    '\u{2020}', '\u{00B0}', '\u{00A2}', '\u{00A3}', '\u{00A7}', '\u{2022}', '\u{00B6}', '\u{00DF}',
+    '\u{00AE}', '\u{00A9}', '\u{2122}', '\u{00B4}', '\u{00A8}', '\u{2260}', '\u{00C6}', '\u{00D8}',
+    '\u{221E}', '\u{00B1}', '\u{2264}', '\u{2265}', '\u{00A5}', '\u{00B5}', '\u{2202}', '\u{2211}',
+    '\u{220F}', '\u{03C0}', '\u{222B}', '\u{00AA}', '\u{00BA}', '\u{03A9}', '\u{00E6}', '\u{00F8}',
+    '\u{00BF}', '\u{00A1}', '\u{00AC}', '\u{221A}', '\u{0192}', '\u{2248}', '\u{2206}', '\u{00AB}',
+    '\u{00BB}', '\u{2026}', '\u{00A0}', '\u{00C0}', '\u{00C3}', '\u{00D5}', '\u{0152}', '\u{0153}',
+    '\u{2013}', '\u{2014}', '\u{201C}', '\u{201D}', '\u{2018}', '\u{2019}', '\u{00F7}', '\u{25CA}',
+    '\u{00FF}', '\u{0178}', '\u{2044}', '\u{20AC}', '\u{2039}', '\u{203A}', '\u{FB01}', '\u{FB02}',
+    '\u{2021}', '\u{00B7}', '\u{201A}', '\u{201E}', '\u{2030}', '\u{00C2}', '\u{00CA}', '\u{00C1}',
+    '\u{00CB}', '\u{00C8}', '\u{00CD}', '\u{00CE}', '\u{00CF}', '\u{00CC}', '\u{00D3}', '\u{00D4}',
+    '\u{F8FF}', '\u{00D2}', '\u{00DA}', '\u{00DB}', '\u{00D9}', '\u{0131}', '\u{02C6}', '\u{02DC}',
+    '\u{00AF}', '\u{02D8}', '\u{02D9}', '\u{02DA}', '\u{00B8}', '\u{02DD}', '\u{02DB}', '\u{02C7}',
+];
+
+/// Decodes a NUL terminated Mac OS Roman string, dropping the trailing spaces some tools pad with.
+fn decode_mac_roman(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .take_while(|byte| **byte != 0)
+        .map(|byte| match byte {
+            0x00..=0x7F => *byte as char,
+            _ => MAC_ROMAN_HIGH[(byte - 0x80) as usize],
+        })
+        .collect::<String>()
+        .trim_end_matches(' ')
+        .to_string()
+}
+
+/// Encodes `text` as Mac OS Roman into a NUL padded buffer of `S` bytes.
+fn encode_mac_roman<const S: usize>(text: &str) -> io::Result<[u8; S]> {
+    let mut bytes = [0u8; S];
+    for (index, character) in text.chars().enumerate() {
+        let byte = match character {
+            '\0'..='\u{7F}' => character as u8,
+            _ => match MAC_ROMAN_HIGH.iter().position(|high| *high == character) {
+                Some(position) => 0x80 + position as u8,
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("`{}` can't be stored in Mac OS Roman", character),
+                    ))
+                }
+            },
+        };
+        match bytes.get_mut(index) {
+            Some(slot) => *slot = byte,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("`{}` is longer than {} bytes", text, S),
+                ))
+            }
+        }
+    }
+    Ok(bytes)
+}
+
+#[derive(Debug, Clone)]
 pub struct ApmPartitionTable {
-    signature: String,
+    signature: [u8; 2],
     number_of_partitions: u32,
     starting_lba: u32,
     size_in_sectors: u32,
-    partition_name: String,
-    partition_type: String,
+    partition_name: [u8; 32],
+    partition_type: [u8; 32],
     starting_lba_of_data: u32,
     size_in_sectors_of_data: u32,
     partition_status: u32,
@@ -80,6 +249,8 @@ pub struct ApmPartitionTable {
     boot_entry_point: u32,
     checksum: u32,
     processor_type: [u8; 16],
+    // Whether the boot code matches `checksum`, `None` until it is read or when no checksum is stored
+    boot_code_checksum_valid: Option<bool>,
 }
 
 impl Readable for ApmPartitionTable {
@@ -87,14 +258,14 @@ impl Readable for ApmPartitionTable {
     where
         Self: Sized,
     {
-        let signature = String::from_utf8(reader.read_byte_array::<2>()?.to_vec()).unwrap();
+        let signature = reader.read_byte_array::<2>()?;
         // reserved 2
         let _ = reader.read_byte_array::<2>()?;
         let number_of_partitions = reader.read_be::<u32>()?;
         let starting_lba = reader.read_be::<u32>()?;
         let size_in_sectors = reader.read_be::<u32>()?;
-        let partition_name = String::from_utf8(reader.read_byte_array::<32>()?.to_vec()).unwrap();
-        let partition_type = String::from_utf8(reader.read_byte_array::<32>()?.to_vec()).unwrap();
+        let partition_name = reader.read_byte_array::<32>()?;
+        let partition_type = reader.read_byte_array::<32>()?;
         let starting_lba_of_data = reader.read_be::<u32>()?;
         let size_in_sectors_of_data = reader.read_be::<u32>()?;
         let partition_status = reader.read_be::<u32>()?;
@@ -124,19 +295,189 @@ impl Readable for ApmPartitionTable {
             boot_entry_point,
             checksum,
             processor_type,
+            boot_code_checksum_valid: None,
         })
     }
 }
 
 impl ApmPartitionTable {
+    /// A map entry whose data area spans the whole partition, with the status flags Apple's tools set for its type.
+    pub fn new(
+        starting_lba: u32,
+        size_in_sectors: u32,
+        name: &str,
+        partition_type: &str,
+    ) -> io::Result<Self> {
+        use ApmPartitionStatus::*;
+        let flags: &[ApmPartitionStatus] = match partition_type {
+            "Apple_Free" => &[],
+            "Apple_partition_map" => &[Valid, Allocated, Readable, Writable],
+            driver if driver.starts_with("Apple_Driver") => &[
+                Valid,
+                Allocated,
+                InUse,
+                ContainsBootInfo,
+                Readable,
+                Writable,
+                PositionIndependent,
+            ],
+            _ => &[Valid, Allocated, Readable, Writable, AutomaticallyMount],
+        };
+        let partition_status = flags.iter().fold(0, |status, flag| status | *flag as u32);
+        Ok(Self {
+            signature: *b"PM",
+            number_of_partitions: 0,
+            starting_lba,
+            size_in_sectors,
+            partition_name: encode_mac_roman(name)?,
+            partition_type: encode_mac_roman(partition_type)?,
+            starting_lba_of_data: 0,
+            size_in_sectors_of_data: if partition_type == "Apple_Free" {
+                0
+            } else {
+                size_in_sectors
+            },
+            partition_status,
+            starting_lba_boot_code: 0,
+            size_boot_code: 0,
+            address_boot_loader: 0,
+            boot_entry_point: 0,
+            checksum: 0,
+            processor_type: [0; 16],
+            boot_code_checksum_valid: None,
+        })
+    }
+
+    pub fn to_bytes(&self) -> [u8; SECTOR_SIZE] {
+        let mut bytes = [0u8; SECTOR_SIZE];
+        bytes[0..2].copy_from_slice(&self.signature);
+        bytes[4..8].copy_from_slice(&self.number_of_partitions.to_be_bytes());
+        bytes[8..12].copy_from_slice(&self.starting_lba.to_be_bytes());
+        bytes[12..16].copy_from_slice(&self.size_in_sectors.to_be_bytes());
+        bytes[16..48].copy_from_slice(&self.partition_name);
+        bytes[48..80].copy_from_slice(&self.partition_type);
+        let fields = [
+            self.starting_lba_of_data,
+            self.size_in_sectors_of_data,
+            self.partition_status,
+            self.starting_lba_boot_code,
+            self.size_boot_code,
+            self.address_boot_loader,
+            0,
+            self.boot_entry_point,
+            0,
+            self.checksum,
+        ];
+        for (index, field) in fields.iter().enumerate() {
+            bytes[80 + index * 4..84 + index * 4].copy_from_slice(&field.to_be_bytes());
+        }
+        bytes[120..136].copy_from_slice(&self.processor_type);
+        bytes
+    }
+
+    pub fn set_number_of_partitions(&mut self, number_of_partitions: u32) {
+        self.number_of_partitions = number_of_partitions;
+    }
+
+    /// Moves the end of the partition, a data area spanning the whole partition follows along.
+    pub fn set_size_in_sectors(&mut self, size_in_sectors: u32) {
+        if self.starting_lba_of_data == 0 && self.size_in_sectors_of_data == self.size_in_sectors {
+            self.size_in_sectors_of_data = size_in_sectors;
+        }
+        self.size_in_sectors = size_in_sectors;
+    }
+
     pub fn is_valid_apm_partition_table_entry(&self) -> bool {
-        self.signature == "PM"
+        self.signature == *b"PM"
+    }
+
+    pub fn starting_lba(&self) -> u32 {
+        self.starting_lba
+    }
+
+    pub fn size_in_sectors(&self) -> u32 {
+        self.size_in_sectors
+    }
+
+    pub fn name(&self) -> String {
+        decode_mac_roman(&self.partition_name)
+    }
+
+    pub fn partition_type(&self) -> String {
+        decode_mac_roman(&self.partition_type)
+    }
+
+    /// The name exactly as stored, including the padding.
+    pub fn name_bytes(&self) -> &[u8; 32] {
+        &self.partition_name
+    }
+
+    /// The type exactly as stored, including the padding.
+    pub fn partition_type_bytes(&self) -> &[u8; 32] {
+        &self.partition_type
+    }
+
+    pub fn is_free(&self) -> bool {
+        self.partition_type() == "Apple_Free"
+    }
+
+    pub fn is_partition_map(&self) -> bool {
+        self.partition_type() == "Apple_partition_map"
+    }
+
+    pub fn is_driver(&self) -> bool {
+        self.partition_type().starts_with("Apple_Driver")
+    }
+
+    pub fn status_flags(&self) -> Vec<ApmPartitionStatus> {
+        ApmPartitionStatus::ALL
+            .into_iter()
+            .filter(|flag| self.partition_status & *flag as u32 != 0)
+            .collect()
+    }
+
+    /// Names of the set status bits, including the ones without a defined meaning.
+    pub fn describe_status(&self) -> Vec<String> {
+        let mut descriptions: Vec<String> = self
+            .status_flags()
+            .iter()
+            .map(|flag| flag.to_string())
+            .collect();
+        let known = ApmPartitionStatus::ALL
+            .iter()
+            .fold(0, |known, flag| known | *flag as u32);
+        for bit in (0..32).filter(|bit| self.partition_status & !known & (1 << bit) != 0) {
+            descriptions.push(format!("Reserved bit {}", bit));
+        }
+        descriptions
+    }
+
+    /// The blocks of the file system, as an inclusive range of absolute block numbers.
+    pub fn data_area(&self) -> Option<(u64, u64)> {
+        let start = self.starting_lba as u64 + self.starting_lba_of_data as u64;
+        (self.size_in_sectors_of_data != 0)
+            .then(|| (start, start + self.size_in_sectors_of_data as u64 - 1))
+    }
+
+    pub fn has_boot_code(&self) -> bool {
+        self.size_boot_code != 0
+    }
+
+    // The boot code starts `starting_lba_boot_code` blocks into the partition
+    fn boot_code_block(&self) -> u64 {
+        self.starting_lba as u64 + self.starting_lba_boot_code as u64
+    }
+
+    pub fn processor_type(&self) -> String {
+        String::from_utf8_lossy(&self.processor_type)
+            .trim_end_matches('\0')
+            .to_string()
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 #[repr(u32)]
-enum ApmPartitionStatus {
+pub enum ApmPartitionStatus {
     Valid = 0x00000001,
     Allocated = 0x00000002,
     InUse = 0x00000004,
@@ -151,39 +492,664 @@ enum ApmPartitionStatus {
     StartupPartition = 0x80000000,
 }
 
-pub fn parse_apm(path: &str) -> io::Result<Vec<ApmPartitionTable>> {
-    let mut partition_tables = Vec::new();
+impl ApmPartitionStatus {
+    const ALL: [ApmPartitionStatus; 12] = [
+        Self::Valid,
+        Self::Allocated,
+        Self::InUse,
+        Self::ContainsBootInfo,
+        Self::Readable,
+        Self::Writable,
+        Self::PositionIndependent,
+        Self::ChainCompatibleDrive,
+        Self::RealDriver,
+        Self::ChainDriver,
+        Self::AutomaticallyMount,
+        Self::StartupPartition,
+    ];
+}
+
+impl Display for ApmPartitionStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Valid => "Valid",
+            Self::Allocated => "Allocated",
+            Self::InUse => "In use",
+            Self::ContainsBootInfo => "Bootable",
+            Self::Readable => "Readable",
+            Self::Writable => "Writable",
+            Self::PositionIndependent => "Position independent boot code",
+            Self::ChainCompatibleDrive => "Chain compatible driver",
+            Self::RealDriver => "Real driver",
+            Self::ChainDriver => "Chain driver",
+            Self::AutomaticallyMount => "Auto-mount",
+            Self::StartupPartition => "Startup",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The partition map of an APM disk and every inconsistency found while reading it.
+#[derive(Debug)]
+pub struct ApmPartitionMap {
+    // Bytes per block, the unit of every block number and size in the map
+    pub block_size: u32,
+    pub driver_descriptor_map: DriverDescriptorMap,
+    pub partitions: Vec<ApmPartitionTable>,
+    pub warnings: Vec<String>,
+}
 
-    for i in 1..63 {
-        let mut stream = ByteStream::new(&Path::new(path), SECTOR_SIZE, i)?;
-        let partition_table = stream.read::<ApmPartitionTable>()?;
-        if !partition_table.is_valid_apm_partition_table_entry() {
+impl ApmPartitionMap {
+    /// Number of 512 byte sectors per block.
+    pub fn sectors_per_block(&self) -> u64 {
+        self.block_size as u64 / SECTOR_SIZE as u64
+    }
+
+    /// The `Apple_Driver*` partition holding a driver of the driver descriptor map.
+    fn driver_partition(&self, driver: &DriverDescriptorEntry) -> Option<&ApmPartitionTable> {
+        self.partitions
+            .iter()
+            .find(|partition| partition.is_driver() && partition.starting_lba == driver.start_lba)
+    }
+}
+
+/// Reads the partition map, one entry per block starting at block 1, using the block size of the driver
+/// descriptor map (2048 bytes on CDs) and the map length stored in the first entry.
+pub fn parse_apm(path: &Path) -> io::Result<ApmPartitionMap> {
+    let mut warnings = Vec::new();
+    let driver_descriptor_map =
+        ByteStream::new(path, SECTOR_SIZE, 0)?.read::<DriverDescriptorMap>()?;
+    if driver_descriptor_map.signature != *b"ER" {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Block 0 does not hold a driver descriptor map",
+        ));
+    }
+    let block_size = match driver_descriptor_map.block_size as u32 {
+        block_size if block_size >= SECTOR_SIZE as u32 && block_size % SECTOR_SIZE as u32 == 0 => {
+            block_size
+        }
+        block_size => {
+            warnings.push(format!(
+                "Driver descriptor map has an invalid block size of {} bytes, using {} bytes",
+                block_size, SECTOR_SIZE
+            ));
+            SECTOR_SIZE as u32
+        }
+    };
+    let sectors_per_block = block_size as u64 / SECTOR_SIZE as u64;
+    let total_blocks = image_size_in_sectors(path)? / sectors_per_block;
+    let read_entry = |block: u64| -> io::Result<ApmPartitionTable> {
+        ByteStream::new(path, SECTOR_SIZE, block * sectors_per_block)?.read::<ApmPartitionTable>()
+    };
+
+    let first = read_entry(1)?;
+    if !first.is_valid_apm_partition_table_entry() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Block 1 does not hold a partition map entry",
+        ));
+    }
+    let map_entries = first.number_of_partitions as u64;
+    // The map can't extend past the end of the image
+    let readable_entries = map_entries.min(total_blocks.saturating_sub(1));
+    if readable_entries < map_entries {
+        warnings.push(format!(
+            "The partition map has {} entries but only {} fit in the image",
+            map_entries, readable_entries
+        ));
+    }
+
+    let mut partitions = vec![first];
+    for block in 2..=readable_entries {
+        let partition = read_entry(block)?;
+        if !partition.is_valid_apm_partition_table_entry() {
+            warnings.push(format!(
+                "Entry {} of the {} entry partition map has no `PM` signature",
+                block, map_entries
+            ));
             break;
         }
-        println!("partition_table: {:#?}", partition_table);
-        partition_tables.push(partition_table);
+        if partition.number_of_partitions as u64 != map_entries {
+            warnings.push(format!(
+                "Entry {} reports {} map entries but entry 1 reports {}",
+                block, partition.number_of_partitions, map_entries
+            ));
+        }
+        partitions.push(partition);
+    }
+    let next_block = readable_entries + 1;
+    if next_block < total_blocks && read_entry(next_block)?.is_valid_apm_partition_table_entry() {
+        warnings.push(format!(
+            "Block {} holds a partition map entry past the {} entries of the map",
+            next_block, map_entries
+        ));
+    }
+
+    for (index, partition) in partitions.iter().enumerate() {
+        for (field, bytes) in [
+            ("name", partition.name_bytes()),
+            ("type", partition.partition_type_bytes()),
+        ] {
+            // Anything but padding after the terminating NUL was left behind by an earlier, longer string or hidden there
+            if bytes
+                .iter()
+                .skip_while(|byte| **byte != 0)
+                .any(|byte| *byte != 0)
+            {
+                warnings.push(format!(
+                    "The {} of entry {} holds data after its terminating NUL",
+                    field,
+                    index + 1
+                ));
+            }
+        }
+    }
+    let image_size = image_size_in_sectors(path)? * SECTOR_SIZE as u64;
+    for (index, partition) in partitions.iter_mut().enumerate() {
+        if !partition.has_boot_code() {
+            continue;
+        }
+        // The size comes straight from the disk, so nothing past the partition or the image is read
+        let boot_code_end = partition.starting_lba_boot_code as u64 * block_size as u64
+            + partition.size_boot_code as u64;
+        if boot_code_end > partition.size_in_sectors as u64 * block_size as u64 {
+            warnings.push(format!(
+                "The boot code of entry {} extends past the end of its partition",
+                index + 1
+            ));
+            continue;
+        }
+        if partition.checksum == 0 {
+            continue;
+        }
+        let boot_code_start = partition.boot_code_block() * block_size as u64;
+        if boot_code_start + partition.size_boot_code as u64 > image_size {
+            warnings.push(format!(
+                "The boot code of entry {} lies past the end of the image",
+                index + 1
+            ));
+            continue;
+        }
+        let boot_code_size = partition.size_boot_code as usize;
+        let boot_code = ByteStream::new(
+            path,
+            boot_code_size,
+            partition.boot_code_block() * sectors_per_block,
+        )?
+        .read_raw(boot_code_size)?;
+        // Only the low word holds the checksum
+        let mut checksum = AppleChecksum::default();
+        checksum.update(&boot_code);
+        partition.boot_code_checksum_valid =
+            Some(checksum.finish() as u32 == partition.checksum & 0xFFFF);
     }
 
-    Ok(partition_tables)
+    if driver_descriptor_map.driver_descriptor_count as usize
+        > driver_descriptor_map.driver_descriptor_map.len()
+    {
+        warnings.push(format!(
+            "The driver descriptor map lists {} drivers, only the first {} are read",
+            driver_descriptor_map.driver_descriptor_count,
+            driver_descriptor_map.driver_descriptor_map.len()
+        ));
+    }
+    for (index, partition) in partitions
+        .iter()
+        .enumerate()
+        .filter(|(_, partition)| partition.is_driver())
+    {
+        if !driver_descriptor_map
+            .drivers()
+            .iter()
+            .any(|driver| driver.start_lba == partition.starting_lba)
+        {
+            warnings.push(format!(
+                "Driver partition {} ({}) is not listed in the driver descriptor map",
+                index + 1,
+                partition.partition_type()
+            ));
+        }
+    }
+
+    Ok(ApmPartitionMap {
+        block_size,
+        driver_descriptor_map,
+        partitions,
+        warnings,
+    })
 }
 
-pub fn display_apm_partitions(partitions: Vec<ApmPartitionTable>) {
+/// Writes the driver descriptor map over the start of block 0 and each map entry to the start of its own
+/// block from block 1. Everything else is left alone, as hybrid images keep an MBR and a GPT in the same
+/// sectors. Entries left behind a map that shrank are cleared so they aren't read as part of it.
+pub fn write_apm(
+    path: &Path,
+    driver_descriptor_map: &DriverDescriptorMap,
+    partitions: &[ApmPartitionTable],
+) -> io::Result<()> {
+    let block_size = driver_descriptor_map.block_size as usize;
+    if block_size < SECTOR_SIZE || !block_size.is_multiple_of(SECTOR_SIZE) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "The driver descriptor map has an invalid block size of {} bytes",
+                block_size
+            ),
+        ));
+    }
+    let map_blocks = partitions
+        .iter()
+        .find(|partition| partition.is_partition_map())
+        .map_or(partitions.len() as u64, |partition| {
+            partition.size_in_sectors as u64
+        });
+    if partitions.len() as u64 > map_blocks {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{} entries don't fit in a partition map of {} blocks",
+                partitions.len(),
+                map_blocks
+            ),
+        ));
+    }
+    let sectors_per_block = (block_size / SECTOR_SIZE) as u64;
+    let total_blocks = image_size_in_sectors(path)? / sectors_per_block;
+
+    let mut sector = ByteStream::new(path, SECTOR_SIZE, 0)?.read_raw(SECTOR_SIZE)?;
+    sector[..DRIVER_DESCRIPTOR_MAP_SIZE]
+        .copy_from_slice(&driver_descriptor_map.to_bytes()[..DRIVER_DESCRIPTOR_MAP_SIZE]);
+    write_sectors_to_file(path, 0, &sector)?;
+    for (index, partition) in partitions.iter().enumerate() {
+        let block = index as u64 + 1;
+        write_sectors_to_file(path, block * sectors_per_block, &partition.to_bytes())?;
+    }
+    for block in
+        (partitions.len() as u64 + 1..=map_blocks).take_while(|block| *block < total_blocks)
+    {
+        let lba = block * sectors_per_block;
+        let sector = ByteStream::new(path, SECTOR_SIZE, lba)?.read_raw(SECTOR_SIZE)?;
+        if !sector.starts_with(b"PM") {
+            break;
+        }
+        write_sectors_to_file(path, lba, &[0u8; SECTOR_SIZE])?;
+    }
+    Ok(())
+}
+
+pub fn display_apm_partitions(map: &ApmPartitionMap) {
+    println!(
+        "Apple partition map with {} entries, LBAs are in {} byte blocks",
+        map.partitions.len(),
+        map.block_size
+    );
     let mut table = Table::new();
     table.add_row(row![
+        "Number",
         "Starting LBA",
         "Ending LBA",
         "Size in Sectors",
         "Partition Name",
         "Partition Type",
+        "Data Area",
+        "Status",
     ]);
-    for partition in partitions {
+    for (index, partition) in map.partitions.iter().enumerate() {
+        let data_area = match partition.data_area() {
+            Some((start, end)) => format!("{}-{}", start, end),
+            None => "None".to_string(),
+        };
         table.add_row(row![
+            index + 1,
             partition.starting_lba,
-            partition.starting_lba + partition.size_in_sectors - 1,
+            (partition.starting_lba + partition.size_in_sectors).saturating_sub(1),
             partition.size_in_sectors,
-            partition.partition_name,
-            partition.partition_type,
+            partition.name(),
+            partition.partition_type(),
+            data_area,
+            partition.describe_status().join("\n"),
         ]);
     }
     table.printstd();
-}
\ No newline at end of file
+
+    let bootable: Vec<&ApmPartitionTable> = map
+        .partitions
+        .iter()
+        .filter(|partition| partition.has_boot_code())
+        .collect();
+    if !bootable.is_empty() {
+        let mut table = Table::new();
+        table.add_row(row![
+            "Partition Name",
+            "Boot Code LBA",
+            "Size in Bytes",
+            "Load Address",
+            "Entry Point",
+            "Processor",
+            "Checksum",
+        ]);
+        for partition in bootable {
+            let checksum = match partition.boot_code_checksum_valid {
+                Some(valid) => format!("{:#06x} ({})", partition.checksum, valid_invalid(valid)),
+                None => "None".to_string(),
+            };
+            table.add_row(row![
+                partition.name(),
+                partition.boot_code_block(),
+                partition.size_boot_code,
+                format!("{:#010x}", partition.address_boot_loader),
+                format!("{:#010x}", partition.boot_entry_point),
+                partition.processor_type(),
+                checksum,
+            ]);
+        }
+        table.printstd();
+    }
+    display_driver_descriptor_map(map);
+    for warning in map.warnings.iter() {
+        println!("Warning: {}", warning);
+    }
+}
+
+fn display_driver_descriptor_map(map: &ApmPartitionMap) {
+    let ddm = &map.driver_descriptor_map;
+    println!("Driver descriptor map");
+    let mut table = Table::new();
+    table.add_row(row!["Block Size", ddm.block_size]);
+    table.add_row(row!["Block Count", ddm.block_count]);
+    table.add_row(row!["Device Type", format!("{:#06x}", ddm.device_type)]);
+    table.add_row(row!["Device ID", format!("{:#06x}", ddm.device_id)]);
+    table.add_row(row!["Driver Data", format!("{:#010x}", ddm.driver_data)]);
+    table.add_row(row!["Drivers", ddm.driver_descriptor_count]);
+    table.printstd();
+    if ddm.drivers().is_empty() {
+        return;
+    }
+
+    let mut table = Table::new();
+    table.add_row(row![
+        "Number",
+        "Starting LBA",
+        "Size in Sectors",
+        "OS Type",
+        "Partition"
+    ]);
+    for (index, driver) in ddm.drivers().iter().enumerate() {
+        let partition = match map.driver_partition(driver) {
+            Some(partition) => format!("{} ({})", partition.name(), partition.partition_type()),
+            None => "None".to_string(),
+        };
+        table.add_row(row![
+            index + 1,
+            driver.start_lba,
+            driver.size_in_sectors,
+            format!(
+                "{} ({:#06x})",
+                driver_os_type(driver.sys_type),
+                driver.sys_type
+            ),
+            partition,
+        ]);
+    }
+    table.printstd();
+}
+
+/// Writes every driver of the driver descriptor map to `driver_<number>.bin` in `output_directory`.
+/// A driver stored in an `Apple_Driver*` partition is cut to the size of the driver code recorded in the
+/// partition's boot code fields, otherwise all sectors listed in the driver descriptor map are written.
+pub fn extract_apm_drivers(
+    path: &Path,
+    map: &ApmPartitionMap,
+    output_directory: &Path,
+) -> io::Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(output_directory)?;
+    let mut files = Vec::new();
+    for (index, driver) in map.driver_descriptor_map.drivers().iter().enumerate() {
+        let size = match map.driver_partition(driver) {
+            Some(partition) if partition.has_boot_code() => partition.size_boot_code as usize,
+            _ => driver.size_in_sectors as usize * SECTOR_SIZE,
+        };
+        let mut stream = ByteStream::new(
+            path,
+            size,
+            driver.start_lba as u64 * map.sectors_per_block(),
+        )?;
+        let file = output_directory.join(format!("driver_{}.bin", index + 1));
+        std::fs::write(&file, stream.read_raw(size)?)?;
+        files.push(file);
+    }
+    Ok(files)
+}
+
+// Writes an image of `blocks` blocks holding a driver descriptor map and one map entry per
+// `(map entries, starting block, size in blocks, name, type)`.
+#[cfg(test)]
+pub(crate) fn write_test_apm(
+    name: &str,
+    block_size: u16,
+    blocks: u32,
+    entries: &[(u32, u32, u32, &str, &str)],
+) -> std::path::PathBuf {
+    let mut image = vec![0u8; blocks as usize * block_size as usize];
+    image[0..2].copy_from_slice(b"ER");
+    image[2..4].copy_from_slice(&block_size.to_be_bytes());
+    image[4..8].copy_from_slice(&blocks.to_be_bytes());
+    for (index, (map_entries, starting_block, size, name, partition_type)) in
+        entries.iter().enumerate()
+    {
+        let entry = &mut image[(index + 1) * block_size as usize..];
+        entry[0..2].copy_from_slice(b"PM");
+        entry[4..8].copy_from_slice(&map_entries.to_be_bytes());
+        entry[8..12].copy_from_slice(&starting_block.to_be_bytes());
+        entry[12..16].copy_from_slice(&size.to_be_bytes());
+        entry[16..16 + name.len()].copy_from_slice(name.as_bytes());
+        entry[48..48 + partition_type.len()].copy_from_slice(partition_type.as_bytes());
+    }
+    crate::bytestream::write_test_image(name, &image)
+}
+
+#[test]
+fn test_parse_apm_block_size() {
+    // A CD style map with 2048 byte blocks whose last entry disagrees on the map length, followed
+    // by a stale entry past the end of the map
+    let path = write_test_apm(
+        "apm_block_size",
+        2048,
+        64,
+        &[
+            (3, 1, 3, "Apple", "Apple_partition_map"),
+            (3, 16, 32, "Disk Image", "Apple_HFS"),
+            (4, 48, 16, "Extra", "Apple_Free"),
+            (4, 4, 12, "Stale", "Apple_HFS"),
+        ],
+    );
+    let map = parse_apm(&path).unwrap();
+    let layout = crate::layout::Layout::from_apm(&map, 64 * 4);
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(map.block_size, 2048);
+    assert_eq!(map.sectors_per_block(), 4);
+    assert_eq!(map.partitions.len(), 3);
+    assert_eq!(map.partitions[1].name(), "Disk Image");
+    assert_eq!(
+        map.warnings,
+        vec![
+            "Entry 3 reports 4 map entries but entry 1 reports 3",
+            "Block 4 holds a partition map entry past the 3 entries of the map",
+        ]
+    );
+    assert_eq!(layout.entries[1].starting_lba, 64);
+    assert_eq!(layout.entries[1].ending_lba, 191);
+}
+
+#[test]
+fn test_apm_status_and_boot_code() {
+    let path = write_test_apm(
+        "apm_boot_code",
+        512,
+        64,
+        &[
+            (2, 1, 2, "Apple", "Apple_partition_map"),
+            (2, 8, 32, "Boot", "Apple_Boot"),
+        ],
+    );
+    let boot_code: Vec<u8> = (0..100).collect();
+    let mut checksum = AppleChecksum::default();
+    checksum.update(&boot_code);
+    // Data area of blocks 12-39, boot code 2 blocks into the partition
+    let mut fields = Vec::new();
+    for field in [
+        4u32,
+        28,
+        0x4100_0037,
+        2,
+        100,
+        0x1000,
+        0,
+        0x1010,
+        0,
+        checksum.finish() as u32,
+    ] {
+        fields.extend_from_slice(&field.to_be_bytes());
+    }
+    fields.extend_from_slice(b"powerpc");
+    let mut entry = std::fs::read(&path).unwrap()[1024..1536].to_vec();
+    entry[80..80 + fields.len()].copy_from_slice(&fields);
+    // A Mac OS Roman name padded with spaces and a type with a remnant of an older one
+    entry[16..30].copy_from_slice(b"Syst\x8Ame \xA5 9  \0");
+    entry[48..68].copy_from_slice(b"Apple_Boot\0Apple_HFS");
+    crate::bytestream::write_sectors_to_file(&path, 2, &entry).unwrap();
+    crate::bytestream::write_sectors_to_file(&path, 10, &boot_code).unwrap();
+
+    let map = parse_apm(&path).unwrap();
+    let partition = &map.partitions[1];
+    assert_eq!(partition.data_area(), Some((12, 39)));
+    assert_eq!(
+        partition.describe_status(),
+        vec![
+            "Valid",
+            "Allocated",
+            "In use",
+            "Readable",
+            "Writable",
+            "Auto-mount",
+            "Reserved bit 24"
+        ]
+    );
+    assert_eq!(partition.processor_type(), "powerpc");
+    assert_eq!(partition.boot_code_checksum_valid, Some(true));
+    assert_eq!(partition.name(), "Syst\u{e4}me \u{2022} 9");
+    assert_eq!(partition.partition_type(), "Apple_Boot");
+    assert_eq!(&partition.name_bytes()[..14], b"Syst\x8Ame \xA5 9  \0");
+    assert_eq!(
+        map.warnings,
+        vec!["The type of entry 2 holds data after its terminating NUL"]
+    );
+
+    // A single flipped byte of boot code
+    crate::bytestream::write_sectors_to_file(&path, 10, &[1]).unwrap();
+    let map = parse_apm(&path).unwrap();
+    assert_eq!(map.partitions[1].boot_code_checksum_valid, Some(false));
+
+    // A corrupt boot code size of almost 4 GiB is reported without reading it
+    entry[96..100].copy_from_slice(&0xFFFF_FFF0u32.to_be_bytes());
+    crate::bytestream::write_sectors_to_file(&path, 2, &entry).unwrap();
+    let map = parse_apm(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(map.partitions[1].boot_code_checksum_valid, None);
+    assert!(map
+        .warnings
+        .contains(&"The boot code of entry 2 extends past the end of its partition".to_string()));
+}
+
+#[test]
+fn test_extract_apm_drivers() {
+    let path = write_test_apm(
+        "apm_drivers",
+        512,
+        64,
+        &[
+            (3, 1, 3, "Apple", "Apple_partition_map"),
+            (3, 8, 16, "Macintosh", "Apple_Driver43"),
+            (3, 24, 16, "Macintosh", "Apple_Driver_ATAPI"),
+        ],
+    );
+    // One driver at block 8 whose code fills 600 of the 16 sectors of its partition
+    let mut ddm = std::fs::read(&path).unwrap()[..512].to_vec();
+    ddm[16..18].copy_from_slice(&1u16.to_be_bytes());
+    ddm[18..26].copy_from_slice(&[0, 0, 0, 8, 0, 16, 0, 1]);
+    crate::bytestream::write_sectors_to_file(&path, 0, &ddm).unwrap();
+    let mut entry = std::fs::read(&path).unwrap()[1024..1536].to_vec();
+    entry[96..100].copy_from_slice(&600u32.to_be_bytes());
+    crate::bytestream::write_sectors_to_file(&path, 2, &entry).unwrap();
+    let driver: Vec<u8> = (0..600).map(|byte| byte as u8).collect();
+    crate::bytestream::write_sectors_to_file(&path, 8, &driver).unwrap();
+
+    let map = parse_apm(&path).unwrap();
+    assert_eq!(map.driver_descriptor_map.drivers().len(), 1);
+    assert_eq!(
+        map.driver_partition(&map.driver_descriptor_map.drivers()[0])
+            .map(|partition| partition.partition_type()),
+        Some("Apple_Driver43".to_string())
+    );
+    assert_eq!(
+        map.warnings,
+        vec!["Driver partition 3 (Apple_Driver_ATAPI) is not listed in the driver descriptor map"]
+    );
+
+    let output_directory =
+        std::env::temp_dir().join(format!("parttable_apm_drivers_{}", std::process::id()));
+    let files = extract_apm_drivers(&path, &map, &output_directory).unwrap();
+    let extracted = std::fs::read(&files[0]).unwrap();
+    std::fs::remove_dir_all(&output_directory).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(files.len(), 1);
+    assert_eq!(extracted, driver);
+}
+
+#[test]
+fn test_extract_multiple_apm_drivers() {
+    let path = write_test_apm(
+        "apm_two_drivers",
+        512,
+        64,
+        &[
+            (3, 1, 3, "Apple", "Apple_partition_map"),
+            (3, 8, 16, "Macintosh", "Apple_Driver43"),
+            (3, 24, 16, "Macintosh", "Apple_Driver_ATAPI"),
+        ],
+    );
+    // Drivers at blocks 8 and 24, each filling its partition
+    let mut ddm = std::fs::read(&path).unwrap()[..512].to_vec();
+    ddm[16..18].copy_from_slice(&2u16.to_be_bytes());
+    ddm[18..26].copy_from_slice(&[0, 0, 0, 8, 0, 16, 0, 1]);
+    ddm[26..34].copy_from_slice(&[0, 0, 0, 24, 0, 16, 0xF8, 0xFF]);
+    crate::bytestream::write_sectors_to_file(&path, 0, &ddm).unwrap();
+    crate::bytestream::write_sectors_to_file(&path, 8, &[1; 16 * SECTOR_SIZE]).unwrap();
+    crate::bytestream::write_sectors_to_file(&path, 24, &[2; 16 * SECTOR_SIZE]).unwrap();
+
+    let map = parse_apm(&path).unwrap();
+    assert!(map.warnings.is_empty(), "{:?}", map.warnings);
+    let drivers: Vec<(u32, u16, u16)> = map
+        .driver_descriptor_map
+        .drivers()
+        .iter()
+        .map(|driver| (driver.start_lba, driver.size_in_sectors, driver.sys_type))
+        .collect();
+    assert_eq!(drivers, vec![(8, 16, 0x0001), (24, 16, 0xF8FF)]);
+
+    let output_directory =
+        std::env::temp_dir().join(format!("parttable_apm_two_drivers_{}", std::process::id()));
+    let files = extract_apm_drivers(&path, &map, &output_directory).unwrap();
+    let extracted: Vec<Vec<u8>> = files
+        .iter()
+        .map(|file| std::fs::read(file).unwrap())
+        .collect();
+    std::fs::remove_dir_all(&output_directory).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(
+        extracted,
+        vec![vec![1; 16 * SECTOR_SIZE], vec![2; 16 * SECTOR_SIZE]]
+    );
+}
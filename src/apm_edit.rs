@@ -0,0 +1,438 @@
+use crate::{
+    apm::{parse_apm, write_apm, ApmPartitionMap, ApmPartitionTable, DriverDescriptorMap},
+    bytestream::{image_size_in_sectors, SECTOR_SIZE},
+};
+use std::{io, path::Path};
+
+// Blocks Apple's tools reserve for the partition map, the most entries it can hold
+pub const DEFAULT_APM_MAP_ENTRIES: u32 = 63;
+const FREE_SPACE_TYPE: &str = "Apple_Free";
+
+/// A change to the partition map. Partitions are numbered by their position in the map, starting at 1.
+#[derive(Debug)]
+pub enum ApmEdit {
+    /// Adds a partition. Without a start it is placed at the start of the first free region it fits in,
+    /// without a size it fills the free region it starts in. Sizes are in 512 byte sectors, rounded up
+    /// to whole blocks.
+    Add {
+        starting_block: Option<u64>,
+        size: Option<u64>,
+        partition_type: String,
+        name: String,
+    },
+    Delete {
+        number: u32,
+    },
+    /// Moves the end of the partition, without a size it grows into all free space following it.
+    Resize {
+        number: u32,
+        size: Option<u64>,
+    },
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// Operating system type of the driver descriptor map entry for a driver partition.
+fn driver_sys_type(partition_type: &str) -> u16 {
+    match partition_type {
+        "Apple_Driver_ATAPI" => 0xF8FF,
+        _ => 0x0001,
+    }
+}
+
+/// Driver sizes in the driver descriptor map are in 512 byte sectors.
+fn driver_size(size_in_blocks: u32, sectors_per_block: u64) -> u16 {
+    (size_in_blocks as u64 * sectors_per_block).min(u16::MAX as u64) as u16
+}
+
+/// Unallocated regions between block 1 and the end of the disk as `(start, end)` block ranges, end
+/// exclusive. `partitions` must be sorted and must not contain free space entries.
+fn free_regions(partitions: &[ApmPartitionTable], total_blocks: u64) -> Vec<(u64, u64)> {
+    let mut regions = Vec::new();
+    let mut next_free = 1;
+    for partition in partitions {
+        let start = partition.starting_lba() as u64;
+        if start > next_free {
+            regions.push((next_free, start));
+        }
+        next_free = next_free.max(start + partition.size_in_sectors() as u64);
+    }
+    if next_free < total_blocks {
+        regions.push((next_free, total_blocks));
+    }
+    regions
+}
+
+/// Rebuilds the map from its allocated partitions: they are sorted by starting block, every unallocated
+/// region gets an `Apple_Free` entry and every entry records the new length of the map.
+fn fill_free_space(
+    mut partitions: Vec<ApmPartitionTable>,
+    total_blocks: u64,
+) -> io::Result<Vec<ApmPartitionTable>> {
+    partitions.retain(|partition| !partition.is_free());
+    partitions.sort_by_key(|partition| partition.starting_lba());
+    for pair in partitions.windows(2) {
+        if (pair[0].starting_lba() as u64 + pair[0].size_in_sectors() as u64)
+            > pair[1].starting_lba() as u64
+        {
+            return Err(invalid_input(format!(
+                "{} ({}) overlaps {} ({})",
+                pair[0].name(),
+                pair[0].partition_type(),
+                pair[1].name(),
+                pair[1].partition_type()
+            )));
+        }
+    }
+    if let Some(last) = partitions.last() {
+        if last.starting_lba() as u64 + last.size_in_sectors() as u64 > total_blocks {
+            return Err(invalid_input(format!(
+                "{} ({}) ends past the end of the disk",
+                last.name(),
+                last.partition_type()
+            )));
+        }
+    }
+
+    let mut filled = Vec::new();
+    for (start, end) in free_regions(&partitions, total_blocks) {
+        filled.push(ApmPartitionTable::new(
+            start as u32,
+            (end - start) as u32,
+            "Extra",
+            FREE_SPACE_TYPE,
+        )?);
+    }
+    filled.extend(partitions);
+    filled.sort_by_key(|partition| partition.starting_lba());
+    let map_entries = filled.len() as u32;
+    for partition in filled.iter_mut() {
+        partition.set_number_of_partitions(map_entries);
+    }
+    Ok(filled)
+}
+
+fn total_blocks(path: &Path, sectors_per_block: u64) -> io::Result<u64> {
+    let total_blocks = image_size_in_sectors(path)? / sectors_per_block;
+    if total_blocks > u32::MAX as u64 {
+        return Err(invalid_input(format!(
+            "The disk has {} blocks, more than an Apple partition map can address",
+            total_blocks
+        )));
+    }
+    Ok(total_blocks)
+}
+
+/// Writes a driver descriptor map and a partition map of `map_entries` blocks to the image, leaving the
+/// rest of the disk as free space.
+pub fn create_apm(path: &Path, block_size: u16, map_entries: u32) -> io::Result<()> {
+    if block_size == 0 || !(block_size as usize).is_multiple_of(SECTOR_SIZE) {
+        return Err(invalid_input(format!(
+            "The block size must be a multiple of {} bytes",
+            SECTOR_SIZE
+        )));
+    }
+    let total_blocks = total_blocks(path, (block_size as usize / SECTOR_SIZE) as u64)?;
+    let driver_descriptor_map = DriverDescriptorMap::new(block_size, total_blocks as u32);
+    let partition_map = ApmPartitionTable::new(1, map_entries, "Apple", "Apple_partition_map")?;
+    let partitions = fill_free_space(vec![partition_map], total_blocks)?;
+    write_apm(path, &driver_descriptor_map, &partitions)
+}
+
+/// Applies `edit` to the partition map of the image, keeping the driver descriptor map in step with the
+/// driver partitions.
+pub fn edit_apm(path: &Path, edit: &ApmEdit) -> io::Result<()> {
+    let ApmPartitionMap {
+        block_size,
+        mut driver_descriptor_map,
+        partitions,
+        ..
+    } = parse_apm(path)?;
+    // An invalid block size on disk was replaced by 512 bytes, which is what gets written back
+    driver_descriptor_map.set_block_size(block_size as u16);
+    let sectors_per_block = (block_size as usize / SECTOR_SIZE) as u64;
+    let total_blocks = total_blocks(path, sectors_per_block)?;
+    let to_blocks = |sectors: u64| sectors.div_ceil(sectors_per_block);
+    let find_partition = |number: u32| -> io::Result<&ApmPartitionTable> {
+        match partitions.get((number as usize).wrapping_sub(1)) {
+            Some(partition) if partition.is_free() => Err(invalid_input(format!(
+                "Partition {} is free space, which is maintained automatically",
+                number
+            ))),
+            Some(partition) => Ok(partition),
+            None => Err(invalid_input(format!("There is no partition {}", number))),
+        }
+    };
+
+    let mut allocated: Vec<ApmPartitionTable> = partitions
+        .iter()
+        .filter(|partition| !partition.is_free())
+        .cloned()
+        .collect();
+    allocated.sort_by_key(|partition| partition.starting_lba());
+    match edit {
+        ApmEdit::Add {
+            starting_block,
+            size,
+            partition_type,
+            name,
+        } => {
+            if partition_type == FREE_SPACE_TYPE {
+                return Err(invalid_input(
+                    "Free space entries are maintained automatically".into(),
+                ));
+            }
+            let size = size.map(to_blocks);
+            let regions = free_regions(&allocated, total_blocks);
+            let (start, end) = match starting_block {
+                Some(start) => regions
+                    .iter()
+                    .find(|(region_start, end)| region_start <= start && start < end)
+                    .map(|(_, end)| (*start, *end))
+                    .ok_or_else(|| invalid_input(format!("Block {} is not free", start)))?,
+                None => regions
+                    .iter()
+                    .find(|(start, end)| end - start >= size.unwrap_or(1))
+                    .copied()
+                    .ok_or_else(|| invalid_input("No free region is large enough".into()))?,
+            };
+            let size = size.unwrap_or(end - start);
+            if start + size > end {
+                return Err(invalid_input(format!(
+                    "{} blocks starting at block {} don't fit in the free region ending at block {}",
+                    size,
+                    start,
+                    end - 1
+                )));
+            }
+            let partition =
+                ApmPartitionTable::new(start as u32, size as u32, name, partition_type)?;
+            if partition.is_driver() {
+                driver_descriptor_map.add_driver(
+                    start as u32,
+                    driver_size(size as u32, sectors_per_block),
+                    driver_sys_type(partition_type),
+                )?;
+            }
+            allocated.push(partition);
+        }
+        ApmEdit::Delete { number } => {
+            let partition = find_partition(*number)?;
+            if partition.is_partition_map() {
+                return Err(invalid_input("The partition map can't be deleted".into()));
+            }
+            if partition.is_driver() {
+                driver_descriptor_map.remove_driver(partition.starting_lba());
+            }
+            let starting_lba = partition.starting_lba();
+            allocated.retain(|partition| partition.starting_lba() != starting_lba);
+        }
+        ApmEdit::Resize { number, size } => {
+            let starting_lba = find_partition(*number)?.starting_lba();
+            let limit = allocated
+                .iter()
+                .map(|partition| partition.starting_lba() as u64)
+                .find(|start| *start > starting_lba as u64)
+                .unwrap_or(total_blocks);
+            let size = size.map(to_blocks).unwrap_or(limit - starting_lba as u64);
+            if starting_lba as u64 + size > limit {
+                return Err(invalid_input(format!(
+                    "Partition {} can grow to at most {} blocks",
+                    number,
+                    limit - starting_lba as u64
+                )));
+            }
+            let partition = allocated
+                .iter_mut()
+                .find(|partition| partition.starting_lba() == starting_lba)
+                .expect("partition is allocated");
+            partition.set_size_in_sectors(size as u32);
+            if partition.is_driver() {
+                driver_descriptor_map.add_driver(
+                    starting_lba,
+                    driver_size(size as u32, sectors_per_block),
+                    driver_sys_type(&partition.partition_type()),
+                )?;
+            }
+        }
+    }
+    let partitions = fill_free_space(allocated, total_blocks)?;
+    write_apm(path, &driver_descriptor_map, &partitions)
+}
+
+#[test]
+fn test_create_and_edit_apm() {
+    let path = crate::bytestream::blank_test_image("apm_edit", 4096);
+    create_apm(&path, 2048, DEFAULT_APM_MAP_ENTRIES).unwrap();
+
+    let add = |size: Option<u64>, partition_type: &str, name: &str| ApmEdit::Add {
+        starting_block: None,
+        size,
+        partition_type: partition_type.into(),
+        name: name.into(),
+    };
+    // 1 block for the driver, 100 blocks (400 sectors) for HFS
+    edit_apm(&path, &add(Some(3), "Apple_Driver43", "Macintosh")).unwrap();
+    edit_apm(&path, &add(Some(400), "Apple_HFS", "Untitled")).unwrap();
+    assert!(edit_apm(&path, &add(Some(4096), "Apple_HFS", "Too large")).is_err());
+    assert!(edit_apm(&path, &ApmEdit::Delete { number: 1 }).is_err());
+
+    let summary = |path: &Path| -> Vec<(u32, u32, String)> {
+        let map = parse_apm(path).unwrap();
+        assert!(map.warnings.is_empty(), "{:?}", map.warnings);
+        map.partitions
+            .iter()
+            .map(|partition| {
+                (
+                    partition.starting_lba(),
+                    partition.size_in_sectors(),
+                    partition.partition_type(),
+                )
+            })
+            .collect()
+    };
+    assert_eq!(
+        summary(&path),
+        vec![
+            (1, 63, "Apple_partition_map".to_string()),
+            (64, 1, "Apple_Driver43".to_string()),
+            (65, 100, "Apple_HFS".to_string()),
+            (165, 859, "Apple_Free".to_string()),
+        ]
+    );
+
+    // Deleting the driver leaves a free entry the grown HFS partition can't reach
+    edit_apm(&path, &ApmEdit::Delete { number: 2 }).unwrap();
+    edit_apm(
+        &path,
+        &ApmEdit::Resize {
+            number: 3,
+            size: None,
+        },
+    )
+    .unwrap();
+    let map = parse_apm(&path).unwrap();
+    assert_eq!(
+        summary(&path),
+        vec![
+            (1, 63, "Apple_partition_map".to_string()),
+            (64, 1, "Apple_Free".to_string()),
+            (65, 959, "Apple_HFS".to_string()),
+        ]
+    );
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(map.partitions[2].data_area(), Some((65, 1023)));
+}
+
+#[test]
+fn test_edit_apm_keeps_drivers() {
+    let path = crate::bytestream::blank_test_image("apm_drivers_edit", 4096);
+    create_apm(&path, 2048, DEFAULT_APM_MAP_ENTRIES).unwrap();
+    let add = |size: u64, partition_type: &str| ApmEdit::Add {
+        starting_block: None,
+        size: Some(size),
+        partition_type: partition_type.into(),
+        name: "Macintosh".into(),
+    };
+    edit_apm(&path, &add(4, "Apple_Driver43")).unwrap();
+    edit_apm(&path, &add(4, "Apple_Driver_ATAPI")).unwrap();
+    // An unrelated edit rewrites the driver descriptor map read back from the disk
+    edit_apm(&path, &add(400, "Apple_HFS")).unwrap();
+
+    let ddm = std::fs::read(&path).unwrap()[..34].to_vec();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(ddm[16..18], [0, 2]);
+    assert_eq!(ddm[18..26], [0, 0, 0, 64, 0, 4, 0x00, 0x01]);
+    assert_eq!(ddm[26..34], [0, 0, 0, 65, 0, 4, 0xF8, 0xFF]);
+}
+
+#[test]
+fn test_edit_apm_with_invalid_block_size() {
+    let path = crate::bytestream::blank_test_image("apm_block_size_edit", 4096);
+    create_apm(&path, 512, DEFAULT_APM_MAP_ENTRIES).unwrap();
+    assert!(write_apm(&path, &DriverDescriptorMap::new(0, 4096), &[]).is_err());
+    edit_apm(
+        &path,
+        &ApmEdit::Add {
+            starting_block: None,
+            size: Some(100),
+            partition_type: "Apple_HFS".into(),
+            name: "Untitled".into(),
+        },
+    )
+    .unwrap();
+    // A zeroed block size is read as 512 bytes and written back as such
+    crate::bytestream::write_sectors_to_file(&path, 0, &[b'E', b'R', 0, 0]).unwrap();
+    edit_apm(&path, &ApmEdit::Delete { number: 2 }).unwrap();
+
+    let map = parse_apm(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(map.warnings.is_empty(), "{:?}", map.warnings);
+    assert_eq!(map.driver_descriptor_map.to_bytes()[2..4], [2, 0]);
+    assert_eq!(map.partitions.len(), 2);
+}
+
+#[test]
+fn test_edit_apm_keeps_mbr_and_gpt() {
+    use crate::{
+        gpt::{parse_gpt, parse_validated_gpt_header, write_new_gpt, GptHeaderCopy},
+        guid::Guid,
+        mbr::{parse_mbr, write_mbr, MbrPartition},
+    };
+
+    // A hybrid CD image: a 4 entry APM of 2048 byte blocks from LBA 4, a GPT with its 4 entries in
+    // LBA 2 and an MBR, all three describing the partition at LBA 2048-4095
+    let path = crate::bytestream::blank_test_image("apm_hybrid", 8192);
+    create_apm(&path, 2048, 4).unwrap();
+    let entry = crate::gpt::GptPartitionTableEntry::new(
+        crate::partition_types::LINUX_FILESYSTEM_GUID,
+        Guid::new_random().unwrap(),
+        2048,
+        4095,
+        0,
+        "CD",
+    );
+    write_new_gpt(
+        &path,
+        Guid::new_random().unwrap(),
+        None,
+        None,
+        4,
+        &[(0, entry)],
+    )
+    .unwrap();
+    let partition = MbrPartition {
+        number: 1,
+        bootable: false,
+        partition_type: 0x83,
+        starting_lba: 2048,
+        num_sectors: 2048,
+    };
+    write_mbr(&path, None, &[partition]).unwrap();
+
+    edit_apm(
+        &path,
+        &ApmEdit::Add {
+            starting_block: Some(512),
+            size: Some(2048),
+            partition_type: "Apple_HFS".into(),
+            name: "CD".into(),
+        },
+    )
+    .unwrap();
+
+    let map = parse_apm(&path).unwrap();
+    let mbr = parse_mbr(&path).unwrap().partitions();
+    let validated = parse_validated_gpt_header(&path).unwrap();
+    let gpt = parse_gpt(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(map.warnings.is_empty(), "{:?}", map.warnings);
+    assert_eq!(map.partitions[2].starting_lba(), 512);
+    assert_eq!(mbr.len(), 1);
+    assert_eq!(mbr[0].starting_lba, 2048);
+    assert_eq!(validated.copy, GptHeaderCopy::Primary);
+    assert!(validated.primary_problem.is_none());
+    assert_eq!(gpt[0].1.starting_lba(), 2048);
+}
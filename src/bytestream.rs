@@ -1,6 +1,6 @@
 use std::{
-    fs::File,
-    io::{self, BufReader, Read, Seek, SeekFrom, Cursor},
+    fs::{File, OpenOptions},
+    io::{self, BufReader, Cursor, Read, Seek, SeekFrom},
     path::Path,
     string::FromUtf16Error,
 };
@@ -15,6 +15,17 @@ pub trait Readable {
         Self: Sized;
 }
 
+pub trait Writable {
+    fn write<W: io::Write>(&self, writer: &mut W) -> io::Result<()>;
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        // Writing into a `Vec` cannot fail.
+        self.write(&mut buffer).unwrap();
+        buffer
+    }
+}
+
 pub trait ReadableEndianness {
     fn read<T>(reader: &mut ByteStream) -> io::Result<Self>
     where
@@ -159,8 +170,12 @@ impl ByteStream {
     where
         T: Readable + Copy,
     {
-        let buffer = [T::read(self)?; S];
-        Ok(buffer)
+        // Each element is read from the stream in turn
+        let mut buffer = [None; S];
+        for item in buffer.iter_mut() {
+            *item = Some(T::read(self)?);
+        }
+        Ok(buffer.map(|item| item.expect("every item was read")))
     }
 
     // Reads S bytes from the stream
@@ -185,6 +200,47 @@ impl ByteStream {
     // }
 }
 
+/// Writes `bytes` to the image at `lba` without truncating the rest of the image.
+pub fn write_sectors_to_file(path: &Path, lba: u64, bytes: &[u8]) -> io::Result<()> {
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    file.seek(SeekFrom::Start(lba * SECTOR_SIZE as u64))?;
+    io::Write::write_all(&mut file, bytes)?;
+    io::Write::flush(&mut file)
+}
+
+/// Number of whole sectors in the image, works for both image files and block devices.
+pub fn image_size_in_sectors(path: &Path) -> io::Result<u64> {
+    let mut file = File::open(path)?;
+    Ok(file.seek(SeekFrom::End(0))? / SECTOR_SIZE as u64)
+}
+
+// Path of a scratch image for tests, unique to `name` and the test process
+#[cfg(test)]
+pub(crate) fn test_image_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("parttable_{}_{}.dd", name, std::process::id()))
+}
+
+#[cfg(test)]
+pub(crate) fn write_test_image(name: &str, image: &[u8]) -> std::path::PathBuf {
+    let path = test_image_path(name);
+    std::fs::write(&path, image).unwrap();
+    path
+}
+
+#[cfg(test)]
+pub(crate) fn blank_test_image(name: &str, sectors: usize) -> std::path::PathBuf {
+    write_test_image(name, &vec![0u8; sectors * SECTOR_SIZE])
+}
+
+/// Encodes `name` as little endian UTF-16 into a zero padded buffer of `S` bytes, truncating names that do not fit.
+pub fn encode_utf16_bytes<const S: usize>(name: &str) -> [u8; S] {
+    let mut buffer = [0u8; S];
+    for (index, unit) in name.encode_utf16().take(S / 2).enumerate() {
+        buffer[index * 2..index * 2 + 2].copy_from_slice(&unit.to_le_bytes());
+    }
+    buffer
+}
+
 pub fn interpret_bytes_as_utf16(name_bytes: &[u8]) -> Result<String, FromUtf16Error> {
     let num_bytes = name_bytes.len();
     let mut unicode_symbols: Vec<u16> = Vec::with_capacity(num_bytes / 2);
@@ -0,0 +1,377 @@
+use crate::bytestream::SECTOR_SIZE;
+use std::{
+    fs::File,
+    io::{self, BufReader, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+// Reflected polynomials of CRC-32 (IEEE 802.3, used by GPT) and CRC-32C (Castagnoli, used by ext4)
+const CRC32_POLYNOMIAL: u32 = 0xEDB88320;
+const CRC32C_POLYNOMIAL: u32 = 0x82F63B78;
+// Bytes read at once when checksumming a range of the image
+const READ_BUFFER_SIZE: usize = 1024 * 1024;
+// Fletcher-64 sums 32 bit words modulo 2^32 - 1
+const FLETCHER_MODULUS: u64 = 0xFFFFFFFF;
+// The NTFS boot sector checksum covers every 32 bit word in front of it
+const NTFS_CHECKSUM_OFFSET: usize = 0x50;
+
+// Slicing-by-8 tables, `tables[0]` is the classic byte-at-a-time table and `tables[n]` advances a byte
+// through `n` further bytes of zeros
+const fn crc32_tables(polynomial: u32) -> [[u32; 256]; 8] {
+    let mut tables = [[0u32; 256]; 8];
+    let mut index = 0;
+    while index < 256 {
+        let mut crc = index as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ polynomial
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        tables[0][index] = crc;
+        index += 1;
+    }
+    let mut table = 1;
+    while table < 8 {
+        let mut index = 0;
+        while index < 256 {
+            let previous = tables[table - 1][index];
+            tables[table][index] = (previous >> 8) ^ tables[0][(previous & 0xFF) as usize];
+            index += 1;
+        }
+        table += 1;
+    }
+    tables
+}
+
+static CRC32_TABLES: [[u32; 256]; 8] = crc32_tables(CRC32_POLYNOMIAL);
+static CRC32C_TABLES: [[u32; 256]; 8] = crc32_tables(CRC32C_POLYNOMIAL);
+
+/// A CRC-32 computed incrementally, eight bytes at a time. Bytes can be fed with `update` or, to checksum
+/// a reader, through `io::copy` since it implements `Write`.
+#[derive(Debug, Clone)]
+pub struct Crc32 {
+    tables: &'static [[u32; 256]; 8],
+    state: u32,
+}
+
+impl Crc32 {
+    /// The CRC-32 of GPT headers and partition entry arrays.
+    pub fn new() -> Self {
+        Self {
+            tables: &CRC32_TABLES,
+            state: 0xFFFFFFFF,
+        }
+    }
+
+    /// CRC-32C, ext4 metadata checksums are the raw CRC-32C state without the final inversion, `!finish()`.
+    pub fn castagnoli() -> Self {
+        Self {
+            tables: &CRC32C_TABLES,
+            state: 0xFFFFFFFF,
+        }
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        let tables = self.tables;
+        let mut crc = self.state;
+        let mut chunks = bytes.chunks_exact(8);
+        for chunk in &mut chunks {
+            let low = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]) ^ crc;
+            let high = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+            crc = tables[7][(low & 0xFF) as usize]
+                ^ tables[6][((low >> 8) & 0xFF) as usize]
+                ^ tables[5][((low >> 16) & 0xFF) as usize]
+                ^ tables[4][(low >> 24) as usize]
+                ^ tables[3][(high & 0xFF) as usize]
+                ^ tables[2][((high >> 8) & 0xFF) as usize]
+                ^ tables[1][((high >> 16) & 0xFF) as usize]
+                ^ tables[0][(high >> 24) as usize];
+        }
+        for byte in chunks.remainder() {
+            crc = tables[0][((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+        }
+        self.state = crc;
+    }
+
+    pub fn finish(&self) -> u32 {
+        !self.state
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Write for Crc32 {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The CRC-32 of `bytes`, as stored in GPT headers.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(bytes);
+    crc.finish()
+}
+
+/// The 16 bit checksum of Apple partition map boot code and of the drivers listed in the driver descriptor
+/// map: every byte is added and the sum rotated left by one bit, a sum of zero is stored as 0xFFFF.
+#[derive(Debug, Clone, Default)]
+pub struct AppleChecksum {
+    sum: u16,
+}
+
+impl AppleChecksum {
+    pub fn update(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.sum = self.sum.wrapping_add(*byte as u16).rotate_left(1);
+        }
+    }
+
+    pub fn finish(&self) -> u16 {
+        match self.sum {
+            0 => 0xFFFF,
+            sum => sum,
+        }
+    }
+}
+
+impl Write for AppleChecksum {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The checksum of the HFS+ journal header and block lists.
+#[derive(Debug, Clone, Default)]
+pub struct HfsJournalChecksum {
+    sum: u32,
+}
+
+impl HfsJournalChecksum {
+    pub fn update(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.sum = (self.sum << 8) ^ self.sum.wrapping_add(*byte as u32);
+        }
+    }
+
+    pub fn finish(&self) -> u32 {
+        !self.sum
+    }
+}
+
+impl Write for HfsJournalChecksum {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Fletcher-64 over little endian 32 bit words, the checksum of APFS objects (computed over the object
+/// without its leading 8 byte checksum field). A trailing partial word is padded with zeros.
+#[derive(Debug, Clone, Default)]
+pub struct Fletcher64 {
+    low: u64,
+    high: u64,
+    pending: Vec<u8>,
+}
+
+impl Fletcher64 {
+    fn add_word(&mut self, word: [u8; 4]) {
+        self.low = (self.low + u32::from_le_bytes(word) as u64) % FLETCHER_MODULUS;
+        self.high = (self.high + self.low) % FLETCHER_MODULUS;
+    }
+
+    pub fn update(&mut self, mut bytes: &[u8]) {
+        if !self.pending.is_empty() {
+            let missing = (4 - self.pending.len()).min(bytes.len());
+            self.pending.extend_from_slice(&bytes[..missing]);
+            bytes = &bytes[missing..];
+            if self.pending.len() < 4 {
+                return;
+            }
+            let word = [
+                self.pending[0],
+                self.pending[1],
+                self.pending[2],
+                self.pending[3],
+            ];
+            self.pending.clear();
+            self.add_word(word);
+        }
+        let mut words = bytes.chunks_exact(4);
+        for word in &mut words {
+            self.add_word([word[0], word[1], word[2], word[3]]);
+        }
+        self.pending.extend_from_slice(words.remainder());
+    }
+
+    pub fn finish(&self) -> u64 {
+        let mut checksum = self.clone();
+        if !checksum.pending.is_empty() {
+            checksum.pending.resize(4, 0);
+            let pending = std::mem::take(&mut checksum.pending);
+            checksum.add_word([pending[0], pending[1], pending[2], pending[3]]);
+        }
+        let low = FLETCHER_MODULUS - ((checksum.low + checksum.high) % FLETCHER_MODULUS);
+        let high = FLETCHER_MODULUS - ((checksum.low + low) % FLETCHER_MODULUS);
+        (high << 32) | low
+    }
+}
+
+impl Write for Fletcher64 {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The NTFS boot sector checksum stored at offset 0x50, the sum of the 32 bit words in front of it.
+/// Windows leaves it zero, which means it isn't checked.
+pub fn ntfs_boot_sector_checksum(boot_sector: &[u8]) -> u32 {
+    boot_sector[..NTFS_CHECKSUM_OFFSET]
+        .chunks_exact(4)
+        .fold(0u32, |sum, word| {
+            sum.wrapping_add(u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+        })
+}
+
+/// The checksum stored in an NTFS boot sector.
+pub fn stored_ntfs_boot_sector_checksum(boot_sector: &[u8]) -> u32 {
+    let field = &boot_sector[NTFS_CHECKSUM_OFFSET..NTFS_CHECKSUM_OFFSET + 4];
+    u32::from_le_bytes([field[0], field[1], field[2], field[3]])
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChecksumAlgorithm {
+    Crc32,
+    Crc32c,
+    Apple,
+    HfsJournal,
+    Fletcher64,
+}
+
+impl ChecksumAlgorithm {
+    /// Feeds everything `reader` yields through the checksum and formats the result as hexadecimal.
+    pub fn checksum_reader<R: Read>(self, reader: &mut R) -> io::Result<String> {
+        Ok(match self {
+            ChecksumAlgorithm::Crc32 | ChecksumAlgorithm::Crc32c => {
+                let mut crc = match self {
+                    ChecksumAlgorithm::Crc32 => Crc32::new(),
+                    _ => Crc32::castagnoli(),
+                };
+                io::copy(reader, &mut crc)?;
+                format!("{:08x}", crc.finish())
+            }
+            ChecksumAlgorithm::Apple => {
+                let mut checksum = AppleChecksum::default();
+                io::copy(reader, &mut checksum)?;
+                format!("{:04x}", checksum.finish())
+            }
+            ChecksumAlgorithm::HfsJournal => {
+                let mut checksum = HfsJournalChecksum::default();
+                io::copy(reader, &mut checksum)?;
+                format!("{:08x}", checksum.finish())
+            }
+            ChecksumAlgorithm::Fletcher64 => {
+                let mut checksum = Fletcher64::default();
+                io::copy(reader, &mut checksum)?;
+                format!("{:016x}", checksum.finish())
+            }
+        })
+    }
+}
+
+/// Checksums `sectors` sectors of the image starting at `starting_lba`.
+pub fn checksum_sectors(
+    path: &Path,
+    starting_lba: u64,
+    sectors: u64,
+    algorithm: ChecksumAlgorithm,
+) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(starting_lba * SECTOR_SIZE as u64))?;
+    let mut reader =
+        BufReader::with_capacity(READ_BUFFER_SIZE, file.take(sectors * SECTOR_SIZE as u64));
+    algorithm.checksum_reader(&mut reader)
+}
+
+#[test]
+fn test_crc32() {
+    let data: Vec<u8> = (0..1024).map(|value| value as u8).collect();
+    assert_eq!(crc32(b""), 0);
+    assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    assert_eq!(crc32(&data), 0xB70B4C26);
+
+    // Feeding the bytes in pieces that don't line up with the 8 byte slices changes nothing
+    let mut crc = Crc32::new();
+    for piece in data.chunks(13) {
+        crc.update(piece);
+    }
+    assert_eq!(crc.finish(), 0xB70B4C26);
+
+    let mut crc = Crc32::castagnoli();
+    crc.update(b"123456789");
+    assert_eq!(crc.finish(), 0xE3069283);
+    // The ext4 form of the same checksum
+    assert_eq!(!crc.finish(), 0x1CF96D7C);
+}
+
+#[test]
+fn test_other_checksums() {
+    let data: Vec<u8> = (0..1024).map(|value| value as u8).collect();
+    let checksum = |algorithm: ChecksumAlgorithm, bytes: &[u8]| {
+        algorithm
+            .checksum_reader(&mut io::Cursor::new(bytes))
+            .unwrap()
+    };
+    assert_eq!(checksum(ChecksumAlgorithm::Apple, b"123456789"), "c78a");
+    assert_eq!(checksum(ChecksumAlgorithm::Apple, &data), "68ff");
+    assert_eq!(checksum(ChecksumAlgorithm::Apple, b""), "ffff");
+    assert_eq!(
+        checksum(ChecksumAlgorithm::HfsJournal, b"123456789"),
+        "66caf222"
+    );
+    assert_eq!(checksum(ChecksumAlgorithm::HfsJournal, &data), "0e295dff");
+    assert_eq!(
+        checksum(ChecksumAlgorithm::Fletcher64, &data[8..64]),
+        "aa40d76f4dc53cb2"
+    );
+    let mut fletcher = Fletcher64::default();
+    for piece in data[8..64].chunks(3) {
+        fletcher.update(piece);
+    }
+    assert_eq!(fletcher.finish(), 0xAA40D76F4DC53CB2);
+
+    let mut boot_sector = vec![0u8; 512];
+    boot_sector[0..8].copy_from_slice(&[1, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF]);
+    boot_sector[0x4C..0x50].copy_from_slice(&3u32.to_le_bytes());
+    boot_sector[0x50..0x54].copy_from_slice(&3u32.to_le_bytes());
+    assert_eq!(ntfs_boot_sector_checksum(&boot_sector), 3);
+    assert_eq!(stored_ntfs_boot_sector_checksum(&boot_sector), 3);
+}
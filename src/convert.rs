@@ -0,0 +1,387 @@
+use crate::{
+    bytestream::{image_size_in_sectors, write_sectors_to_file, SECTOR_SIZE},
+    gpt::{
+        parse_gpt, write_new_gpt, GptPartitionTableEntry, DEFAULT_NUMBER_PARTITION_ENTRIES,
+        DEFAULT_PARTITION_ENTRY_SIZE, LEGACY_BIOS_BOOTABLE_ATTRIBUTE,
+    },
+    guid::Guid,
+    mbr::{parse_mbr, write_mbr, MbrPartition},
+    partition_types::{
+        gpt_type_name, is_discoverable_partition_type, mbr_type_name, APPLE_HFS_PLUS_GUID,
+        APPLE_UFS_GUID, EFI_SYSTEM_PARTITION_GUID, FREEBSD_DATA_GUID, LDM_DATA_GUID,
+        LINUX_FILESYSTEM_GUID, LINUX_LVM_GUID, LINUX_RAID_GUID, LINUX_SWAP_GUID,
+        MICROSOFT_BASIC_DATA_GUID, NETBSD_FFS_GUID, OPENBSD_DATA_GUID, WINDOWS_RECOVERY_GUID,
+    },
+};
+use prettytable::{row, Table};
+use std::{io, path::Path};
+
+// MBR type codes and the GPT type GUIDs they convert to. Converting back uses the first code listed for a GUID.
+const MBR_GPT_TYPE_MAP: [(u8, Guid); 19] = [
+    (0x07, MICROSOFT_BASIC_DATA_GUID),
+    (0x01, MICROSOFT_BASIC_DATA_GUID),
+    (0x04, MICROSOFT_BASIC_DATA_GUID),
+    (0x06, MICROSOFT_BASIC_DATA_GUID),
+    (0x0b, MICROSOFT_BASIC_DATA_GUID),
+    (0x0c, MICROSOFT_BASIC_DATA_GUID),
+    (0x0e, MICROSOFT_BASIC_DATA_GUID),
+    (0x27, WINDOWS_RECOVERY_GUID),
+    (0x42, LDM_DATA_GUID),
+    (0x82, LINUX_SWAP_GUID),
+    (0x83, LINUX_FILESYSTEM_GUID),
+    (0x8e, LINUX_LVM_GUID),
+    (0xa5, FREEBSD_DATA_GUID),
+    (0xa6, OPENBSD_DATA_GUID),
+    (0xa8, APPLE_UFS_GUID),
+    (0xa9, NETBSD_FFS_GUID),
+    (0xaf, APPLE_HFS_PLUS_GUID),
+    (0xef, EFI_SYSTEM_PARTITION_GUID),
+    (0xfd, LINUX_RAID_GUID),
+];
+// Used for types without a counterpart in the other scheme
+const FALLBACK_MBR_TYPE: u8 = 0x83;
+const FALLBACK_GPT_TYPE: Guid = LINUX_FILESYSTEM_GUID;
+const ENTRIES_PER_SECTOR: u64 = SECTOR_SIZE as u64 / DEFAULT_PARTITION_ENTRY_SIZE as u64;
+
+/// The GPT type GUID an MBR type code converts to, if there is one.
+pub fn mbr_to_gpt_type(partition_type: u8) -> Option<Guid> {
+    MBR_GPT_TYPE_MAP
+        .iter()
+        .find(|(code, _)| *code == partition_type)
+        .map(|(_, guid)| *guid)
+}
+
+/// The MBR type code a GPT type GUID converts to, Discoverable Partitions Specification types are Linux partitions.
+pub fn gpt_to_mbr_type(partition_type: &Guid) -> Option<u8> {
+    match MBR_GPT_TYPE_MAP
+        .iter()
+        .find(|(_, guid)| guid == partition_type)
+    {
+        Some((code, _)) => Some(*code),
+        None if is_discoverable_partition_type(partition_type) => Some(0x83),
+        None => None,
+    }
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// The GPT an MBR converts to, partitions keep their number and LBA range.
+#[derive(Debug)]
+pub struct GptConversion {
+    pub number_partition_entries: u32,
+    pub entries: Vec<(usize, GptPartitionTableEntry)>,
+    pub notes: Vec<String>,
+}
+
+/// The MBR a GPT converts to, along with the GPT number each MBR partition had.
+#[derive(Debug)]
+pub struct MbrConversion {
+    pub partitions: Vec<(u32, MbrPartition)>,
+    pub notes: Vec<String>,
+}
+
+/// Plans converting the MBR of the image, including the logical partitions, to a GPT. When the first
+/// partition starts before LBA 34 or the last one ends too close to the end of the image the partition
+/// entry array is shrunk so no partition has to move.
+pub fn plan_mbr_to_gpt(path: &Path) -> io::Result<GptConversion> {
+    let mbr = parse_mbr(path)?;
+    if mbr.is_gpt() {
+        return Err(invalid_input("The image already has a GPT".into()));
+    }
+    let partitions: Vec<MbrPartition> = mbr
+        .partitions()
+        .into_iter()
+        .filter(|partition| !partition.is_extended() && partition.partition_type != 0)
+        .collect();
+    let total_sectors = image_size_in_sectors(path)?;
+    let mut notes = Vec::new();
+
+    // Protective MBR and primary header before the entry array, backup header after the backup array
+    let first_lba = partitions
+        .iter()
+        .map(|partition| partition.starting_lba)
+        .min();
+    let last_lba = partitions
+        .iter()
+        .map(|partition| partition.ending_lba())
+        .max();
+    let sectors_before = first_lba.map(|lba| lba.saturating_sub(2));
+    let sectors_after = last_lba.map(|lba| total_sectors.saturating_sub(lba + 2));
+    let available_sectors = match (sectors_before, sectors_after) {
+        (Some(before), Some(after)) => before.min(after),
+        _ => u64::MAX,
+    };
+    let number_partition_entries = (available_sectors.saturating_mul(ENTRIES_PER_SECTOR))
+        .min(DEFAULT_NUMBER_PARTITION_ENTRIES as u64) as u32;
+    let highest_number = partitions
+        .iter()
+        .map(|partition| partition.number)
+        .max()
+        .unwrap_or(0);
+    if number_partition_entries < highest_number.max(1) {
+        return Err(invalid_input(format!(
+            "Only {} sectors are free at the start or end of the image, too few for the GPT structures",
+            available_sectors
+        )));
+    }
+    if number_partition_entries < DEFAULT_NUMBER_PARTITION_ENTRIES {
+        notes.push(format!(
+            "Partitions leave only {} sectors for each partition entry array, the array is shrunk to {} entries",
+            available_sectors, number_partition_entries
+        ));
+    }
+
+    let mut entries = Vec::new();
+    for partition in partitions.iter() {
+        let partition_type = match mbr_to_gpt_type(partition.partition_type) {
+            Some(guid) => guid,
+            None => {
+                notes.push(format!(
+                    "Partition {} has type {:#04x} ({}) without a GPT equivalent, using Linux filesystem",
+                    partition.number,
+                    partition.partition_type,
+                    mbr_type_name(partition.partition_type)
+                ));
+                FALLBACK_GPT_TYPE
+            }
+        };
+        let attributes = if partition.bootable {
+            LEGACY_BIOS_BOOTABLE_ATTRIBUTE
+        } else {
+            0
+        };
+        entries.push((
+            partition.number as usize - 1,
+            GptPartitionTableEntry::new(
+                partition_type,
+                Guid::new_random()?,
+                partition.starting_lba,
+                partition.ending_lba(),
+                attributes,
+                "",
+            ),
+        ));
+    }
+    Ok(GptConversion {
+        number_partition_entries,
+        entries,
+        notes,
+    })
+}
+
+/// Writes the protective MBR and both copies of the converted GPT, partition data is left untouched.
+pub fn convert_mbr_to_gpt(path: &Path, conversion: &GptConversion) -> io::Result<()> {
+    write_new_gpt(
+        path,
+        Guid::new_random()?,
+        None,
+        None,
+        conversion.number_partition_entries,
+        &conversion.entries,
+    )
+}
+
+/// Plans converting the GPT of the image to an MBR. Up to four partitions become primary partitions,
+/// otherwise the fourth and later partitions become logical partitions, which needs a free sector for the
+/// EBR in front of each of them. Every partition has to end below 2 TiB.
+pub fn plan_gpt_to_mbr(path: &Path) -> io::Result<MbrConversion> {
+    if !parse_mbr(path)?.is_gpt() {
+        return Err(invalid_input("The image has no GPT".into()));
+    }
+    let mut entries = parse_gpt(path)?;
+    entries.sort_by_key(|(_, entry)| entry.starting_lba());
+    let mut notes = Vec::new();
+
+    let mut partitions: Vec<(u32, MbrPartition)> = Vec::new();
+    for (position, (index, entry)) in entries.iter().enumerate() {
+        let gpt_number = *index as u32 + 1;
+        if entry.ending_lba() > u32::MAX as u64 {
+            return Err(invalid_input(format!(
+                "Partition {} ends at LBA {}, beyond the 2 TiB an MBR can address",
+                gpt_number,
+                entry.ending_lba()
+            )));
+        }
+        let partition_type = match gpt_to_mbr_type(entry.partition_type()) {
+            Some(code) => code,
+            None => {
+                notes.push(format!(
+                    "Partition {} has type {} without an MBR equivalent, using {:#04x}",
+                    gpt_number,
+                    gpt_type_name(entry.partition_type()),
+                    FALLBACK_MBR_TYPE
+                ));
+                FALLBACK_MBR_TYPE
+            }
+        };
+        let number = if entries.len() <= 4 || position < 3 {
+            position as u32 + 1
+        } else {
+            position as u32 + 2
+        };
+        // The EBR of a logical partition lives in the sector right before it
+        if number > 4 && entry.starting_lba() <= entries[position - 1].1.ending_lba() + 1 {
+            return Err(invalid_input(format!(
+                "Partition {} directly follows partition {}, there is no room for the EBR of a logical partition",
+                gpt_number,
+                entries[position - 1].0 + 1
+            )));
+        }
+        partitions.push((
+            gpt_number,
+            MbrPartition {
+                number,
+                bootable: entry.attributes() & LEGACY_BIOS_BOOTABLE_ATTRIBUTE != 0,
+                partition_type,
+                starting_lba: entry.starting_lba(),
+                num_sectors: entry.ending_lba() - entry.starting_lba() + 1,
+            },
+        ));
+    }
+
+    if entries.len() > 4 {
+        let first_logical = &partitions[3].1;
+        let last_logical = &partitions[partitions.len() - 1].1;
+        let starting_lba = first_logical.starting_lba - 1;
+        partitions.insert(
+            3,
+            (
+                0,
+                MbrPartition {
+                    number: 4,
+                    bootable: false,
+                    partition_type: 0x05,
+                    starting_lba,
+                    num_sectors: last_logical.ending_lba() - starting_lba + 1,
+                },
+            ),
+        );
+        notes.push(
+            "The fourth and later partitions by starting LBA become logical partitions".into(),
+        );
+    }
+    Ok(MbrConversion { partitions, notes })
+}
+
+/// Removes both GPT headers so the image is no longer seen as GPT, then writes the converted MBR.
+/// The boot code and disk signature of the MBR are kept and partition data is left untouched.
+pub fn convert_gpt_to_mbr(path: &Path, conversion: &MbrConversion) -> io::Result<()> {
+    let last_lba = image_size_in_sectors(path)?.saturating_sub(1);
+    for lba in [1, last_lba] {
+        write_sectors_to_file(path, lba, &[0u8; SECTOR_SIZE])?;
+    }
+    let partitions: Vec<MbrPartition> = conversion
+        .partitions
+        .iter()
+        .map(|(_, partition)| partition.clone())
+        .collect();
+    write_mbr(path, None, &partitions)
+}
+
+pub fn display_mbr_conversion(conversion: &MbrConversion) {
+    let mut table = Table::new();
+    table.add_row(row![
+        "GPT Number",
+        "MBR Number",
+        "Bootable",
+        "Starting LBA",
+        "Total Sectors",
+        "Partition Type"
+    ]);
+    for (gpt_number, partition) in conversion.partitions.iter() {
+        table.add_row(row![
+            if *gpt_number == 0 {
+                String::new()
+            } else {
+                gpt_number.to_string()
+            },
+            partition.number,
+            if partition.bootable { "Yes" } else { "No" },
+            partition.starting_lba,
+            partition.num_sectors,
+            format!(
+                "{:#04x} :: {}",
+                partition.partition_type,
+                mbr_type_name(partition.partition_type)
+            )
+        ]);
+    }
+    table.printstd();
+}
+
+#[test]
+fn test_convert_mbr_to_gpt_and_back() {
+    let path = crate::bytestream::blank_test_image("convert", 16384);
+    let partition = |number, partition_type, starting_lba, num_sectors| MbrPartition {
+        number,
+        bootable: number == 1,
+        partition_type,
+        starting_lba,
+        num_sectors,
+    };
+    // The first partition starts at LBA 18, leaving 16 sectors for the entry array
+    let partitions = [
+        partition(1, 0x0c, 18, 2030),
+        partition(2, 0x05, 4096, 8192),
+        partition(5, 0x83, 4097, 2047),
+        partition(6, 0x82, 6145, 2047),
+        partition(7, 0x8e, 8193, 4095),
+    ];
+    write_mbr(&path, None, &partitions).unwrap();
+
+    let conversion = plan_mbr_to_gpt(&path).unwrap();
+    assert_eq!(conversion.number_partition_entries, 64);
+    convert_mbr_to_gpt(&path, &conversion).unwrap();
+    let verification = crate::gpt::verify_gpt(&path).unwrap();
+    assert!(verification.issues.is_empty(), "{:?}", verification.issues);
+    let entries = parse_gpt(&path).unwrap();
+    let summary: Vec<(usize, u64, u64, u64)> = entries
+        .iter()
+        .map(|(index, entry)| {
+            (
+                *index,
+                entry.starting_lba(),
+                entry.ending_lba(),
+                entry.attributes(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (0, 18, 2047, LEGACY_BIOS_BOOTABLE_ATTRIBUTE),
+            (4, 4097, 6143, 0),
+            (5, 6145, 8191, 0),
+            (6, 8193, 12287, 0),
+        ]
+    );
+
+    let conversion = plan_gpt_to_mbr(&path).unwrap();
+    convert_gpt_to_mbr(&path, &conversion).unwrap();
+    let mbr = parse_mbr(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(!mbr.is_gpt());
+    let partitions: Vec<(u32, u8, u64, u64)> = mbr
+        .partitions()
+        .iter()
+        .map(|partition| {
+            (
+                partition.number,
+                partition.partition_type,
+                partition.starting_lba,
+                partition.num_sectors,
+            )
+        })
+        .collect();
+    assert_eq!(
+        partitions,
+        vec![
+            (1, 0x07, 18, 2030),
+            (2, 0x83, 4097, 2047),
+            (3, 0x82, 6145, 2047),
+            (4, 0x8e, 8193, 4095),
+        ]
+    );
+}
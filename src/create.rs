@@ -0,0 +1,213 @@
+use crate::{
+    bytestream::{image_size_in_sectors, SECTOR_SIZE},
+    gpt::{default_usable_lba_range, write_new_gpt, GptPartitionTableEntry},
+    guid::Guid,
+    partition_types::registry,
+    sfdisk::split_fields,
+};
+use std::{fs::OpenOptions, io, path::Path, str::FromStr};
+
+// Partitions start on 1 MiB boundaries unless asked otherwise, like every current partitioning tool
+pub const DEFAULT_ALIGNMENT_SECTORS: u64 = 1024 * 1024 / SECTOR_SIZE as u64;
+const DEFAULT_PARTITION_TYPE: &str = "linux";
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// Parses a size in sectors, or in bytes with a `K`, `M`, `G` or `T` (binary) suffix.
+pub fn parse_size_in_sectors(size: &str) -> io::Result<u64> {
+    let size = size.trim();
+    let invalid = || invalid_input(format!("Invalid size `{}`", size));
+    let (number, multiplier) = match size.char_indices().last() {
+        Some((index, suffix)) if suffix.is_ascii_alphabetic() => {
+            let exponent = match suffix.to_ascii_uppercase() {
+                'K' => 1,
+                'M' => 2,
+                'G' => 3,
+                'T' => 4,
+                _ => return Err(invalid()),
+            };
+            (&size[..index], 1024u64.pow(exponent))
+        }
+        _ => (size, SECTOR_SIZE as u64),
+    };
+    let bytes = number
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(multiplier))
+        .ok_or_else(invalid)?;
+    if bytes == 0 || bytes % SECTOR_SIZE as u64 != 0 {
+        return Err(invalid_input(format!(
+            "Size `{}` is not a non-zero multiple of {} bytes",
+            size, SECTOR_SIZE
+        )));
+    }
+    Ok(bytes / SECTOR_SIZE as u64)
+}
+
+/// A partition to create, given as `size=SIZE,align=SIZE,type=TYPE,name=NAME` where every field is optional.
+/// Without a size the partition fills the rest of the disk.
+#[derive(Debug, Default)]
+pub struct PartitionSpec {
+    size: Option<u64>,
+    alignment: Option<u64>,
+    partition_type: Option<String>,
+    name: Option<String>,
+}
+
+impl FromStr for PartitionSpec {
+    type Err = io::Error;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let mut result = Self::default();
+        for field in split_fields(spec) {
+            let (key, value) = field
+                .split_once('=')
+                .map(|(key, value)| (key.trim(), value.trim().trim_matches('"')))
+                .ok_or_else(|| invalid_input(format!("Invalid partition field `{}`", field)))?;
+            match key {
+                "size" => result.size = Some(parse_size_in_sectors(value)?),
+                "align" => result.alignment = Some(parse_size_in_sectors(value)?),
+                "type" => result.partition_type = Some(value.into()),
+                "name" => result.name = Some(value.into()),
+                _ => return Err(invalid_input(format!("Unknown partition field `{}`", key))),
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// Creates the image file with `size_in_sectors`, refusing to touch an existing file.
+pub fn create_image(path: &Path, size_in_sectors: u64) -> io::Result<()> {
+    let file = OpenOptions::new().write(true).create_new(true).open(path)?;
+    file.set_len(size_in_sectors * SECTOR_SIZE as u64)
+}
+
+/// Places `partitions` one after another in the usable area of a new GPT with `number_partition_entries`
+/// entries, each starting at the next multiple of its alignment. Partitions get random unique GUIDs.
+pub fn allocate_partitions(
+    total_sectors: u64,
+    number_partition_entries: u32,
+    partitions: &[PartitionSpec],
+) -> io::Result<Vec<(usize, GptPartitionTableEntry)>> {
+    if partitions.len() > number_partition_entries as usize {
+        return Err(invalid_input(format!(
+            "{} partitions do not fit in a table of {} entries",
+            partitions.len(),
+            number_partition_entries
+        )));
+    }
+    let (first_usable_lba, last_usable_lba) =
+        default_usable_lba_range(total_sectors, number_partition_entries)?;
+
+    let mut entries = Vec::new();
+    let mut next_free = first_usable_lba;
+    for (index, partition) in partitions.iter().enumerate() {
+        let alignment = partition.alignment.unwrap_or(DEFAULT_ALIGNMENT_SECTORS);
+        let starting_lba = next_free.div_ceil(alignment) * alignment;
+        let ending_lba = match partition.size {
+            Some(size) => starting_lba + size - 1,
+            None if index + 1 == partitions.len() => last_usable_lba,
+            None => {
+                return Err(invalid_input(format!(
+                "Only the last partition can fill the rest of the disk, partition {} needs a size",
+                index + 1
+            )))
+            }
+        };
+        if ending_lba > last_usable_lba || starting_lba > ending_lba {
+            return Err(invalid_input(format!(
+                "Partition {} at LBA {}-{} does not fit in the usable area {}-{}",
+                index + 1,
+                starting_lba,
+                ending_lba,
+                first_usable_lba,
+                last_usable_lba
+            )));
+        }
+
+        let type_name = partition
+            .partition_type
+            .as_deref()
+            .unwrap_or(DEFAULT_PARTITION_TYPE);
+        let partition_type = registry()
+            .find_gpt(type_name)
+            .ok_or_else(|| invalid_input(format!("Unknown GPT partition type `{}`", type_name)))?;
+        entries.push((
+            index,
+            GptPartitionTableEntry::new(
+                partition_type,
+                Guid::new_random()?,
+                starting_lba,
+                ending_lba,
+                0,
+                partition.name.as_deref().unwrap_or(""),
+            ),
+        ));
+        next_free = ending_lba + 1;
+    }
+    Ok(entries)
+}
+
+/// Writes a protective MBR and a new GPT with a random disk GUID holding `partitions` to the image.
+pub fn create_gpt(
+    path: &Path,
+    number_partition_entries: u32,
+    partitions: &[PartitionSpec],
+) -> io::Result<()> {
+    let total_sectors = image_size_in_sectors(path)?;
+    let entries = allocate_partitions(total_sectors, number_partition_entries, partitions)?;
+    write_new_gpt(
+        path,
+        Guid::new_random()?,
+        None,
+        None,
+        number_partition_entries,
+        &entries,
+    )
+}
+
+#[test]
+fn test_parse_size_in_sectors() {
+    assert_eq!(parse_size_in_sectors("2048").unwrap(), 2048);
+    assert_eq!(parse_size_in_sectors("1M").unwrap(), 2048);
+    assert_eq!(parse_size_in_sectors("4k").unwrap(), 8);
+    assert_eq!(parse_size_in_sectors("2G").unwrap(), 4194304);
+    assert!(parse_size_in_sectors("0").is_err());
+    assert!(parse_size_in_sectors("1X").is_err());
+    assert!(parse_size_in_sectors("1K1").is_err());
+}
+
+#[test]
+fn test_create_gpt() {
+    let path = crate::bytestream::test_image_path("create_gpt");
+    let _ = std::fs::remove_file(&path);
+    create_image(&path, parse_size_in_sectors("16M").unwrap()).unwrap();
+    assert!(create_image(&path, 1).is_err());
+
+    let partitions: Vec<PartitionSpec> = [
+        "size=4M,type=esp,name=\"EFI system\"",
+        "size=3,align=8,type=swap",
+        "type=root-x86-64,name=root",
+    ]
+    .iter()
+    .map(|spec| spec.parse().unwrap())
+    .collect();
+    create_gpt(&path, 128, &partitions).unwrap();
+
+    let verification = crate::gpt::verify_gpt(&path).unwrap();
+    let entries = crate::gpt::parse_gpt(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(verification.issues.is_empty(), "{:?}", verification.issues);
+    let ranges: Vec<(u64, u64)> = entries
+        .iter()
+        .map(|(_, entry)| (entry.starting_lba(), entry.ending_lba()))
+        .collect();
+    assert_eq!(ranges, vec![(2048, 10239), (10240, 10242), (12288, 32734)]);
+    assert_eq!(entries[0].1.name(), "EFI system");
+    assert_eq!(
+        *entries[2].1.partition_type(),
+        "4F68BCE3-E8CD-4DB1-96E7-FBCAF984B709".parse().unwrap()
+    );
+}
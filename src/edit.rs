@@ -0,0 +1,489 @@
+use crate::{
+    create::DEFAULT_ALIGNMENT_SECTORS,
+    gpt::{
+        build_entry_array, parse_gpt, parse_validated_gpt_header, write_gpt, GptHeader,
+        GptHeaderCopy, GptPartitionTableEntry,
+    },
+    guid::Guid,
+    layout::Layout,
+    mbr::{read_disk_signature, write_disk_signature},
+};
+use prettytable::{row, Table};
+use std::{io, path::Path};
+
+// A partition name holds 36 UTF-16 code units
+const MAXIMUM_NAME_LENGTH: usize = 36;
+
+/// A change to a single entry of an existing GPT, partitions are numbered from 1.
+#[derive(Debug)]
+pub enum GptEdit {
+    /// Adds a partition in the first free slot, or slot `number`. Without a start it is placed at the first
+    /// aligned LBA of the first free region it fits in, without a size it fills the free region it starts in.
+    Add {
+        number: Option<u32>,
+        starting_lba: Option<u64>,
+        size: Option<u64>,
+        alignment: Option<u64>,
+        partition_type: Guid,
+        name: String,
+    },
+    Delete {
+        number: u32,
+    },
+    /// Moves the end of the partition, without a size it grows into all free space following it.
+    Resize {
+        number: u32,
+        size: Option<u64>,
+    },
+    Rename {
+        number: u32,
+        name: String,
+    },
+    Retype {
+        number: u32,
+        partition_type: Guid,
+    },
+    SetAttributes {
+        number: u32,
+        attributes: u64,
+    },
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn find_entry(
+    entries: &mut [(usize, GptPartitionTableEntry)],
+    number: u32,
+) -> io::Result<&mut GptPartitionTableEntry> {
+    entries
+        .iter_mut()
+        .find(|(index, _)| *index + 1 == number as usize)
+        .map(|(_, entry)| entry)
+        .ok_or_else(|| invalid_input(format!("Partition {} does not exist", number)))
+}
+
+fn check_name(name: &str) -> io::Result<()> {
+    if name.encode_utf16().count() > MAXIMUM_NAME_LENGTH {
+        return Err(invalid_input(format!(
+            "Partition name `{}` is longer than {} characters",
+            name, MAXIMUM_NAME_LENGTH
+        )));
+    }
+    Ok(())
+}
+
+// The slot index and LBA range of a partition added according to `GptEdit::Add`
+fn place_partition(
+    header: &GptHeader,
+    entries: &[(usize, GptPartitionTableEntry)],
+    number: Option<u32>,
+    starting_lba: Option<u64>,
+    size: Option<u64>,
+    alignment: Option<u64>,
+) -> io::Result<(usize, u64, u64)> {
+    let index = match number {
+        Some(0) => return Err(invalid_input("Partition numbers start at 1".into())),
+        Some(number)
+            if entries
+                .iter()
+                .any(|(index, _)| *index + 1 == number as usize) =>
+        {
+            return Err(invalid_input(format!(
+                "Partition {} already exists",
+                number
+            )))
+        }
+        Some(number) if number > header.number_partition_entries() => {
+            return Err(invalid_input(format!(
+                "Partition {} does not fit in a table of {} entries",
+                number,
+                header.number_partition_entries()
+            )))
+        }
+        Some(number) => number as usize - 1,
+        None => (0..header.number_partition_entries() as usize)
+            .find(|slot| entries.iter().all(|(index, _)| index != slot))
+            .ok_or_else(|| invalid_input("The partition entry array is full".into()))?,
+    };
+
+    let regions = Layout::from_gpt(header, entries).unallocated_regions();
+    let (starting_lba, ending_lba) = match starting_lba {
+        Some(starting_lba) => {
+            let ending_lba = match size {
+                Some(size) => starting_lba + size - 1,
+                None => regions
+                    .iter()
+                    .find(|(start, end)| *start <= starting_lba && starting_lba <= *end)
+                    .map(|(_, end)| *end)
+                    .ok_or_else(|| invalid_input(format!("LBA {} is not free", starting_lba)))?,
+            };
+            (starting_lba, ending_lba)
+        }
+        None => {
+            let alignment = alignment.unwrap_or(DEFAULT_ALIGNMENT_SECTORS);
+            regions
+                .iter()
+                .find_map(|(start, end)| {
+                    let starting_lba = start.div_ceil(alignment) * alignment;
+                    let ending_lba = match size {
+                        Some(size) => starting_lba + size - 1,
+                        None => *end,
+                    };
+                    (starting_lba <= ending_lba && ending_lba <= *end)
+                        .then_some((starting_lba, ending_lba))
+                })
+                .ok_or_else(|| invalid_input("No free space large enough".into()))?
+        }
+    };
+    Ok((index, starting_lba, ending_lba))
+}
+
+/// Applies `edit` to `entries`, the used entries of the GPT described by `header`.
+pub fn apply_gpt_edit(
+    header: &GptHeader,
+    entries: &mut Vec<(usize, GptPartitionTableEntry)>,
+    edit: &GptEdit,
+) -> io::Result<()> {
+    match edit {
+        GptEdit::Add {
+            number,
+            starting_lba,
+            size,
+            alignment,
+            partition_type,
+            name,
+        } => {
+            check_name(name)?;
+            let (index, starting_lba, ending_lba) =
+                place_partition(header, entries, *number, *starting_lba, *size, *alignment)?;
+            entries.push((
+                index,
+                GptPartitionTableEntry::new(
+                    *partition_type,
+                    Guid::new_random()?,
+                    starting_lba,
+                    ending_lba,
+                    0,
+                    name,
+                ),
+            ));
+            entries.sort_by_key(|(index, _)| *index);
+        }
+        GptEdit::Delete { number } => {
+            find_entry(entries, *number)?;
+            entries.retain(|(index, _)| *index + 1 != *number as usize);
+        }
+        GptEdit::Resize { number, size } => {
+            let starting_lba = find_entry(entries, *number)?.starting_lba();
+            let ending_lba = match size {
+                Some(size) => starting_lba + size - 1,
+                None => entries
+                    .iter()
+                    .map(|(_, entry)| entry.starting_lba())
+                    .filter(|start| *start > starting_lba)
+                    .min()
+                    .map(|start| start - 1)
+                    .unwrap_or(header.last_usable_lba())
+                    .min(header.last_usable_lba()),
+            };
+            find_entry(entries, *number)?.set_ending_lba(ending_lba);
+        }
+        GptEdit::Rename { number, name } => {
+            check_name(name)?;
+            find_entry(entries, *number)?.set_name(name);
+        }
+        GptEdit::Retype {
+            number,
+            partition_type,
+        } => find_entry(entries, *number)?.set_partition_type(*partition_type),
+        GptEdit::SetAttributes { number, attributes } => {
+            find_entry(entries, *number)?.set_attributes(*attributes)
+        }
+    }
+    check_entries(header, entries)
+}
+
+/// Checks that every entry lies within the usable LBA range and that no two entries overlap.
+pub fn check_entries(
+    header: &GptHeader,
+    entries: &[(usize, GptPartitionTableEntry)],
+) -> io::Result<()> {
+    for (index, entry) in entries {
+        if entry.starting_lba() > entry.ending_lba()
+            || entry.starting_lba() < header.first_usable_lba()
+            || entry.ending_lba() > header.last_usable_lba()
+        {
+            return Err(invalid_input(format!(
+                "Partition {} at LBA {}-{} is outside of the usable LBA range {}-{}",
+                index + 1,
+                entry.starting_lba(),
+                entry.ending_lba(),
+                header.first_usable_lba(),
+                header.last_usable_lba()
+            )));
+        }
+    }
+    for (position, (index, entry)) in entries.iter().enumerate() {
+        if let Some((other_index, _)) = entries[position + 1..].iter().find(|(_, other)| {
+            entry.starting_lba() <= other.ending_lba() && other.starting_lba() <= entry.ending_lba()
+        }) {
+            return Err(invalid_input(format!(
+                "Partitions {} and {} overlap",
+                index + 1,
+                other_index + 1
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Applies `edit` to the GPT of the image and writes both copies with updated CRC32s. Nothing is written
+/// unless the resulting table is valid.
+pub fn edit_gpt(path: &Path, edit: &GptEdit) -> io::Result<()> {
+    let validated = parse_validated_gpt_header(path)?;
+    if validated.copy != GptHeaderCopy::Primary {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}, repair the GPT first", validated.describe()),
+        ));
+    }
+    let header = validated.header;
+    let mut entries = parse_gpt(path)?;
+    apply_gpt_edit(&header, &mut entries, edit)?;
+    let entry_array = build_entry_array(
+        &entries,
+        header.number_partition_entries(),
+        header.size_single_partition_entry(),
+    );
+    write_gpt(path, &header, &entry_array)
+}
+
+/// Which identifiers `regenerate_guids` replaces with random ones.
+#[derive(Debug)]
+pub struct GuidRegeneration {
+    pub disk_guid: bool,
+    // Partition numbers, every partition when `None`
+    pub partitions: Option<Vec<u32>>,
+    pub mbr_disk_signature: bool,
+}
+
+/// An identifier replaced by `regenerate_guids`, such as `Partition 2`, with its old and new value.
+#[derive(Debug)]
+pub struct GuidChange {
+    pub identifier: String,
+    pub old: String,
+    pub new: String,
+}
+
+/// Assigns random GUIDs to the disk and the selected partitions of the GPT and writes both copies with
+/// updated CRC32s, so clones of an image no longer share their disk GUID and PARTUUIDs. The MBR disk
+/// signature is replaced too when asked for.
+pub fn regenerate_guids(
+    path: &Path,
+    regeneration: &GuidRegeneration,
+) -> io::Result<Vec<GuidChange>> {
+    let validated = parse_validated_gpt_header(path)?;
+    if validated.copy != GptHeaderCopy::Primary {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}, repair the GPT first", validated.describe()),
+        ));
+    }
+    let mut header = validated.header;
+    let mut entries = parse_gpt(path)?;
+    if let Some(numbers) = &regeneration.partitions {
+        for number in numbers {
+            find_entry(&mut entries, *number)?;
+        }
+    }
+
+    let mut changes = Vec::new();
+    if regeneration.disk_guid {
+        let disk_guid = Guid::new_random()?;
+        changes.push(GuidChange {
+            identifier: "Disk GUID".into(),
+            old: header.disk_guid().to_string(),
+            new: disk_guid.to_string(),
+        });
+        header.set_disk_guid(disk_guid);
+    }
+    for (index, entry) in entries.iter_mut() {
+        let number = *index as u32 + 1;
+        if let Some(numbers) = &regeneration.partitions {
+            if !numbers.contains(&number) {
+                continue;
+            }
+        }
+        let unique_partition_guid = Guid::new_random()?;
+        changes.push(GuidChange {
+            identifier: format!("Partition {}", number),
+            old: entry.unique_partition_guid().to_string(),
+            new: unique_partition_guid.to_string(),
+        });
+        entry.set_unique_partition_guid(unique_partition_guid);
+    }
+    let entry_array = build_entry_array(
+        &entries,
+        header.number_partition_entries(),
+        header.size_single_partition_entry(),
+    );
+    write_gpt(path, &header, &entry_array)?;
+
+    if regeneration.mbr_disk_signature {
+        let mut bytes = [0u8; 4];
+        // Zero means the disk has no signature
+        while bytes == [0u8; 4] {
+            getrandom::getrandom(&mut bytes)
+                .map_err(|error| io::Error::other(error.to_string()))?;
+        }
+        let disk_signature = u32::from_le_bytes(bytes);
+        changes.push(GuidChange {
+            identifier: "MBR disk signature".into(),
+            old: format!("{:#010x}", read_disk_signature(path)?),
+            new: format!("{:#010x}", disk_signature),
+        });
+        write_disk_signature(path, disk_signature)?;
+    }
+    Ok(changes)
+}
+
+pub fn display_guid_changes(changes: &[GuidChange]) {
+    let mut table = Table::new();
+    table.add_row(row!["Identifier", "Old", "New"]);
+    for change in changes {
+        table.add_row(row![change.identifier, change.old, change.new]);
+    }
+    table.printstd();
+}
+
+#[test]
+fn test_edit_gpt() {
+    let path = crate::gpt::write_test_gpt("edit", 16384);
+    let linux = crate::partition_types::LINUX_FILESYSTEM_GUID;
+    let add = |starting_lba: Option<u64>, size: Option<u64>| GptEdit::Add {
+        number: None,
+        starting_lba,
+        size,
+        alignment: None,
+        partition_type: linux,
+        name: "added".into(),
+    };
+
+    // The test GPT holds partition 1 at 2048-4095
+    edit_gpt(&path, &add(None, Some(2048))).unwrap();
+    assert!(edit_gpt(&path, &add(Some(3000), Some(8))).is_err());
+    edit_gpt(&path, &GptEdit::Delete { number: 1 }).unwrap();
+    edit_gpt(
+        &path,
+        &GptEdit::Resize {
+            number: 2,
+            size: None,
+        },
+    )
+    .unwrap();
+    edit_gpt(&path, &add(None, None)).unwrap();
+    edit_gpt(
+        &path,
+        &GptEdit::Rename {
+            number: 1,
+            name: "first".into(),
+        },
+    )
+    .unwrap();
+    edit_gpt(
+        &path,
+        &GptEdit::SetAttributes {
+            number: 2,
+            attributes: 1 << 60,
+        },
+    )
+    .unwrap();
+    assert!(edit_gpt(
+        &path,
+        &GptEdit::Resize {
+            number: 2,
+            size: Some(20000),
+        },
+    )
+    .is_err());
+
+    let verification = crate::gpt::verify_gpt(&path).unwrap();
+    let entries = parse_gpt(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(verification.issues.is_empty(), "{:?}", verification.issues);
+    let summary: Vec<(usize, u64, u64, String, u64)> = entries
+        .iter()
+        .map(|(index, entry)| {
+            (
+                *index,
+                entry.starting_lba(),
+                entry.ending_lba(),
+                entry.name(),
+                entry.attributes(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (0, 2048, 4095, "first".into(), 0),
+            (1, 4096, 16350, "added".into(), 1 << 60),
+        ]
+    );
+}
+
+#[test]
+fn test_regenerate_guids() {
+    let path = crate::gpt::write_test_gpt("regenerate", 8192);
+    let before = parse_gpt(&path).unwrap();
+    let disk_guid = *parse_validated_gpt_header(&path)
+        .unwrap()
+        .header
+        .disk_guid();
+
+    let mut regeneration = GuidRegeneration {
+        disk_guid: false,
+        partitions: Some(vec![1]),
+        mbr_disk_signature: true,
+    };
+    let changes = regenerate_guids(&path, &regeneration).unwrap();
+    let identifiers: Vec<&str> = changes
+        .iter()
+        .map(|change| change.identifier.as_str())
+        .collect();
+    assert_eq!(identifiers, vec!["Partition 1", "MBR disk signature"]);
+    assert_eq!(
+        changes[0].old,
+        before[0].1.unique_partition_guid().to_string()
+    );
+
+    let verification = crate::gpt::verify_gpt(&path).unwrap();
+    let after = parse_gpt(&path).unwrap();
+    assert!(verification.issues.is_empty(), "{:?}", verification.issues);
+    assert_eq!(
+        changes[0].new,
+        after[0].1.unique_partition_guid().to_string()
+    );
+    assert_eq!(
+        changes[1].new,
+        format!("{:#010x}", read_disk_signature(&path).unwrap())
+    );
+    assert_eq!(
+        *parse_validated_gpt_header(&path)
+            .unwrap()
+            .header
+            .disk_guid(),
+        disk_guid
+    );
+
+    regeneration.partitions = Some(vec![2]);
+    assert!(regenerate_guids(&path, &regeneration).is_err());
+    regeneration.disk_guid = true;
+    regeneration.partitions = Some(Vec::new());
+    regenerate_guids(&path, &regeneration).unwrap();
+    let header = parse_validated_gpt_header(&path).unwrap().header;
+    std::fs::remove_file(&path).unwrap();
+    assert_ne!(*header.disk_guid(), disk_guid);
+}
@@ -1,97 +1,57 @@
-use crate::bytestream::{interpret_bytes_as_utf16, ByteStream, Readable, SECTOR_SIZE};
+use crate::{
+    bytestream::{
+        encode_utf16_bytes, image_size_in_sectors, interpret_bytes_as_utf16, write_sectors_to_file,
+        ByteStream, Readable, Writable, SECTOR_SIZE,
+    },
+    checksum::{crc32, Crc32},
+    guid::Guid,
+    mbr::write_protective_mbr,
+    partition_types::{
+        gpt_type_name, is_discoverable_partition_type, CHROMEOS_KERNEL_GUID,
+        MICROSOFT_BASIC_DATA_GUID,
+    },
+};
+use byteorder::{LittleEndian, WriteBytesExt};
 use prettytable::{row, Table};
 use std::{
     fmt::Display,
-    io::{self},
+    io::{self, Write},
     path::Path,
     string::FromUtf16Error,
 };
 
-// https://www.ietf.org/rfc/rfc4122.txt
-// 4.1.2.  Layout and Byte Order
-#[derive(Debug)]
-struct Guid {
-    // The low field of the timestamp
-    time_low: u32,
-    // The middle field of the timestamp
-    time_mid: u16,
-    // The high field of the timestamp multiplexed with the version number
-    time_high_and_version: u16,
-    // The high field of the clock sequence multiplexed with the variant
-    clock_seq_high_and_reserved: u8,
-    // The low field of the clock sequence
-    clock_seq_low: u8,
-    // The spatially unique node identifier
-    node_identifier: [u8; 6],
-}
-
-impl Guid {
-    pub const fn new(bytes: [u8; 16]) -> Self {
-        // The first three dash-delimited fields of the GUID are stored little endian, and the last two fields are not
-        let time_low = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
-        let time_mid = u16::from_le_bytes([bytes[4], bytes[5]]);
-        let time_high_and_version = u16::from_le_bytes([bytes[6], bytes[7]]);
-        let clock_seq_high_and_reserved = bytes[8];
-        let clock_seq_low = bytes[9];
-        let node_identifier = [
-            bytes[10], bytes[11], bytes[12], bytes[13], bytes[14], bytes[15],
-        ];
-
-        Self {
-            time_low,
-            time_mid,
-            time_high_and_version,
-            clock_seq_high_and_reserved,
-            clock_seq_low,
-            node_identifier,
-        }
-    }
-}
-
-impl ToString for Guid {
-    fn to_string(&self) -> String {
-        let clock_seq = u16::from_be_bytes([self.clock_seq_high_and_reserved, self.clock_seq_low]);
+pub const GPT_SIGNATURE: &str = "EFI PART";
+// Revision 1.0
+const GPT_REVISION: [u8; 4] = [0x00, 0x00, 0x01, 0x00];
+const GPT_HEADER_SIZE: u32 = 92;
+pub const DEFAULT_NUMBER_PARTITION_ENTRIES: u32 = 128;
+pub const DEFAULT_PARTITION_ENTRY_SIZE: u32 = 128;
+// Size of the fields of a partition entry, larger entries are padded with reserved bytes
+pub const MINIMUM_PARTITION_ENTRY_SIZE: u32 = 128;
 
-        // Ignore first 2 bytes.
-        let mut tmp_buffer = [0u8; 8];
-        tmp_buffer[2..].copy_from_slice(&self.node_identifier);
-        let node = u64::from_be_bytes(tmp_buffer);
-
-        let guid = format!(
-            "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
-            self.time_low, self.time_mid, self.time_high_and_version, clock_seq, node
-        )
-        .to_uppercase();
-        guid
-    }
-}
-
-impl Readable for Guid {
-    fn read(reader: &mut ByteStream) -> io::Result<Self>
-    where
-        Self: Sized,
-    {
-        let x = reader.read_byte_array::<16>()?;
-        Ok(Guid::new(x))
-    }
-}
+// https://en.wikipedia.org/wiki/GUID_Partition_Table#Partition_entries_(LBA_2%E2%80%9333)
+const GPT_ATTRIBUTE_NAMES: [(u32, &str); 3] = [
+    (0, "Platform required"),
+    (1, "EFI firmware ignore"),
+    (2, "Legacy BIOS bootable"),
+];
+pub const GPT_TYPE_SPECIFIC_ATTRIBUTES_START: u32 = 48;
+pub const LEGACY_BIOS_BOOTABLE_ATTRIBUTE: u64 = 1 << 2;
+const MICROSOFT_BASIC_DATA_ATTRIBUTE_NAMES: [(u32, &str); 4] = [
+    (60, "Read-only"),
+    (61, "Shadow copy"),
+    (62, "Hidden"),
+    (63, "No drive letter"),
+];
+// https://uapi-group.org/specifications/specs/discoverable_partitions_specification/#partition-attribute-flags
+const DISCOVERABLE_PARTITION_ATTRIBUTE_NAMES: [(u32, &str); 3] = [
+    (59, "Grow file system"),
+    (60, "Read-only"),
+    (63, "No auto-mount"),
+];
 
-#[test]
-fn test_guid() {
-    // https://developer.apple.com/library/archive/technotes/tn2166/_index.html#//apple_ref/doc/uid/DTS10003927-CH1-SECTION2
-    let bytes: [u8; 16] = [
-        0x28, 0x73, 0x2a, 0xc1, 0x1f, 0xf8, 0xd2, 0x11, 0xba, 0x4b, 0x00, 0xa0, 0xc9, 0x3e, 0xc9,
-        0x3b,
-    ];
-    let guid = Guid::new(bytes);
-    assert_eq!(
-        format!("{}", guid.to_string()),
-        "C12A7328-F81F-11D2-BA4B-00A0C93EC93B"
-    )
-}
-
-#[derive(Debug)]
-struct GptHeader {
+#[derive(Debug, Clone)]
+pub struct GptHeader {
     efi_part: String,
     //Revision 1.0 (00h 00h 01h 00h) for UEFI 2.8
     revision: [u8; 4],
@@ -120,10 +80,8 @@ impl Readable for GptHeader {
         let revision_buffer = reader.read_byte_array::<4>()?;
 
         Ok(Self {
-            efi_part: String::from_utf8(efi_part_buffer.to_vec())
-                .unwrap()
-                .trim()
-                .into(),
+            // A damaged header may hold anything, which then fails the signature check
+            efi_part: String::from_utf8_lossy(&efi_part_buffer).trim().into(),
             revision: revision_buffer,
             header_size: reader.read_le()?,
             crc32: reader.read_le()?,
@@ -141,7 +99,185 @@ impl Readable for GptHeader {
     }
 }
 
-#[derive(Debug)]
+impl Writable for GptHeader {
+    fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut signature = [0u8; 8];
+        signature[..self.efi_part.len().min(8)]
+            .copy_from_slice(&self.efi_part.as_bytes()[..self.efi_part.len().min(8)]);
+        writer.write_all(&signature)?;
+        writer.write_all(&self.revision)?;
+        writer.write_u32::<LittleEndian>(self.header_size)?;
+        writer.write_u32::<LittleEndian>(self.crc32)?;
+        writer.write_u32::<LittleEndian>(self.reserved)?;
+        writer.write_u64::<LittleEndian>(self.current_lba)?;
+        writer.write_u64::<LittleEndian>(self.backup_lba)?;
+        writer.write_u64::<LittleEndian>(self.first_usable_lba)?;
+        writer.write_u64::<LittleEndian>(self.last_usable_lba)?;
+        writer.write_all(&self.disk_guid.to_bytes())?;
+        writer.write_u64::<LittleEndian>(self.starting_lba_of_partition_entries)?;
+        writer.write_u32::<LittleEndian>(self.number_partition_entries)?;
+        writer.write_u32::<LittleEndian>(self.size_single_partition_entry)?;
+        writer.write_u32::<LittleEndian>(self.crc32_partition_entries)
+    }
+}
+
+impl GptHeader {
+    /// A primary header at LBA 1 whose entry array directly follows it and whose backup lives at `backup_lba`.
+    pub fn new(
+        disk_guid: Guid,
+        backup_lba: u64,
+        first_usable_lba: u64,
+        last_usable_lba: u64,
+        number_partition_entries: u32,
+    ) -> Self {
+        Self {
+            efi_part: GPT_SIGNATURE.into(),
+            revision: GPT_REVISION,
+            header_size: GPT_HEADER_SIZE,
+            crc32: 0,
+            reserved: 0,
+            current_lba: 1,
+            backup_lba,
+            first_usable_lba,
+            last_usable_lba,
+            disk_guid,
+            starting_lba_of_partition_entries: 2,
+            number_partition_entries,
+            size_single_partition_entry: DEFAULT_PARTITION_ENTRY_SIZE,
+            crc32_partition_entries: 0,
+        }
+    }
+
+    pub fn current_lba(&self) -> u64 {
+        self.current_lba
+    }
+
+    pub fn backup_lba(&self) -> u64 {
+        self.backup_lba
+    }
+
+    pub fn disk_guid(&self) -> &Guid {
+        &self.disk_guid
+    }
+
+    pub fn set_disk_guid(&mut self, disk_guid: Guid) {
+        self.disk_guid = disk_guid;
+    }
+
+    pub fn first_usable_lba(&self) -> u64 {
+        self.first_usable_lba
+    }
+
+    pub fn last_usable_lba(&self) -> u64 {
+        self.last_usable_lba
+    }
+
+    pub fn starting_lba_of_partition_entries(&self) -> u64 {
+        self.starting_lba_of_partition_entries
+    }
+
+    pub fn number_partition_entries(&self) -> u32 {
+        self.number_partition_entries
+    }
+
+    pub fn size_single_partition_entry(&self) -> u32 {
+        self.size_single_partition_entry
+    }
+
+    /// A primary header at LBA 1 with the contents of this header copy, pointing to a backup at `backup_lba`.
+    /// A backup header's entry array is moved to LBA 2, right after the primary header.
+    pub fn to_primary(&self, backup_lba: u64, last_usable_lba: u64) -> Self {
+        let mut primary = self.clone();
+        if primary.current_lba != 1 {
+            primary.starting_lba_of_partition_entries = 2;
+        }
+        primary.current_lba = 1;
+        primary.backup_lba = backup_lba;
+        primary.last_usable_lba = last_usable_lba;
+        primary
+    }
+
+    /// Field names and values in on-disk order, for showing and comparing headers.
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("Signature", self.efi_part.clone()),
+            (
+                "Revision",
+                format!(
+                    "{}.{}",
+                    u16::from_le_bytes([self.revision[2], self.revision[3]]),
+                    u16::from_le_bytes([self.revision[0], self.revision[1]])
+                ),
+            ),
+            ("Header Size", self.header_size.to_string()),
+            ("Header CRC32", format!("{:#010x}", self.crc32)),
+            ("Current LBA", self.current_lba.to_string()),
+            ("Backup LBA", self.backup_lba.to_string()),
+            ("First Usable LBA", self.first_usable_lba.to_string()),
+            ("Last Usable LBA", self.last_usable_lba.to_string()),
+            ("Disk GUID", self.disk_guid.to_string()),
+            (
+                "Disk GUID Version",
+                format!(
+                    "{} ({} variant)",
+                    self.disk_guid.version(),
+                    self.disk_guid.variant()
+                ),
+            ),
+            (
+                "Partition Entry LBA",
+                self.starting_lba_of_partition_entries.to_string(),
+            ),
+            (
+                "Number of Partition Entries",
+                self.number_partition_entries.to_string(),
+            ),
+            (
+                "Partition Entry Size",
+                self.size_single_partition_entry.to_string(),
+            ),
+            (
+                "Partition Entries CRC32",
+                format!("{:#010x}", self.crc32_partition_entries),
+            ),
+        ]
+    }
+
+    /// Number of sectors needed to hold the partition entry array.
+    pub fn partition_entry_sectors(&self) -> u64 {
+        (self.number_partition_entries as u64 * self.size_single_partition_entry as u64)
+            .div_ceil(SECTOR_SIZE as u64)
+    }
+
+    /// The backup copy of this header, placed at `backup_lba` with its entry array right before it.
+    pub fn to_backup(&self) -> Self {
+        let mut backup = self.clone();
+        backup.current_lba = self.backup_lba;
+        backup.backup_lba = self.current_lba;
+        backup.starting_lba_of_partition_entries = self.backup_lba - self.partition_entry_sectors();
+        backup.update_header_crc32();
+        backup
+    }
+
+    /// Sets both CRC32 fields, `entry_array` being the complete array of `number_partition_entries` entries.
+    pub fn update_crc32(&mut self, entry_array: &[u8]) {
+        self.crc32_partition_entries = crc32(entry_array);
+        self.update_header_crc32();
+    }
+
+    fn has_valid_header_size(&self) -> bool {
+        self.header_size >= GPT_HEADER_SIZE && self.header_size as usize <= SECTOR_SIZE
+    }
+
+    fn update_header_crc32(&mut self) {
+        self.crc32 = 0;
+        let mut header_bytes = self.to_bytes();
+        header_bytes.resize(self.header_size as usize, 0);
+        self.crc32 = crc32(&header_bytes);
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct GptPartitionTableEntry {
     partition_type_guid: Guid,
     unique_partition_guid: Guid,
@@ -173,20 +309,134 @@ impl Readable for GptPartitionTableEntry {
     }
 }
 
+impl Writable for GptPartitionTableEntry {
+    fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.partition_type_guid.to_bytes())?;
+        writer.write_all(&self.unique_partition_guid.to_bytes())?;
+        writer.write_u64::<LittleEndian>(self.starting_lba)?;
+        writer.write_u64::<LittleEndian>(self.ending_lba)?;
+        writer.write_all(&self.attribute_flags)?;
+        writer.write_all(&self.partition_name)
+    }
+}
+
 impl GptPartitionTableEntry {
-    pub fn get_partition_type_guid(&self) -> String {
-        self.partition_type_guid.to_string()
+    pub fn new(
+        partition_type_guid: Guid,
+        unique_partition_guid: Guid,
+        starting_lba: u64,
+        ending_lba: u64,
+        attributes: u64,
+        name: &str,
+    ) -> Self {
+        Self {
+            partition_type_guid,
+            unique_partition_guid,
+            starting_lba,
+            ending_lba,
+            attribute_flags: attributes.to_le_bytes(),
+            partition_name: encode_utf16_bytes::<72>(name),
+        }
+    }
+
+    pub fn partition_type(&self) -> &Guid {
+        &self.partition_type_guid
+    }
+
+    pub fn unique_partition_guid(&self) -> &Guid {
+        &self.unique_partition_guid
     }
 
     pub fn starting_lba(&self) -> u64 {
         self.starting_lba
     }
 
+    pub fn ending_lba(&self) -> u64 {
+        self.ending_lba
+    }
+
+    pub fn attributes(&self) -> u64 {
+        u64::from_le_bytes(self.attribute_flags)
+    }
+
+    pub fn set_partition_type(&mut self, partition_type: Guid) {
+        self.partition_type_guid = partition_type;
+    }
+
+    pub fn set_unique_partition_guid(&mut self, unique_partition_guid: Guid) {
+        self.unique_partition_guid = unique_partition_guid;
+    }
+
+    pub fn set_ending_lba(&mut self, ending_lba: u64) {
+        self.ending_lba = ending_lba;
+    }
+
+    pub fn set_attributes(&mut self, attributes: u64) {
+        self.attribute_flags = attributes.to_le_bytes();
+    }
+
+    pub fn set_name(&mut self, name: &str) {
+        self.partition_name = encode_utf16_bytes::<72>(name);
+    }
+
+    /// The partition name with the trailing NUL padding removed.
+    pub fn name(&self) -> String {
+        self.partition_name()
+            .unwrap_or_default()
+            .trim_end_matches('\0')
+            .into()
+    }
+
+    /// Names of the set attribute bits, type specific bits (48-63) interpreted according to the partition type.
+    pub fn describe_attributes(&self) -> Vec<String> {
+        let attributes = self.attributes();
+        let is_set = |bit: u32| attributes & (1u64 << bit) != 0;
+        let partition_type = self.partition_type_guid;
+        let mut descriptions = Vec::new();
+
+        for bit in (0..GPT_TYPE_SPECIFIC_ATTRIBUTES_START).filter(|bit| is_set(*bit)) {
+            match GPT_ATTRIBUTE_NAMES
+                .iter()
+                .find(|(named_bit, _)| *named_bit == bit)
+            {
+                Some((_, name)) => descriptions.push(name.to_string()),
+                None => descriptions.push(format!("Reserved bit {}", bit)),
+            }
+        }
+
+        let type_specific_names: &[(u32, &str)] = if partition_type == MICROSOFT_BASIC_DATA_GUID {
+            &MICROSOFT_BASIC_DATA_ATTRIBUTE_NAMES
+        } else if is_discoverable_partition_type(&self.partition_type_guid) {
+            &DISCOVERABLE_PARTITION_ATTRIBUTE_NAMES
+        } else if partition_type == CHROMEOS_KERNEL_GUID {
+            // Bits 48-51 hold the boot priority, 52-55 the remaining tries and 56 marks a successful boot
+            descriptions.push(format!("Priority {}", (attributes >> 48) & 0xF));
+            descriptions.push(format!("Tries remaining {}", (attributes >> 52) & 0xF));
+            if is_set(56) {
+                descriptions.push("Successful boot".into());
+            }
+            for bit in (57..64).filter(|bit| is_set(*bit)) {
+                descriptions.push(format!("Type specific bit {}", bit));
+            }
+            return descriptions;
+        } else {
+            &[]
+        };
+        for bit in (GPT_TYPE_SPECIFIC_ATTRIBUTES_START..64).filter(|bit| is_set(*bit)) {
+            match type_specific_names
+                .iter()
+                .find(|(named_bit, _)| *named_bit == bit)
+            {
+                Some((_, name)) => descriptions.push(name.to_string()),
+                None => descriptions.push(format!("Type specific bit {}", bit)),
+            }
+        }
+        descriptions
+    }
+
+    // An entry is unused when its partition type GUID is zero, whatever the other fields contain
     fn is_empty(&self) -> bool {
-        self.starting_lba == 0
-            && self.ending_lba == 0
-            && self.attribute_flags.iter().all(|byte| *byte == 0)
-            && self.partition_name.iter().all(|byte| *byte == 0)
+        self.partition_type_guid.is_zero()
     }
 
     fn partition_name(&self) -> Result<String, FromUtf16Error> {
@@ -196,209 +446,915 @@ impl GptPartitionTableEntry {
 
 impl Display for GptPartitionTableEntry {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "partition_type_guid: {}\n", self.partition_type_guid)?;
+        write!(f, "unique_partition_guid: {}\n", self.unique_partition_guid)?;
+        write!(f, "starting_lba: {}\n", self.starting_lba)?;
+        write!(f, "ending_lba: {}\n", self.ending_lba)?;
         write!(
             f,
-            "partition_type_guid: {}\n",
-            self.partition_type_guid.to_string()
-        )?;
-        write!(
-            f,
-            "unique_partition_guid: {}\n",
-            self.unique_partition_guid.to_string()
+            "attribute_flags: {:#018x} {:?}\n",
+            self.attributes(),
+            self.describe_attributes()
         )?;
-        write!(f, "starting_lba: {}\n", self.starting_lba)?;
-        write!(f, "ending_lba: {}\n", self.ending_lba)?;
-        write!(f, "attribute_flags: {:#?}\n", self.attribute_flags)?;
         write!(f, "partition_name: {:#?}\n", self.partition_name())
     }
 }
 
-fn is_valid_header_crc32(path: &Path, header_size: u32, crc32: u32) -> io::Result<bool> {
-    let mut stream = ByteStream::new(path, SECTOR_SIZE, 1)?;
-    let mut header_bytes = stream.read_raw(header_size as usize)?;
+fn is_valid_header_crc32(path: &Path, lba: u64, header_size: u32, crc32: u32) -> io::Result<bool> {
+    let mut stream = ByteStream::new(path, SECTOR_SIZE, lba)?;
+    let header_bytes = stream.read_raw(header_size as usize)?;
 
     // CRC32 of header (offset +0 to +0x5b) in little endian, with this field zeroed during calculation
-    header_bytes.splice(16..20, vec![0u8; 4]);
-    Ok(calculate_crc32(header_bytes) == crc32)
+    let mut crc = Crc32::new();
+    crc.update(&header_bytes[..16]);
+    crc.update(&[0u8; 4]);
+    crc.update(&header_bytes[20..]);
+    Ok(crc.finish() == crc32)
+}
+
+/// Serializes `entries` into an entry array of `number_partition_entries` slots, each entry placed
+/// at its zero based slot index. Unused slots are left zeroed.
+pub fn build_entry_array(
+    entries: &[(usize, GptPartitionTableEntry)],
+    number_partition_entries: u32,
+    size_single_partition_entry: u32,
+) -> Vec<u8> {
+    let entry_size = size_single_partition_entry as usize;
+    let mut entry_array = vec![0u8; number_partition_entries as usize * entry_size];
+    for (index, entry) in entries {
+        let bytes = entry.to_bytes();
+        let offset = index * entry_size;
+        entry_array[offset..offset + bytes.len()].copy_from_slice(&bytes);
+    }
+    entry_array
+}
+
+/// The sectors of both copies of the GPT as `(lba, bytes)` pairs: `primary`, its entry array, the backup
+/// header at `primary.backup_lba` and the backup entry array. The CRC32 fields of both headers are recalculated.
+pub fn gpt_sectors(primary: &GptHeader, entry_array: &[u8]) -> Vec<(u64, Vec<u8>)> {
+    let mut primary = primary.clone();
+    primary.update_crc32(entry_array);
+    let backup = primary.to_backup();
+    let mut sectors = Vec::new();
+    for header in [&primary, &backup] {
+        let mut sector = header.to_bytes();
+        sector.resize(SECTOR_SIZE, 0);
+        sectors.push((header.current_lba, sector));
+        sectors.push((
+            header.starting_lba_of_partition_entries,
+            entry_array.to_vec(),
+        ));
+    }
+    sectors
+}
+
+/// Writes `primary` and its entry array, followed by the backup header and entry array at `primary.backup_lba`.
+/// The CRC32 fields of both headers are recalculated before writing.
+pub fn write_gpt(path: &Path, primary: &GptHeader, entry_array: &[u8]) -> io::Result<()> {
+    for (lba, bytes) in gpt_sectors(primary, entry_array) {
+        write_sectors_to_file(path, lba, &bytes)?;
+    }
+    Ok(())
+}
+
+/// The widest usable LBA range of a GPT with `number_partition_entries` default sized entries on an image of
+/// `total_sectors`, everything between the primary entry array and the backup entry array.
+pub fn default_usable_lba_range(
+    total_sectors: u64,
+    number_partition_entries: u32,
+) -> io::Result<(u64, u64)> {
+    let entry_sectors = (number_partition_entries as u64 * DEFAULT_PARTITION_ENTRY_SIZE as u64)
+        .div_ceil(SECTOR_SIZE as u64);
+    // Protective MBR, primary header and the primary entry array
+    let first_usable_lba = 2 + entry_sectors;
+    if total_sectors < first_usable_lba * 2 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Image of {} sectors is too small for a GPT", total_sectors),
+        ));
+    }
+    Ok((first_usable_lba, total_sectors - entry_sectors - 2))
+}
+
+/// Writes a protective MBR and a fresh GPT spanning the whole image with the default 128 entry array.
+/// `entries` are placed at their zero based slot index.
+pub fn write_new_gpt(
+    path: &Path,
+    disk_guid: Guid,
+    first_usable_lba: Option<u64>,
+    last_usable_lba: Option<u64>,
+    number_partition_entries: u32,
+    entries: &[(usize, GptPartitionTableEntry)],
+) -> io::Result<()> {
+    let total_sectors = image_size_in_sectors(path)?;
+    let (minimum_first_usable, maximum_last_usable) =
+        default_usable_lba_range(total_sectors, number_partition_entries)?;
+    let backup_lba = total_sectors - 1;
+    let first_usable_lba = first_usable_lba.unwrap_or(minimum_first_usable);
+    let last_usable_lba = last_usable_lba.unwrap_or(maximum_last_usable);
+    if first_usable_lba < minimum_first_usable || last_usable_lba > maximum_last_usable {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Usable LBA range {}-{} overlaps the GPT structures",
+                first_usable_lba, last_usable_lba
+            ),
+        ));
+    }
+    if let Some((index, _)) = entries
+        .iter()
+        .find(|(index, _)| *index >= number_partition_entries as usize)
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Partition {} does not fit in a table of {} entries",
+                index + 1,
+                number_partition_entries
+            ),
+        ));
+    }
+    if let Some((index, _)) = entries.iter().find(|(_, entry)| {
+        entry.starting_lba < first_usable_lba
+            || entry.ending_lba > last_usable_lba
+            || entry.starting_lba > entry.ending_lba
+    }) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Partition {} is outside of the usable LBA range {}-{}",
+                index + 1,
+                first_usable_lba,
+                last_usable_lba
+            ),
+        ));
+    }
+
+    let header = GptHeader::new(
+        disk_guid,
+        backup_lba,
+        first_usable_lba,
+        last_usable_lba,
+        number_partition_entries,
+    );
+    let entry_array = build_entry_array(
+        entries,
+        number_partition_entries,
+        DEFAULT_PARTITION_ENTRY_SIZE,
+    );
+    write_protective_mbr(path, total_sectors)?;
+    write_gpt(path, &header, &entry_array)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GptHeaderCopy {
+    Primary,
+    Backup,
 }
 
-// https://lxp32.github.io/docs/a-simple-example-crc32-calculation/
-fn calculate_crc32(bytes: Vec<u8>) -> u32 {
-    let mut crc: u32 = 0xFFFFFFFF;
+impl Display for GptHeaderCopy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GptHeaderCopy::Primary => write!(f, "primary"),
+            GptHeaderCopy::Backup => write!(f, "backup"),
+        }
+    }
+}
+
+/// Why a GPT header copy can't be trusted.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GptHeaderProblem {
+    InvalidSignature,
+    InvalidHeaderSize(u32),
+    HeaderCrc32Mismatch,
+    EntryArrayUnreadable,
+    EntryArrayCrc32Mismatch,
+}
 
-    for mut byte in bytes {
-        for _ in 0..8 {
-            let tmp = ((byte as u32) ^ crc) & 1;
-            crc >>= 1;
-            if tmp != 0 {
-                crc = crc ^ 0xEDB88320;
+impl Display for GptHeaderProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GptHeaderProblem::InvalidSignature => write!(f, "signature is not `{}`", GPT_SIGNATURE),
+            GptHeaderProblem::InvalidHeaderSize(size) => {
+                write!(f, "invalid header size of {} bytes", size)
+            }
+            GptHeaderProblem::HeaderCrc32Mismatch => write!(f, "header CRC32 mismatch"),
+            GptHeaderProblem::EntryArrayUnreadable => {
+                write!(f, "partition entry array lies outside of the image")
+            }
+            GptHeaderProblem::EntryArrayCrc32Mismatch => {
+                write!(f, "partition entry array CRC32 mismatch")
             }
-            byte >>= 1;
         }
     }
-    !crc
 }
 
-pub fn parse_gpt(path: &Path) -> io::Result<Vec<GptPartitionTableEntry>> {
-    let mut stream = ByteStream::new(path, SECTOR_SIZE, 1)?;
-    // stream.jump_to_sector(1)?;
-    let header = stream.read::<GptHeader>()?;
+/// A GPT header that passed validation, along with which copy it is and, when it is the backup,
+/// why the primary was rejected.
+#[derive(Debug)]
+pub struct ValidatedGptHeader {
+    pub header: GptHeader,
+    pub copy: GptHeaderCopy,
+    pub primary_problem: Option<GptHeaderProblem>,
+}
 
-    if header.efi_part == "EFI PART" {
-        //FIXME: Throw error if invalid header.
+impl ValidatedGptHeader {
+    /// One line description of which header copy is used and why.
+    pub fn describe(&self) -> String {
+        match (self.copy, &self.primary_problem) {
+            (GptHeaderCopy::Backup, Some(problem)) => format!(
+                "Primary GPT header is invalid ({}), using backup GPT header at LBA {}",
+                problem, self.header.current_lba
+            ),
+            (copy, _) => format!(
+                "Using {} GPT header at LBA {}",
+                copy, self.header.current_lba
+            ),
+        }
     }
+}
 
-    println!("Header guid: {}", header.disk_guid.to_string());
-    println!();
-    if !is_valid_header_crc32(path, header.header_size, header.crc32)? {
-        // FIXME: Check backup header if crc32 fails.
+/// One copy of the GPT, a header with the partition entry array it points to, as found on disk.
+#[derive(Debug)]
+pub struct GptCopy {
+    pub header: GptHeader,
+    pub header_crc32_valid: bool,
+    // None when the header is unusable or the array lies outside of the image
+    pub entry_array: Option<Vec<u8>>,
+    pub entry_array_crc32_valid: bool,
+}
+
+impl GptCopy {
+    /// Reads the header at `lba` and checks its signature, header CRC32 and the CRC32 of its partition entry array.
+    pub fn read(path: &Path, lba: u64) -> io::Result<Self> {
+        Self::read_at(path, lba, 0)
     }
 
-    let number_of_sectors =
-        (header.number_partition_entries * header.size_single_partition_entry) / SECTOR_SIZE as u32;
+    /// Like `read`, for a GPT of a disk starting at `disk_lba` of the image, such as a VM disk stored in a
+    /// partition. The LBAs in the header are relative to `disk_lba`, `lba` is not.
+    pub fn read_at(path: &Path, lba: u64, disk_lba: u64) -> io::Result<Self> {
+        let mut stream = ByteStream::new(path, SECTOR_SIZE, lba)?;
+        let header = stream.read::<GptHeader>()?;
+        let mut copy = Self {
+            header,
+            header_crc32_valid: false,
+            entry_array: None,
+            entry_array_crc32_valid: false,
+        };
+        let header = &copy.header;
+        if header.efi_part != GPT_SIGNATURE || !header.has_valid_header_size() {
+            return Ok(copy);
+        }
+        copy.header_crc32_valid =
+            is_valid_header_crc32(path, lba, header.header_size, header.crc32)?;
 
-    let buffer = stream.read_raw_sectors_from_file(
-        header.starting_lba_of_partition_entries as usize,
-        number_of_sectors as usize,
-    )?;
+        // The CRC32 covers exactly `number_partition_entries` entries, not the whole sectors holding them
+        let entry_array_size =
+            header.number_partition_entries as u64 * header.size_single_partition_entry as u64;
+        let entry_array_start = disk_lba.saturating_add(header.starting_lba_of_partition_entries);
+        let entry_array_end = entry_array_start.saturating_add(header.partition_entry_sectors());
+        if entry_array_end > image_size_in_sectors(path)? {
+            return Ok(copy);
+        }
+        let mut entry_array = stream.read_raw_sectors_from_file(
+            entry_array_start as usize,
+            header.partition_entry_sectors() as usize,
+        )?;
+        entry_array.truncate(entry_array_size as usize);
+        copy.entry_array_crc32_valid = crc32(&entry_array) == header.crc32_partition_entries;
+        copy.entry_array = Some(entry_array);
+        Ok(copy)
+    }
 
-    if calculate_crc32(buffer) != header.crc32_partition_entries {
-        // FIXME: Check backup header if crc32 fails.
+    /// The first reason this copy can't be trusted, if any.
+    pub fn problem(&self) -> Option<GptHeaderProblem> {
+        if self.header.efi_part != GPT_SIGNATURE {
+            Some(GptHeaderProblem::InvalidSignature)
+        } else if !self.header.has_valid_header_size() {
+            Some(GptHeaderProblem::InvalidHeaderSize(self.header.header_size))
+        } else if !self.header_crc32_valid {
+            Some(GptHeaderProblem::HeaderCrc32Mismatch)
+        } else if self.entry_array.is_none() {
+            Some(GptHeaderProblem::EntryArrayUnreadable)
+        } else if !self.entry_array_crc32_valid {
+            Some(GptHeaderProblem::EntryArrayCrc32Mismatch)
+        } else {
+            None
+        }
     }
+}
 
-    let mut partition_table = Vec::new();
-    'outer: for index in 0..number_of_sectors {
-        let sector_lba = header.starting_lba_of_partition_entries + index as u64;
-        let mut table_stream = ByteStream::new(&path, SECTOR_SIZE * 32, sector_lba)?;
-
-        loop {
-            let partition_table_entry = table_stream.read::<GptPartitionTableEntry>()?;
-            if partition_table_entry.is_empty() {
-                break 'outer;
+fn read_validated_header(path: &Path, lba: u64) -> io::Result<Result<GptHeader, GptHeaderProblem>> {
+    let copy = GptCopy::read(path, lba)?;
+    Ok(match copy.problem() {
+        Some(problem) => Err(problem),
+        None => Ok(copy.header),
+    })
+}
+
+/// Validates the primary GPT header and falls back to the backup header when the primary is damaged.
+/// The backup is looked for at the primary's `backup_lba` when the primary header itself is intact and
+/// at the last LBA of the image otherwise.
+pub fn parse_validated_gpt_header(path: &Path) -> io::Result<ValidatedGptHeader> {
+    let primary_problem = match read_validated_header(path, 1)? {
+        Ok(header) => {
+            return Ok(ValidatedGptHeader {
+                header,
+                copy: GptHeaderCopy::Primary,
+                primary_problem: None,
+            })
+        }
+        Err(problem) => problem,
+    };
+
+    let last_lba = image_size_in_sectors(path)?.saturating_sub(1);
+    let mut backup_lbas = Vec::new();
+    if primary_problem == GptHeaderProblem::EntryArrayCrc32Mismatch {
+        backup_lbas.push(parse_gpt_header(path)?.backup_lba);
+    }
+    backup_lbas.push(last_lba);
+
+    let mut backup_problem = None;
+    for backup_lba in backup_lbas {
+        if backup_lba <= 1 || backup_lba > last_lba {
+            continue;
+        }
+        match read_validated_header(path, backup_lba)? {
+            Ok(header) => {
+                return Ok(ValidatedGptHeader {
+                    header,
+                    copy: GptHeaderCopy::Backup,
+                    primary_problem: Some(primary_problem),
+                })
             }
-            partition_table.push(partition_table_entry);
+            Err(problem) => backup_problem = Some(problem),
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "Primary GPT header is invalid ({}) and no valid backup was found{}",
+            primary_problem,
+            backup_problem
+                .map(|problem| format!(" ({})", problem))
+                .unwrap_or_default()
+        ),
+    ))
+}
+
+/// Reads the primary GPT header as is, without any validation.
+pub fn parse_gpt_header(path: &Path) -> io::Result<GptHeader> {
+    let mut stream = ByteStream::new(path, SECTOR_SIZE, 1)?;
+    stream.read::<GptHeader>()
+}
+
+/// Reads the partition entries of the primary GPT, or of the backup GPT when the primary is damaged.
+/// Parses every used entry of the partition entry array along with its zero based slot index, so entry
+/// `index` is partition `index + 1` the way Linux numbers them. Unused entries may appear anywhere in the array.
+pub fn parse_gpt(path: &Path) -> io::Result<Vec<(usize, GptPartitionTableEntry)>> {
+    let header = parse_validated_gpt_header(path)?.header;
+    parse_gpt_entries(path, &header, 0)
+}
+
+/// Parses the used entries of the partition entry array `header` points to, for a disk starting at
+/// `disk_lba` of the image.
+pub fn parse_gpt_entries(
+    path: &Path,
+    header: &GptHeader,
+    disk_lba: u64,
+) -> io::Result<Vec<(usize, GptPartitionTableEntry)>> {
+    let entry_size = header.size_single_partition_entry;
+    if entry_size < MINIMUM_PARTITION_ENTRY_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Partition entry size of {} bytes is smaller than {} bytes",
+                entry_size, MINIMUM_PARTITION_ENTRY_SIZE
+            ),
+        ));
+    }
+
+    let mut table_stream = ByteStream::new(
+        path,
+        header.partition_entry_sectors() as usize * SECTOR_SIZE,
+        disk_lba + header.starting_lba_of_partition_entries,
+    )?;
+    let mut partition_table = Vec::new();
+    for index in 0..header.number_partition_entries as usize {
+        let partition_table_entry = table_stream.read::<GptPartitionTableEntry>()?;
+        table_stream.skip_bytes((entry_size - MINIMUM_PARTITION_ENTRY_SIZE) as u64)?;
+        if !partition_table_entry.is_empty() {
+            partition_table.push((index, partition_table_entry));
         }
     }
     Ok(partition_table)
 }
 
-pub fn display_gpt(partition_table_entries: Vec<GptPartitionTableEntry>) {
+/// Both copies of a GPT and every inconsistency found between them.
+#[derive(Debug)]
+pub struct GptVerification {
+    pub last_lba: u64,
+    pub primary: GptCopy,
+    // The header at the primary's `backup_lba`, or at the last LBA when the primary is unusable
+    pub backup: GptCopy,
+    pub backup_lba: u64,
+    pub issues: Vec<String>,
+}
+
+/// Parses the primary and the backup GPT and cross-checks their location pointers, disk GUID,
+/// usable range, entry array geometry and every partition entry.
+pub fn verify_gpt(path: &Path) -> io::Result<GptVerification> {
+    let last_lba = image_size_in_sectors(path)?.saturating_sub(1);
+    let primary = GptCopy::read(path, 1)?;
+    let mut issues = Vec::new();
+
+    if let Some(problem) = primary.problem() {
+        issues.push(format!("Primary GPT is invalid: {}", problem));
+    }
+    let primary_usable = primary.header.efi_part == GPT_SIGNATURE;
+    let backup_lba =
+        if primary_usable && primary.header.backup_lba > 1 && primary.header.backup_lba <= last_lba
+        {
+            primary.header.backup_lba
+        } else {
+            last_lba
+        };
+    if primary_usable && primary.header.backup_lba != last_lba {
+        issues.push(format!(
+            "Primary header points to a backup at LBA {} but the last LBA of the image is {}{}",
+            primary.header.backup_lba,
+            last_lba,
+            if primary.header.backup_lba < last_lba {
+                ", the image was probably copied to a larger disk"
+            } else {
+                ""
+            }
+        ));
+    }
+
+    let backup = GptCopy::read(path, backup_lba)?;
+    if let Some(problem) = backup.problem() {
+        issues.push(format!(
+            "Backup GPT at LBA {} is invalid: {}",
+            backup_lba, problem
+        ));
+    }
+
+    let (primary_header, backup_header) = (&primary.header, &backup.header);
+    if primary_header.current_lba != 1 {
+        issues.push(format!(
+            "Primary header's current LBA is {} instead of 1",
+            primary_header.current_lba
+        ));
+    }
+    if backup_header.current_lba != backup_lba {
+        issues.push(format!(
+            "Backup header's current LBA is {} but it is located at LBA {}",
+            backup_header.current_lba, backup_lba
+        ));
+    }
+    if backup_header.backup_lba != 1 {
+        issues.push(format!(
+            "Backup header points to a primary at LBA {} instead of 1",
+            backup_header.backup_lba
+        ));
+    }
+    if backup_header
+        .starting_lba_of_partition_entries
+        .saturating_add(backup_header.partition_entry_sectors())
+        > backup_lba
+    {
+        issues.push(format!(
+            "Backup partition entry array at LBA {} overlaps the backup header",
+            backup_header.starting_lba_of_partition_entries
+        ));
+    }
+    if primary_header.disk_guid != backup_header.disk_guid {
+        issues.push(format!(
+            "Disk GUIDs differ: {} (primary) and {} (backup)",
+            primary_header.disk_guid, backup_header.disk_guid
+        ));
+    }
+    let fields = [
+        (
+            "First usable LBA",
+            primary_header.first_usable_lba,
+            backup_header.first_usable_lba,
+        ),
+        (
+            "Last usable LBA",
+            primary_header.last_usable_lba,
+            backup_header.last_usable_lba,
+        ),
+        (
+            "Number of partition entries",
+            primary_header.number_partition_entries as u64,
+            backup_header.number_partition_entries as u64,
+        ),
+        (
+            "Partition entry size",
+            primary_header.size_single_partition_entry as u64,
+            backup_header.size_single_partition_entry as u64,
+        ),
+    ];
+    for (name, primary_value, backup_value) in fields {
+        if primary_value != backup_value {
+            issues.push(format!(
+                "{} differs: {} (primary) and {} (backup)",
+                name, primary_value, backup_value
+            ));
+        }
+    }
+    if primary_header.last_usable_lba >= backup_header.starting_lba_of_partition_entries {
+        issues.push(format!(
+            "Last usable LBA {} overlaps the backup partition entry array at LBA {}",
+            primary_header.last_usable_lba, backup_header.starting_lba_of_partition_entries
+        ));
+    }
+
+    if let (Some(primary_array), Some(backup_array)) = (&primary.entry_array, &backup.entry_array) {
+        let entry_size = primary_header.size_single_partition_entry.max(1) as usize;
+        let differing: Vec<String> = primary_array
+            .chunks(entry_size)
+            .zip(backup_array.chunks(entry_size))
+            .enumerate()
+            .filter(|(_, (primary_entry, backup_entry))| primary_entry != backup_entry)
+            .map(|(index, _)| (index + 1).to_string())
+            .collect();
+        if !differing.is_empty() {
+            issues.push(format!(
+                "Partition entries differ between the copies: {}",
+                differing.join(", ")
+            ));
+        }
+    }
+
+    Ok(GptVerification {
+        last_lba,
+        primary,
+        backup,
+        backup_lba,
+        issues,
+    })
+}
+
+pub fn valid_invalid(valid: bool) -> &'static str {
+    if valid {
+        "Valid"
+    } else {
+        "Invalid"
+    }
+}
+
+pub fn display_gpt_verification(verification: &GptVerification) {
+    println!(
+        "Primary GPT at LBA 1, backup GPT at LBA {}, last LBA of the image is {}\n",
+        verification.backup_lba, verification.last_lba
+    );
+    let (primary, backup) = (&verification.primary, &verification.backup);
+    let mut table = Table::new();
+    table.add_row(row!["Field", "Primary", "Backup"]);
+    table.add_row(row![
+        "Signature",
+        primary.header.efi_part,
+        backup.header.efi_part
+    ]);
+    table.add_row(row![
+        "Current LBA",
+        primary.header.current_lba,
+        backup.header.current_lba
+    ]);
+    table.add_row(row![
+        "Backup LBA",
+        primary.header.backup_lba,
+        backup.header.backup_lba
+    ]);
+    table.add_row(row![
+        "Disk GUID",
+        primary.header.disk_guid.to_string(),
+        backup.header.disk_guid.to_string()
+    ]);
+    table.add_row(row![
+        "Usable LBAs",
+        format!(
+            "{} - {}",
+            primary.header.first_usable_lba, primary.header.last_usable_lba
+        ),
+        format!(
+            "{} - {}",
+            backup.header.first_usable_lba, backup.header.last_usable_lba
+        )
+    ]);
+    table.add_row(row![
+        "Partition Entry LBA",
+        primary.header.starting_lba_of_partition_entries,
+        backup.header.starting_lba_of_partition_entries
+    ]);
+    table.add_row(row![
+        "Partition Entries",
+        format!(
+            "{} x {} bytes",
+            primary.header.number_partition_entries, primary.header.size_single_partition_entry
+        ),
+        format!(
+            "{} x {} bytes",
+            backup.header.number_partition_entries, backup.header.size_single_partition_entry
+        )
+    ]);
+    table.add_row(row![
+        "Header CRC32",
+        format!(
+            "{:#010x} ({})",
+            primary.header.crc32,
+            valid_invalid(primary.header_crc32_valid)
+        ),
+        format!(
+            "{:#010x} ({})",
+            backup.header.crc32,
+            valid_invalid(backup.header_crc32_valid)
+        )
+    ]);
+    table.add_row(row![
+        "Partition Entries CRC32",
+        format!(
+            "{:#010x} ({})",
+            primary.header.crc32_partition_entries,
+            valid_invalid(primary.entry_array_crc32_valid)
+        ),
+        format!(
+            "{:#010x} ({})",
+            backup.header.crc32_partition_entries,
+            valid_invalid(backup.entry_array_crc32_valid)
+        )
+    ]);
+    table.printstd();
+
+    if verification.issues.is_empty() {
+        println!("\nPrimary and backup GPT are consistent");
+    } else {
+        println!();
+        for issue in verification.issues.iter() {
+            println!("Problem: {}", issue);
+        }
+    }
+}
+
+pub fn display_gpt_header(validated: &ValidatedGptHeader) {
+    println!("{}\n", validated.describe());
+    let mut table = Table::new();
+    table.add_row(row!["Field", "Value"]);
+    for (name, value) in validated.header.fields() {
+        // Only a header whose CRC32s match is ever used
+        if name.ends_with("CRC32") {
+            table.add_row(row![name, format!("{} (Valid)", value)]);
+        } else {
+            table.add_row(row![name, value]);
+        }
+    }
+    table.printstd();
+    println!();
+}
+
+pub fn display_gpt(partition_table_entries: Vec<(usize, GptPartitionTableEntry)>) {
     let mut table = Table::new();
-    // TODO: Partition Attributes
-    // https://en.wikipedia.org/wiki/GUID_Partition_Table#:~:text=The%20GUID%20Partition%20Table%20(GPT,globally%20unique%20identifiers%20(GUIDs).
     table.add_row(row![
+        "Number",
+        "Name",
+        "PARTUUID",
         "LBA Starting Sector",
         "LBA Ending Sector",
         "Total Sectors",
         "Size (MB)",
-        "Partition Type"
+        "Partition Type",
+        "Attributes"
     ]);
-    for partition_table_entry in partition_table_entries {
+    for (index, partition_table_entry) in partition_table_entries {
         let total_sectors =
             partition_table_entry.ending_lba - partition_table_entry.starting_lba + 1;
         table.add_row(row![
+            index + 1,
+            partition_table_entry.name(),
+            partition_table_entry.unique_partition_guid.to_string(),
             partition_table_entry.starting_lba,
             partition_table_entry.ending_lba,
             total_sectors,
             ((total_sectors * SECTOR_SIZE as u64) as f64 / 1048576 as f64).round(),
-            lookup_partition_type(partition_table_entry.partition_type_guid)
+            format!(
+                "{}\n{}",
+                gpt_type_name(&partition_table_entry.partition_type_guid),
+                partition_table_entry.partition_type_guid.to_string()
+            ),
+            partition_table_entry.describe_attributes().join("\n")
         ]);
     }
     table.printstd();
 }
 
-fn lookup_partition_type(partition_type: Guid) -> String {
-    match partition_type.to_string().as_str() {
-        "C12A7328-F81F-11D2-BA4B-00A0C93EC93B" => "EFI System",
-        "024DEE41-33E7-11D3-9D69-0008C781F39F" => "MBR partition scheme",
-        "D3BFE2DE-3DAF-11DF-BA40-E3A556D89593" => "Intel Fast Flash",
-        "21686148-6449-6E6F-744E-656564454649" => "BIOS boot",
-        "F4019732-066E-4E12-8273-346C5641494F" => "Sony boot partition",
-        "BFBFAFE7-A34F-448A-9A5B-6213EB736C22" => "Lenovo boot partition",
-        "9E1A2D38-C612-4316-AA26-8B49521E5A8B" => "PowerPC PReP boot",
-        "7412F7D5-A156-4B13-81DC-867174929325" => "ONIE boot",
-        "D4E6E2CD-4469-46F3-B5CB-1BFF57AFC149" => "ONIE config",
-        "E3C9E316-0B5C-4DB8-817D-F92DF00215AE" => "Microsoft reserved",
-        "EBD0A0A2-B9E5-4433-87C0-68B6B72699C7" => "Microsoft basic data",
-        "5808C8AA-7E8F-42E0-85D2-E1E90434CFB3" => "Microsoft LDM metadata",
-        "AF9B60A0-1431-4F62-BC68-3311714A69AD" => "Microsoft LDM data",
-        "DE94BBA4-06D1-4D40-A16A-BFD50179D6AC" => "Windows recovery environment",
-        "37AFFC90-EF7D-4E96-91C3-2D7AE055B174" => "IBM General Parallel Fs",
-        "E75CAF8F-F680-4CEE-AFA3-B001E56EFC2D" => "Microsoft Storage Spaces",
-        "75894C1E-3AEB-11D3-B7C1-7B03A0000000" => "HP-UX data",
-        "E2A1E728-32E3-11D6-A682-7B03A0000000" => "HP-UX service",
-        "0657FD6D-A4AB-43C4-84E5-0933C84B4F4F" => "Linux swap",
-        "0FC63DAF-8483-4772-8E79-3D69D8477DE4" => "Linux filesystem",
-        "3B8F8425-20E0-4F3B-907F-1A25A76F98E8" => "Linux server data",
-        "44479540-F297-41B2-9AF7-D131D5F0458A" => "Linux root (x86)",
-        "69DAD710-2CE4-4E3C-B16C-21A1D49ABED3" => "Linux root (ARM)",
-        "4F68BCE3-E8CD-4DB1-96E7-FBCAF984B709" => "Linux root (x86-64)",
-        "B921B045-1DF0-41C3-AF44-4C6F280D3FAE" => "Linux root (ARM-64)",
-        "993D8D3D-F80E-4225-855A-9DAF8ED7EA97" => "Linux root  (IA-64)",
-        "8DA63339-0007-60C0-C436-083AC8230908" => "Linux reserved",
-        "933AC7E1-2EB4-4F13-B844-0E14E2AEF915" => "Linux home",
-        "A19D880F-05FC-4D3B-A006-743F0F84911E" => "Linux RAID",
-        "BC13C2FF-59E6-4262-A352-B275FD6F7172" => "Linux extended boot",
-        "E6D6D379-F507-44C2-A23C-238F2A3DF928" => "Linux LVM",
-        "516E7CB4-6ECF-11D6-8FF8-00022D09712B" => "FreeBSD data",
-        "83BD6B9D-7F41-11DC-BE0B-001560B84F0F" => "FreeBSD boot",
-        "516E7CB5-6ECF-11D6-8FF8-00022D09712B" => "FreeBSD swap",
-        "516E7CB6-6ECF-11D6-8FF8-00022D09712B" => "FreeBSD UFS",
-        "516E7CBA-6ECF-11D6-8FF8-00022D09712B" => "FreeBSD ZFS",
-        "516E7CB8-6ECF-11D6-8FF8-00022D09712B" => "FreeBSD Vinum",
-        "48465300-0000-11AA-AA11-00306543ECAC" => "Apple HFS/HFS+",
-        "55465300-0000-11AA-AA11-00306543ECAC" => "Apple UFS",
-        "52414944-0000-11AA-AA11-00306543ECAC" => "Apple RAID",
-        "52414944-5F4F-11AA-AA11-00306543ECAC" => "Apple RAID offline",
-        "426F6F74-0000-11AA-AA11-00306543ECAC" => "Apple boot",
-        "4C616265-6C00-11AA-AA11-00306543ECAC" => "Apple label",
-        "5265636F-7665-11AA-AA11-00306543ECAC" => "Apple TV recovery",
-        "53746F72-6167-11AA-AA11-00306543ECAC" => "Apple Core storage",
-        "6A82CB45-1DD2-11B2-99A6-080020736631" => "Solaris boot",
-        "6A85CF4D-1DD2-11B2-99A6-080020736631" => "Solaris root",
-        "6A898CC3-1DD2-11B2-99A6-080020736631" => "Solaris /usr & Apple ZFS",
-        "6A87C46F-1DD2-11B2-99A6-080020736631" => "Solaris swap",
-        "6A8B642B-1DD2-11B2-99A6-080020736631" => "Solaris backup",
-        "6A8EF2E9-1DD2-11B2-99A6-080020736631" => "Solaris /var",
-        "6A90BA39-1DD2-11B2-99A6-080020736631" => "Solaris /home",
-        "6A9283A5-1DD2-11B2-99A6-080020736631" => "Solaris alternate sector",
-        "6A945A3B-1DD2-11B2-99A6-080020736631" => "Solaris reserved 1",
-        "6A9630D1-1DD2-11B2-99A6-080020736631" => "Solaris reserved 2",
-        "6A980767-1DD2-11B2-99A6-080020736631" => "Solaris reserved 3",
-        "6A96237F-1DD2-11B2-99A6-080020736631" => "Solaris reserved 4",
-        "6A8D2AC7-1DD2-11B2-99A6-080020736631" => "Solaris reserved 5",
-        "49F48D32-B10E-11DC-B99B-0019D1879648" => "NetBSD swap",
-        "49F48D5A-B10E-11DC-B99B-0019D1879648" => "NetBSD FFS",
-        "49F48D82-B10E-11DC-B99B-0019D1879648" => "NetBSD LFS",
-        "2DB519C4-B10E-11DC-B99B-0019D1879648" => "NetBSD concatenated",
-        "2DB519EC-B10E-11DC-B99B-0019D1879648" => "NetBSD encrypted",
-        "49F48DAA-B10E-11DC-B99B-0019D1879648" => "NetBSD RAID",
-        "FE3A2A5D-4F32-41A7-B725-ACCC3285A309" => "ChromeOS kernel",
-        "3CB8E202-3B7E-47DD-8A3C-7FF2A13CFCEC" => "ChromeOS root fs",
-        "2E0A753D-9E48-43B0-8337-B15192CB1B5E" => "ChromeOS reserved",
-        "85D5E45A-237C-11E1-B4B3-E89A8F7FC3A7" => "MidnightBSD data",
-        "85D5E45E-237C-11E1-B4B3-E89A8F7FC3A7" => "MidnightBSD boot",
-        "85D5E45B-237C-11E1-B4B3-E89A8F7FC3A7" => "MidnightBSD swap",
-        "0394EF8B-237E-11E1-B4B3-E89A8F7FC3A7" => "MidnightBSD UFS",
-        "85D5E45D-237C-11E1-B4B3-E89A8F7FC3A7" => "MidnightBSD ZFS",
-        "85D5E45C-237C-11E1-B4B3-E89A8F7FC3A7" => "MidnightBSD Vinum",
-        "45B0969E-9B03-4F30-B4C6-B4B80CEFF106" => "Ceph Journal",
-        "45B0969E-9B03-4F30-B4C6-5EC00CEFF106" => "Ceph Encrypted Journal",
-        "4FBD7E29-9D25-41B8-AFD0-062C0CEFF05D" => "Ceph OSD",
-        "4FBD7E29-9D25-41B8-AFD0-5EC00CEFF05D" => "Ceph crypt OSD",
-        "89C57F98-2FE5-4DC0-89C1-F3AD0CEFF2BE" => "Ceph disk in creation",
-        "89C57F98-2FE5-4DC0-89C1-5EC00CEFF2BE" => "Ceph crypt disk in creation",
-        "AA31E02A-400F-11DB-9590-000C2911D1B8" => "VMware VMFS",
-        "9D275380-40AD-11DB-BF97-000C2911D1B8" => "VMware Diagnostic",
-        "381CFCCC-7288-11E0-92EE-000C2911D0B2" => "VMware Virtual SAN",
-        "77719A0C-A4A0-11E3-A47E-000C29745A24" => "VMware Virsto",
-        "9198EFFC-31C0-11DB-8F78-000C2911D1B8" => "VMware Reserved",
-        "824CC7A0-36A8-11E3-890A-952519AD3F61" => "OpenBSD data",
-        "CEF5A9AD-73BC-4601-89F3-CDEEEEE321A1" => "QNX6 file system",
-        "C91818F9-8025-47AF-89D2-F030D7000C2C" => "Plan 9 partition",
-        "5B193300-FC78-40CD-8002-E86C45580B47" => "HiFive Unleashed FSBL",
-        "2E54B353-1271-4842-806F-E436D6AF6985" => "HiFive Unleashed BBL",
-        _ => "Unknown Partition Type",
-    }
-    .into()
+#[cfg(test)]
+pub(crate) fn write_test_gpt(name: &str, sectors: usize) -> std::path::PathBuf {
+    let path = crate::bytestream::blank_test_image(name, sectors);
+    let entry = GptPartitionTableEntry::new(
+        crate::partition_types::LINUX_FILESYSTEM_GUID,
+        Guid::new_random().unwrap(),
+        2048,
+        4095,
+        0,
+        "data",
+    );
+    write_new_gpt(
+        &path,
+        Guid::new_random().unwrap(),
+        None,
+        None,
+        DEFAULT_NUMBER_PARTITION_ENTRIES,
+        &[(0, entry)],
+    )
+    .unwrap();
+    path
+}
+
+#[test]
+fn test_gpt_backup_fallback() {
+    let path = write_test_gpt("gpt_fallback", 8192);
+    let validated = parse_validated_gpt_header(&path).unwrap();
+    assert_eq!(validated.copy, GptHeaderCopy::Primary);
+    assert!(validated.primary_problem.is_none());
+
+    // Damage the primary partition entry array, the backup is found through `backup_lba`
+    write_sectors_to_file(&path, 2, &[0xFF; 16]).unwrap();
+    let validated = parse_validated_gpt_header(&path).unwrap();
+    assert_eq!(validated.copy, GptHeaderCopy::Backup);
+    assert_eq!(validated.header.current_lba, 8191);
+    assert_eq!(
+        validated.primary_problem,
+        Some(GptHeaderProblem::EntryArrayCrc32Mismatch)
+    );
+    assert_eq!(parse_gpt(&path).unwrap()[0].1.name(), "data");
+
+    // Damage the primary header, the backup is taken from the last LBA
+    write_sectors_to_file(&path, 1, b"EFI PART\x00\x00\x01\x00\x5d").unwrap();
+    let validated = parse_validated_gpt_header(&path).unwrap();
+    assert_eq!(validated.copy, GptHeaderCopy::Backup);
+    assert_eq!(
+        validated.primary_problem,
+        Some(GptHeaderProblem::HeaderCrc32Mismatch)
+    );
+
+    write_sectors_to_file(&path, 1, b"NOT GPT!").unwrap();
+    let validated = parse_validated_gpt_header(&path).unwrap();
+    assert_eq!(
+        validated.primary_problem,
+        Some(GptHeaderProblem::InvalidSignature)
+    );
+    write_sectors_to_file(&path, 1, &[0xFF, 0xFE, 0x80, 0xC3, 0x28, 0xA0, 0xA1, 0xE2]).unwrap();
+    let validated = parse_validated_gpt_header(&path).unwrap();
+    assert_eq!(validated.copy, GptHeaderCopy::Backup);
+    assert_eq!(
+        validated.primary_problem,
+        Some(GptHeaderProblem::InvalidSignature)
+    );
+
+    // Without an intact copy there is nothing to fall back to
+    write_sectors_to_file(&path, 8191, b"NOT GPT!").unwrap();
+    assert!(parse_validated_gpt_header(&path).is_err());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_parse_gpt_entry_geometry() {
+    let path = crate::bytestream::blank_test_image("gpt_geometry", 8192);
+    let entry = |starting_lba: u64, name: &str| {
+        GptPartitionTableEntry::new(
+            crate::partition_types::LINUX_FILESYSTEM_GUID,
+            Guid::new_random().unwrap(),
+            starting_lba,
+            starting_lba + 1023,
+            0,
+            name,
+        )
+    };
+    // 256 byte entries with a gap in the first slot and between the partitions
+    let mut header = GptHeader::new(Guid::new_random().unwrap(), 8191, 34, 8158, 64);
+    header.size_single_partition_entry = 256;
+    let entry_array = build_entry_array(
+        &[(1, entry(2048, "one")), (5, entry(4096, "five"))],
+        64,
+        256,
+    );
+    write_gpt(&path, &header, &entry_array).unwrap();
+
+    let partitions = parse_gpt(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(
+        partitions
+            .iter()
+            .map(|(index, entry)| (*index, entry.name()))
+            .collect::<Vec<_>>(),
+        vec![(1, "one".to_string()), (5, "five".to_string())]
+    );
+}
+
+#[test]
+fn test_verify_gpt() {
+    let path = write_test_gpt("gpt_verify", 8192);
+    assert!(verify_gpt(&path).unwrap().issues.is_empty());
+
+    // A grown image keeps its backup at the old end of the disk
+    let file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+    file.set_len(16384 * SECTOR_SIZE as u64).unwrap();
+    let verification = verify_gpt(&path).unwrap();
+    assert_eq!(verification.backup_lba, 8191);
+    assert_eq!(verification.last_lba, 16383);
+    assert_eq!(verification.issues.len(), 1);
+    assert!(verification.issues[0].contains("larger disk"));
+
+    // Rename the first partition in the backup entry array only
+    let backup_entry_array = verification.backup.header.starting_lba_of_partition_entries;
+    let mut sector = ByteStream::new(&path, SECTOR_SIZE, backup_entry_array)
+        .unwrap()
+        .read_raw(SECTOR_SIZE)
+        .unwrap();
+    sector[56] = b'D';
+    write_sectors_to_file(&path, backup_entry_array, &sector).unwrap();
+    let issues = verify_gpt(&path).unwrap().issues;
+    std::fs::remove_file(&path).unwrap();
+    assert!(issues.contains(
+        &"Backup GPT at LBA 8191 is invalid: partition entry array CRC32 mismatch".into()
+    ));
+    assert!(issues.contains(&"Partition entries differ between the copies: 1".into()));
+}
+
+#[test]
+fn test_verify_gpt_non_ascii_headers() {
+    let garbage = [0xFF, 0xFE, 0x80, 0xC3, 0x28, 0xA0, 0xA1, 0xE2];
+    let path = write_test_gpt("gpt_verify_non_ascii", 8192);
+    write_sectors_to_file(&path, 8191, &garbage).unwrap();
+    let issues = verify_gpt(&path).unwrap().issues;
+    assert!(
+        issues.contains(&"Backup GPT at LBA 8191 is invalid: signature is not `EFI PART`".into())
+    );
+
+    write_sectors_to_file(&path, 1, &garbage).unwrap();
+    let issues = verify_gpt(&path).unwrap().issues;
+    std::fs::remove_file(&path).unwrap();
+    assert!(issues.contains(&"Primary GPT is invalid: signature is not `EFI PART`".into()));
+}
+
+#[test]
+fn test_verify_gpt_garbage_backup() {
+    let path = write_test_gpt("gpt_verify_garbage", 8192);
+    // The backup's partition entry LBA points past the end of any disk
+    let mut sector = ByteStream::new(&path, SECTOR_SIZE, 8191)
+        .unwrap()
+        .read_raw(SECTOR_SIZE)
+        .unwrap();
+    sector[72..80].copy_from_slice(&[0xFF; 8]);
+    write_sectors_to_file(&path, 8191, &sector).unwrap();
+    let verification = verify_gpt(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(verification.primary.problem().is_none());
+    assert!(verification.issues.contains(
+        &"Backup partition entry array at LBA 18446744073709551615 overlaps the backup header"
+            .into()
+    ));
+}
+
+#[test]
+fn test_describe_attributes() {
+    use crate::partition_types::{EFI_SYSTEM_PARTITION_GUID, LINUX_FILESYSTEM_GUID};
+    let entry = |partition_type: Guid, attributes: u64| {
+        GptPartitionTableEntry::new(
+            partition_type,
+            Guid::new([0; 16]),
+            2048,
+            4095,
+            attributes,
+            "",
+        )
+        .describe_attributes()
+    };
+    assert_eq!(
+        entry(EFI_SYSTEM_PARTITION_GUID, 0b101 | 1 << 63),
+        vec!["Platform required", "Legacy BIOS bootable", "No auto-mount"]
+    );
+    assert_eq!(
+        entry(MICROSOFT_BASIC_DATA_GUID, 1 << 60 | 1 << 61),
+        vec!["Read-only", "Shadow copy"]
+    );
+    assert_eq!(
+        entry(CHROMEOS_KERNEL_GUID, 0xF << 48 | 1 << 52 | 1 << 56),
+        vec!["Priority 15", "Tries remaining 1", "Successful boot"]
+    );
+    assert_eq!(
+        entry(
+            "4F68BCE3-E8CD-4DB1-96E7-FBCAF984B709".parse().unwrap(),
+            1 << 59
+        ),
+        vec!["Grow file system"]
+    );
+    assert_eq!(
+        entry(LINUX_FILESYSTEM_GUID, 1 << 3 | 1 << 60),
+        vec!["Reserved bit 3", "Type specific bit 60"]
+    );
 }
@@ -0,0 +1,248 @@
+use crate::bytestream::{ByteStream, Readable};
+use std::{fmt::Display, io, str::FromStr};
+
+// https://www.ietf.org/rfc/rfc4122.txt
+// 4.1.2.  Layout and Byte Order
+// Fields are declared in textual order so the derived ordering matches the ordering of the GUID strings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Guid {
+    // The low field of the timestamp
+    time_low: u32,
+    // The middle field of the timestamp
+    time_mid: u16,
+    // The high field of the timestamp multiplexed with the version number
+    time_high_and_version: u16,
+    // The high field of the clock sequence multiplexed with the variant
+    clock_seq_high_and_reserved: u8,
+    // The low field of the clock sequence
+    clock_seq_low: u8,
+    // The spatially unique node identifier
+    node_identifier: [u8; 6],
+}
+
+/// The layout a GUID follows, stored in the high bits of `clock_seq_high_and_reserved`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GuidVariant {
+    // 0xxx, backwards compatible with the Apollo Network Computing System
+    Ncs,
+    // 10xx
+    Rfc4122,
+    // 110x, backwards compatible with Microsoft COM GUIDs
+    Microsoft,
+    // 111x
+    Reserved,
+}
+
+impl Display for GuidVariant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Ncs => "NCS",
+            Self::Rfc4122 => "RFC 4122",
+            Self::Microsoft => "Microsoft",
+            Self::Reserved => "Reserved",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Guid {
+    /// Reads the on-disk, mixed endian representation of a GUID.
+    pub const fn new(bytes: [u8; 16]) -> Self {
+        // The first three dash-delimited fields of the GUID are stored little endian, and the last two fields are not
+        let time_low = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        let time_mid = u16::from_le_bytes([bytes[4], bytes[5]]);
+        let time_high_and_version = u16::from_le_bytes([bytes[6], bytes[7]]);
+        let clock_seq_high_and_reserved = bytes[8];
+        let clock_seq_low = bytes[9];
+        let node_identifier = [
+            bytes[10], bytes[11], bytes[12], bytes[13], bytes[14], bytes[15],
+        ];
+
+        Self {
+            time_low,
+            time_mid,
+            time_high_and_version,
+            clock_seq_high_and_reserved,
+            clock_seq_low,
+            node_identifier,
+        }
+    }
+
+    /// Builds a GUID from the five dash-delimited fields of its textual form, for constants.
+    pub const fn from_fields(
+        time_low: u32,
+        time_mid: u16,
+        time_high_and_version: u16,
+        clock_seq: u16,
+        node: u64,
+    ) -> Self {
+        let node = node.to_be_bytes();
+        Self {
+            time_low,
+            time_mid,
+            time_high_and_version,
+            clock_seq_high_and_reserved: (clock_seq >> 8) as u8,
+            clock_seq_low: clock_seq as u8,
+            node_identifier: [node[2], node[3], node[4], node[5], node[6], node[7]],
+        }
+    }
+
+    /// Generates a random (version 4) GUID.
+    pub fn new_random() -> io::Result<Self> {
+        let mut bytes = [0u8; 16];
+        getrandom::getrandom(&mut bytes).map_err(|error| io::Error::other(error.to_string()))?;
+        // `time_high_and_version` is stored little endian so the version nibble lives in byte 7
+        bytes[7] = (bytes[7] & 0x0F) | 0x40;
+        // RFC 4122 variant (10xx)
+        bytes[8] = (bytes[8] & 0x3F) | 0x80;
+        Ok(Self::new(bytes))
+    }
+
+    /// The on-disk, mixed endian representation of the GUID.
+    pub fn to_bytes(self) -> [u8; 16] {
+        let mut bytes = [0u8; 16];
+        bytes[0..4].copy_from_slice(&self.time_low.to_le_bytes());
+        bytes[4..6].copy_from_slice(&self.time_mid.to_le_bytes());
+        bytes[6..8].copy_from_slice(&self.time_high_and_version.to_le_bytes());
+        bytes[8] = self.clock_seq_high_and_reserved;
+        bytes[9] = self.clock_seq_low;
+        bytes[10..].copy_from_slice(&self.node_identifier);
+        bytes
+    }
+
+    /// The all zero GUID marks unused GPT entries.
+    pub fn is_zero(&self) -> bool {
+        self.to_bytes() == [0u8; 16]
+    }
+
+    /// The version of an RFC 4122 GUID, 1 for time based and 4 for random GUIDs.
+    pub fn version(&self) -> u8 {
+        (self.time_high_and_version >> 12) as u8
+    }
+
+    pub fn variant(&self) -> GuidVariant {
+        match self.clock_seq_high_and_reserved {
+            byte if byte & 0x80 == 0 => GuidVariant::Ncs,
+            byte if byte & 0xC0 == 0x80 => GuidVariant::Rfc4122,
+            byte if byte & 0xE0 == 0xC0 => GuidVariant::Microsoft,
+            _ => GuidVariant::Reserved,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GuidParseError(String);
+
+impl Display for GuidParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid GUID `{}`", self.0)
+    }
+}
+
+impl FromStr for Guid {
+    type Err = GuidParseError;
+
+    // Parses the textual `XXXXXXXX-XXXX-XXXX-XXXX-XXXXXXXXXXXX` form, case insensitive.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || GuidParseError(s.into());
+        let fields: Vec<&str> = s.trim().split('-').collect();
+        let lengths = [8, 4, 4, 4, 12];
+        if fields.len() != lengths.len()
+            || fields.iter().zip(lengths).any(|(field, length)| {
+                field.len() != length || !field.chars().all(|c| c.is_ascii_hexdigit())
+            })
+        {
+            return Err(error());
+        }
+
+        let time_low = u32::from_str_radix(fields[0], 16).map_err(|_| error())?;
+        let time_mid = u16::from_str_radix(fields[1], 16).map_err(|_| error())?;
+        let time_high_and_version = u16::from_str_radix(fields[2], 16).map_err(|_| error())?;
+        let clock_seq = u16::from_str_radix(fields[3], 16).map_err(|_| error())?;
+        let node = u64::from_str_radix(fields[4], 16).map_err(|_| error())?;
+        Ok(Self::from_fields(
+            time_low,
+            time_mid,
+            time_high_and_version,
+            clock_seq,
+            node,
+        ))
+    }
+}
+
+impl Display for Guid {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let clock_seq = u16::from_be_bytes([self.clock_seq_high_and_reserved, self.clock_seq_low]);
+
+        // Ignore first 2 bytes.
+        let mut tmp_buffer = [0u8; 8];
+        tmp_buffer[2..].copy_from_slice(&self.node_identifier);
+        let node = u64::from_be_bytes(tmp_buffer);
+
+        write!(
+            f,
+            "{:08X}-{:04X}-{:04X}-{:04X}-{:012X}",
+            self.time_low, self.time_mid, self.time_high_and_version, clock_seq, node
+        )
+    }
+}
+
+impl Readable for Guid {
+    fn read(reader: &mut ByteStream) -> io::Result<Self>
+    where
+        Self: Sized,
+    {
+        let x = reader.read_byte_array::<16>()?;
+        Ok(Guid::new(x))
+    }
+}
+
+#[test]
+fn test_guid() {
+    // https://developer.apple.com/library/archive/technotes/tn2166/_index.html#//apple_ref/doc/uid/DTS10003927-CH1-SECTION2
+    let bytes: [u8; 16] = [
+        0x28, 0x73, 0x2a, 0xc1, 0x1f, 0xf8, 0xd2, 0x11, 0xba, 0x4b, 0x00, 0xa0, 0xc9, 0x3e, 0xc9,
+        0x3b,
+    ];
+    let guid = Guid::new(bytes);
+    assert_eq!(guid.to_string(), "C12A7328-F81F-11D2-BA4B-00A0C93EC93B");
+    assert_eq!(guid.to_bytes(), bytes);
+    assert_eq!(
+        "c12a7328-f81f-11d2-ba4b-00a0c93ec93b"
+            .parse::<Guid>()
+            .unwrap(),
+        guid
+    );
+    assert_eq!(
+        Guid::from_fields(0xC12A7328, 0xF81F, 0x11D2, 0xBA4B, 0x00A0C93EC93B),
+        guid
+    );
+    assert!("C12A7328-F81F-11D2-BA4B".parse::<Guid>().is_err());
+    assert_eq!(guid.version(), 1);
+    assert_eq!(guid.variant(), GuidVariant::Rfc4122);
+}
+
+#[test]
+fn test_guid_random_and_ordering() {
+    let guid = Guid::new_random().unwrap();
+    assert_eq!(guid.version(), 4);
+    assert_eq!(guid.variant(), GuidVariant::Rfc4122);
+    assert!(!guid.is_zero() && Guid::new([0; 16]).is_zero());
+
+    let mut guids: Vec<Guid> = [
+        "EBD0A0A2-B9E5-4433-87C0-68B6B72699C7",
+        "0FC63DAF-8483-4772-8E79-3D69D8477DE4",
+        "0FC63DAF-8483-4772-8E79-3D69D8477DE5",
+        "C12A7328-F81F-11D2-BA4B-00A0C93EC93B",
+    ]
+    .iter()
+    .map(|guid| guid.parse().unwrap())
+    .collect();
+    let mut strings: Vec<String> = guids.iter().map(Guid::to_string).collect();
+    guids.sort();
+    strings.sort();
+    assert_eq!(
+        guids.iter().map(Guid::to_string).collect::<Vec<_>>(),
+        strings
+    );
+}
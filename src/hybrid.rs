@@ -0,0 +1,283 @@
+use crate::{
+    bytestream::image_size_in_sectors,
+    convert::{gpt_to_mbr_type, mbr_to_gpt_type},
+    gpt::{parse_gpt, GptPartitionTableEntry, LEGACY_BIOS_BOOTABLE_ATTRIBUTE},
+    mbr::{parse_mbr, write_mbr, MbrPartition, GPT_PARTITION_TYPE},
+    partition_types::{gpt_type_name, mbr_type_name},
+};
+use prettytable::{row, Table};
+use std::{io, path::Path};
+
+// Hybrid MBRs keep one slot for the 0xEE partition protecting the GPT
+const MAXIMUM_HYBRID_PARTITIONS: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MbrKind {
+    /// Only a 0xEE partition covering the disk
+    Protective,
+    /// A 0xEE partition along with partitions mirroring (or contradicting) GPT partitions
+    Hybrid,
+}
+
+/// An MBR partition, a GPT partition or an MBR partition with the GPT partition covering exactly the same sectors.
+pub type MbrGptRow = (
+    Option<MbrPartition>,
+    Option<(usize, GptPartitionTableEntry)>,
+);
+
+/// The MBR and the GPT of a disk next to each other.
+#[derive(Debug)]
+pub struct MbrGptComparison {
+    pub kind: MbrKind,
+    pub rows: Vec<MbrGptRow>,
+    pub warnings: Vec<String>,
+}
+
+fn overlaps(first: (u64, u64), second: (u64, u64)) -> bool {
+    first.0 <= second.1 && second.0 <= first.1
+}
+
+/// Pairs the partitions of the MBR of a GPT disk with the GPT partitions and reports where a hybrid MBR
+/// contradicts the GPT or a protective MBR doesn't cover the disk.
+pub fn compare_mbr_with_gpt(path: &Path) -> io::Result<MbrGptComparison> {
+    let mbr = parse_mbr(path)?;
+    if !mbr.is_gpt() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "The MBR has no 0xEE partition protecting a GPT",
+        ));
+    }
+    let kind = if mbr.is_hybrid() {
+        MbrKind::Hybrid
+    } else {
+        MbrKind::Protective
+    };
+    let mbr_partitions = mbr.partitions();
+    let mut gpt_entries = parse_gpt(path)?;
+    let mut warnings = Vec::new();
+
+    let last_lba = image_size_in_sectors(path)?.saturating_sub(1);
+    for protective in mbr_partitions
+        .iter()
+        .filter(|partition| partition.partition_type == GPT_PARTITION_TYPE)
+    {
+        if protective.starting_lba != 1 {
+            warnings.push(format!(
+                "The 0xEE partition starts at LBA {} instead of the GPT header at LBA 1",
+                protective.starting_lba
+            ));
+        }
+        if kind == MbrKind::Protective && protective.ending_lba() != last_lba.min(u32::MAX as u64) {
+            warnings.push(format!(
+                "The 0xEE partition ends at LBA {} but the disk ends at LBA {}",
+                protective.ending_lba(),
+                last_lba
+            ));
+        }
+        for partition in mbr_partitions.iter().filter(|partition| {
+            partition.partition_type != GPT_PARTITION_TYPE
+                && !partition.is_extended()
+                && overlaps(
+                    (partition.starting_lba, partition.ending_lba()),
+                    (protective.starting_lba, protective.ending_lba()),
+                )
+        }) {
+            warnings.push(format!(
+                "MBR partition {} overlaps the 0xEE partition",
+                partition.number
+            ));
+        }
+    }
+
+    let mut rows = Vec::new();
+    for partition in mbr_partitions.into_iter() {
+        let range = (partition.starting_lba, partition.ending_lba());
+        if partition.partition_type == GPT_PARTITION_TYPE || partition.is_extended() {
+            rows.push((Some(partition), None));
+            continue;
+        }
+        let position = gpt_entries
+            .iter()
+            .position(|(_, entry)| (entry.starting_lba(), entry.ending_lba()) == range);
+        let gpt_entry = position.map(|position| gpt_entries.remove(position));
+        match &gpt_entry {
+            Some((index, entry)) => {
+                if let Some(expected) = mbr_to_gpt_type(partition.partition_type) {
+                    if expected != *entry.partition_type() {
+                        warnings.push(format!(
+                            "MBR partition {} has type {:#04x} ({}) but GPT partition {} is a {} partition",
+                            partition.number,
+                            partition.partition_type,
+                            mbr_type_name(partition.partition_type),
+                            index + 1,
+                            gpt_type_name(entry.partition_type())
+                        ));
+                    }
+                }
+            }
+            None => match gpt_entries.iter().find(|(_, entry)| {
+                overlaps(range, (entry.starting_lba(), entry.ending_lba()))
+            }) {
+                Some((index, entry)) => warnings.push(format!(
+                    "MBR partition {} (LBA {}-{}) overlaps GPT partition {} (LBA {}-{}) but their bounds differ",
+                    partition.number,
+                    range.0,
+                    range.1,
+                    index + 1,
+                    entry.starting_lba(),
+                    entry.ending_lba()
+                )),
+                None => warnings.push(format!(
+                    "MBR partition {} (LBA {}-{}) does not correspond to any GPT partition",
+                    partition.number, range.0, range.1
+                )),
+            },
+        }
+        rows.push((Some(partition), gpt_entry));
+    }
+    rows.extend(gpt_entries.into_iter().map(|entry| (None, Some(entry))));
+
+    Ok(MbrGptComparison {
+        kind,
+        rows,
+        warnings,
+    })
+}
+
+pub fn display_mbr_gpt_comparison(comparison: &MbrGptComparison) {
+    match comparison.kind {
+        MbrKind::Protective => println!("Protective MBR"),
+        MbrKind::Hybrid => println!("Hybrid MBR"),
+    }
+    let mut table = Table::new();
+    table.add_row(row![
+        "MBR Number",
+        "MBR Type",
+        "MBR Sectors",
+        "GPT Number",
+        "GPT Type",
+        "GPT Sectors"
+    ]);
+    for (mbr_partition, gpt_entry) in comparison.rows.iter() {
+        let (mbr_number, mbr_type, mbr_sectors) = match mbr_partition {
+            Some(partition) => (
+                partition.number.to_string(),
+                format!(
+                    "{:#04x} :: {}",
+                    partition.partition_type,
+                    mbr_type_name(partition.partition_type)
+                ),
+                format!("{} - {}", partition.starting_lba, partition.ending_lba()),
+            ),
+            None => Default::default(),
+        };
+        let (gpt_number, gpt_type, gpt_sectors) = match gpt_entry {
+            Some((index, entry)) => (
+                (index + 1).to_string(),
+                gpt_type_name(entry.partition_type()),
+                format!("{} - {}", entry.starting_lba(), entry.ending_lba()),
+            ),
+            None => Default::default(),
+        };
+        table.add_row(row![
+            mbr_number,
+            mbr_type,
+            mbr_sectors,
+            gpt_number,
+            gpt_type,
+            gpt_sectors
+        ]);
+    }
+    table.printstd();
+    for warning in comparison.warnings.iter() {
+        println!("Warning: {}", warning);
+    }
+}
+
+/// Writes a hybrid MBR mirroring up to three GPT partitions, numbered the GPT way, in MBR slots 2-4. The 0xEE
+/// partition in slot 1 covers the GPT structures and everything else before the first mirrored partition.
+/// Partitions with the Legacy BIOS bootable attribute are marked bootable, the boot code is kept.
+pub fn create_hybrid_mbr(path: &Path, numbers: &[u32]) -> io::Result<Vec<MbrPartition>> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+    if numbers.is_empty() || numbers.len() > MAXIMUM_HYBRID_PARTITIONS {
+        return Err(invalid(format!(
+            "A hybrid MBR mirrors between 1 and {} GPT partitions",
+            MAXIMUM_HYBRID_PARTITIONS
+        )));
+    }
+    let entries = parse_gpt(path)?;
+    let mut mirrored = Vec::new();
+    for number in numbers {
+        let (_, entry) = entries
+            .iter()
+            .find(|(index, _)| *index + 1 == *number as usize)
+            .ok_or_else(|| invalid(format!("GPT partition {} does not exist", number)))?;
+        if entry.ending_lba() > u32::MAX as u64 {
+            return Err(invalid(format!(
+                "GPT partition {} ends beyond the 2 TiB an MBR can address",
+                number
+            )));
+        }
+        mirrored.push((*number, entry));
+    }
+    mirrored.sort_by_key(|(_, entry)| entry.starting_lba());
+
+    let mut partitions = vec![MbrPartition {
+        number: 1,
+        bootable: false,
+        partition_type: GPT_PARTITION_TYPE,
+        starting_lba: 1,
+        num_sectors: mirrored[0].1.starting_lba() - 1,
+    }];
+    for (slot, (number, entry)) in mirrored.iter().enumerate() {
+        let partition_type = gpt_to_mbr_type(entry.partition_type()).ok_or_else(|| {
+            invalid(format!(
+                "GPT partition {} has type {} without an MBR equivalent",
+                number,
+                gpt_type_name(entry.partition_type())
+            ))
+        })?;
+        partitions.push(MbrPartition {
+            number: slot as u32 + 2,
+            bootable: entry.attributes() & LEGACY_BIOS_BOOTABLE_ATTRIBUTE != 0,
+            partition_type,
+            starting_lba: entry.starting_lba(),
+            num_sectors: entry.ending_lba() - entry.starting_lba() + 1,
+        });
+    }
+    write_mbr(path, None, &partitions)?;
+    Ok(partitions)
+}
+
+#[test]
+fn test_hybrid_mbr() {
+    let path = crate::gpt::write_test_gpt("hybrid", 8192);
+    let comparison = compare_mbr_with_gpt(&path).unwrap();
+    assert_eq!(comparison.kind, MbrKind::Protective);
+    assert!(comparison.warnings.is_empty(), "{:?}", comparison.warnings);
+
+    create_hybrid_mbr(&path, &[1]).unwrap();
+    let comparison = compare_mbr_with_gpt(&path).unwrap();
+    assert_eq!(comparison.kind, MbrKind::Hybrid);
+    assert!(comparison.warnings.is_empty(), "{:?}", comparison.warnings);
+    assert!(comparison
+        .rows
+        .iter()
+        .any(|(mbr, gpt)| mbr.is_some() && gpt.is_some()));
+
+    // A hybrid entry of the wrong type and one that doesn't match the GPT partition
+    let mut partitions = create_hybrid_mbr(&path, &[1]).unwrap();
+    partitions[1].partition_type = 0x82;
+    partitions.push(MbrPartition {
+        number: 3,
+        bootable: false,
+        partition_type: 0x83,
+        starting_lba: 4000,
+        num_sectors: 200,
+    });
+    write_mbr(&path, None, &partitions).unwrap();
+    let comparison = compare_mbr_with_gpt(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(comparison.warnings.len(), 2, "{:?}", comparison.warnings);
+    assert!(create_hybrid_mbr(Path::new("/nonexistent"), &[1, 2, 3, 4]).is_err());
+}
@@ -0,0 +1,360 @@
+use crate::{
+    apm::ApmPartitionMap,
+    bytestream::SECTOR_SIZE,
+    gpt::{GptHeader, GptPartitionTableEntry},
+    mbr::MbrPartitionTableEntryNode,
+    partition_types::{gpt_type_name, mbr_type_name},
+};
+use prettytable::{row, Table};
+
+const ONE_MIB_SECTORS: u64 = 1024 * 1024 / SECTOR_SIZE as u64;
+// Advanced Format drives use 4096 byte physical sectors
+const FOUR_KIB_SECTORS: u64 = 4096 / SECTOR_SIZE as u64;
+// Cylinder size of the 255 head, 63 sectors per track geometry used by DOS partitioning tools
+const CYLINDER_SECTORS: u64 = 255 * 63;
+const TRACK_SECTORS: u64 = 63;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LayoutEntryKind {
+    Data,
+    // Partitioning metadata such as the APM partition map, allocated but its alignment doesn't matter
+    Metadata,
+    // MBR extended partitions, logical partitions can still be placed in their free space
+    Container,
+}
+
+/// A partition as far as the layout analysis is concerned, independent of the partitioning scheme.
+#[derive(Debug, Clone)]
+pub struct LayoutEntry {
+    pub name: String,
+    pub starting_lba: u64,
+    pub ending_lba: u64,
+    pub kind: LayoutEntryKind,
+}
+
+impl LayoutEntry {
+    fn new(name: String, starting_lba: u64, ending_lba: u64, kind: LayoutEntryKind) -> Self {
+        Self {
+            name,
+            starting_lba,
+            ending_lba,
+            kind,
+        }
+    }
+}
+
+/// The area of the disk partitions can be placed in along with the partitions in it.
+#[derive(Debug)]
+pub struct Layout {
+    pub first_usable_lba: u64,
+    pub last_usable_lba: u64,
+    pub entries: Vec<LayoutEntry>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Alignment {
+    pub one_mib: bool,
+    pub four_kib: bool,
+    pub cylinder: bool,
+}
+
+impl Alignment {
+    pub fn of(lba: u64) -> Self {
+        Self {
+            one_mib: lba.is_multiple_of(ONE_MIB_SECTORS),
+            four_kib: lba.is_multiple_of(FOUR_KIB_SECTORS),
+            // DOS tools start the first partition on the second track and the rest on cylinder boundaries
+            cylinder: lba.is_multiple_of(CYLINDER_SECTORS) || lba == TRACK_SECTORS,
+        }
+    }
+}
+
+impl Layout {
+    pub fn from_mbr(root: &MbrPartitionTableEntryNode, total_sectors: u64) -> Self {
+        let entries = root
+            .partitions()
+            .iter()
+            .map(|partition| {
+                let name = format!(
+                    "{} ({})",
+                    partition.number,
+                    mbr_type_name(partition.partition_type)
+                );
+                let kind = if partition.is_extended() {
+                    LayoutEntryKind::Container
+                } else {
+                    LayoutEntryKind::Data
+                };
+                LayoutEntry::new(name, partition.starting_lba, partition.ending_lba(), kind)
+            })
+            .collect();
+        Self {
+            first_usable_lba: 1,
+            last_usable_lba: total_sectors.saturating_sub(1),
+            entries,
+        }
+    }
+
+    pub fn from_gpt(header: &GptHeader, partitions: &[(usize, GptPartitionTableEntry)]) -> Self {
+        let entries = partitions
+            .iter()
+            .map(|(index, partition)| {
+                let name = match partition.name() {
+                    name if name.is_empty() => {
+                        format!(
+                            "{} ({})",
+                            index + 1,
+                            gpt_type_name(partition.partition_type())
+                        )
+                    }
+                    name => format!(
+                        "{} {} ({})",
+                        index + 1,
+                        name,
+                        gpt_type_name(partition.partition_type())
+                    ),
+                };
+                LayoutEntry::new(
+                    name,
+                    partition.starting_lba(),
+                    partition.ending_lba(),
+                    LayoutEntryKind::Data,
+                )
+            })
+            .collect();
+        Self {
+            first_usable_lba: header.first_usable_lba(),
+            last_usable_lba: header.last_usable_lba(),
+            entries,
+        }
+    }
+
+    pub fn from_apm(map: &ApmPartitionMap, total_sectors: u64) -> Self {
+        // APM block numbers are converted to 512 byte sectors
+        let sectors_per_block = map.sectors_per_block();
+        let entries = map
+            .partitions
+            .iter()
+            .filter(|partition| !partition.is_free())
+            .map(|partition| {
+                let name = format!("{} ({})", partition.name(), partition.partition_type());
+                let starting_lba = partition.starting_lba() as u64 * sectors_per_block;
+                let size = partition.size_in_sectors() as u64 * sectors_per_block;
+                let ending_lba = starting_lba + size.max(1) - 1;
+                let kind = if partition.is_partition_map() {
+                    LayoutEntryKind::Metadata
+                } else {
+                    LayoutEntryKind::Data
+                };
+                LayoutEntry::new(name, starting_lba, ending_lba, kind)
+            })
+            .collect();
+        Self {
+            first_usable_lba: sectors_per_block,
+            last_usable_lba: total_sectors.saturating_sub(1),
+            entries,
+        }
+    }
+
+    /// Regions of the usable area not covered by any partition, as inclusive LBA ranges.
+    pub fn unallocated_regions(&self) -> Vec<(u64, u64)> {
+        let mut allocated: Vec<(u64, u64)> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.kind != LayoutEntryKind::Container)
+            .map(|entry| (entry.starting_lba, entry.ending_lba))
+            .collect();
+        allocated.sort();
+
+        let mut regions = Vec::new();
+        let mut next_free = self.first_usable_lba;
+        for (starting_lba, ending_lba) in allocated {
+            if starting_lba > next_free && next_free <= self.last_usable_lba {
+                regions.push((next_free, (starting_lba - 1).min(self.last_usable_lba)));
+            }
+            next_free = next_free.max(ending_lba.saturating_add(1));
+        }
+        if next_free <= self.last_usable_lba {
+            regions.push((next_free, self.last_usable_lba));
+        }
+
+        // Split regions at the bounds of extended partitions so each one is either inside or outside of them
+        let mut bounds: Vec<u64> = self
+            .containers()
+            .flat_map(|container| {
+                [
+                    container.starting_lba,
+                    container.ending_lba.saturating_add(1),
+                ]
+            })
+            .collect();
+        bounds.sort();
+        regions
+            .into_iter()
+            .flat_map(|(starting_lba, ending_lba)| {
+                let mut split = Vec::new();
+                let mut start = starting_lba;
+                for &bound in bounds.iter() {
+                    if bound > start && bound <= ending_lba {
+                        split.push((start, bound - 1));
+                        start = bound;
+                    }
+                }
+                split.push((start, ending_lba));
+                split
+            })
+            .collect()
+    }
+
+    fn containers(&self) -> impl Iterator<Item = &LayoutEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.kind == LayoutEntryKind::Container)
+    }
+
+    /// The extended partition a LBA lies in, if any.
+    pub fn container_of(&self, lba: u64) -> Option<&LayoutEntry> {
+        self.containers()
+            .find(|container| container.starting_lba <= lba && lba <= container.ending_lba)
+    }
+
+    /// Partitions whose start is not on a 4 KiB boundary, every write to them straddles physical sectors
+    /// on Advanced Format drives.
+    pub fn misaligned_entries(&self) -> Vec<&LayoutEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| {
+                entry.kind == LayoutEntryKind::Data && !Alignment::of(entry.starting_lba).four_kib
+            })
+            .collect()
+    }
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value {
+        "Yes"
+    } else {
+        "No"
+    }
+}
+
+fn size_in_mb(sectors: u64) -> f64 {
+    ((sectors * SECTOR_SIZE as u64) as f64 / 1048576_f64).round()
+}
+
+pub fn display_layout(layout: &Layout) {
+    println!(
+        "Usable sectors: {} - {}\n",
+        layout.first_usable_lba, layout.last_usable_lba
+    );
+
+    let mut table = Table::new();
+    table.add_row(row![
+        "Partition",
+        "Starting LBA",
+        "Ending LBA",
+        "1 MiB Aligned",
+        "4 KiB Aligned",
+        "Cylinder Aligned"
+    ]);
+    for entry in layout.entries.iter() {
+        let alignment = Alignment::of(entry.starting_lba);
+        table.add_row(row![
+            entry.name,
+            entry.starting_lba,
+            entry.ending_lba,
+            yes_no(alignment.one_mib),
+            yes_no(alignment.four_kib),
+            yes_no(alignment.cylinder)
+        ]);
+    }
+    table.printstd();
+
+    let regions = layout.unallocated_regions();
+    if regions.is_empty() {
+        println!("\nNo unallocated space");
+    } else {
+        println!("\nUnallocated space:");
+        let mut table = Table::new();
+        table.add_row(row![
+            "Starting LBA",
+            "Ending LBA",
+            "Total Sectors",
+            "Size (MB)",
+            "Inside"
+        ]);
+        for (starting_lba, ending_lba) in regions {
+            let total_sectors = ending_lba - starting_lba + 1;
+            let inside = layout
+                .container_of(starting_lba)
+                .map(|container| container.name.as_str())
+                .unwrap_or("");
+            table.add_row(row![
+                starting_lba,
+                ending_lba,
+                total_sectors,
+                size_in_mb(total_sectors),
+                inside
+            ]);
+        }
+        table.printstd();
+    }
+
+    for entry in layout.misaligned_entries() {
+        println!(
+            "Warning: partition {} starts at LBA {} which is not 4 KiB aligned, performance will suffer on Advanced Format drives",
+            entry.name, entry.starting_lba
+        );
+    }
+}
+
+#[test]
+fn test_alignment() {
+    assert_eq!(
+        Alignment::of(2048),
+        Alignment {
+            one_mib: true,
+            four_kib: true,
+            cylinder: false
+        }
+    );
+    assert_eq!(
+        Alignment::of(63),
+        Alignment {
+            one_mib: false,
+            four_kib: false,
+            cylinder: true
+        }
+    );
+    assert!(Alignment::of(16065 * 8).cylinder);
+    assert!(Alignment::of(40).four_kib);
+}
+
+#[test]
+fn test_unallocated_regions() {
+    let layout = Layout {
+        first_usable_lba: 34,
+        last_usable_lba: 20446,
+        entries: vec![
+            LayoutEntry::new("1".into(), 2048, 4095, LayoutEntryKind::Data),
+            LayoutEntry::new("2".into(), 6144, 8191, LayoutEntryKind::Data),
+            LayoutEntry::new("3".into(), 8000, 10239, LayoutEntryKind::Data),
+            LayoutEntry::new("4".into(), 10241, 20446, LayoutEntryKind::Data),
+            LayoutEntry::new("5".into(), 5000, 9999, LayoutEntryKind::Container),
+        ],
+    };
+    assert_eq!(
+        layout.unallocated_regions(),
+        vec![(34, 2047), (4096, 4999), (5000, 6143), (10240, 10240)]
+    );
+    assert!(layout.container_of(4999).is_none());
+    assert_eq!(layout.container_of(5000).unwrap().name, "5");
+    assert_eq!(
+        layout
+            .misaligned_entries()
+            .iter()
+            .map(|entry| entry.name.as_str())
+            .collect::<Vec<_>>(),
+        vec!["4"]
+    );
+}
@@ -1,22 +1,59 @@
 use crate::{
-    apm::{display_apm_partitions, parse_apm},
+    apm::{display_apm_partitions, extract_apm_drivers, parse_apm},
+    apm_edit::{create_apm, edit_apm, ApmEdit, DEFAULT_APM_MAP_ENTRIES},
     mbr::parse_mbr,
 };
-use apm::is_apm_disk;
-use clap::{Parser, Subcommand};
-use gpt::{display_gpt, parse_gpt};
-use mbr::display_mbr;
+use bytestream::{image_size_in_sectors, SECTOR_SIZE};
+use checksum::{checksum_sectors, ChecksumAlgorithm};
+use clap::{Parser, Subcommand, ValueEnum};
+use convert::{
+    convert_gpt_to_mbr, convert_mbr_to_gpt, display_mbr_conversion, plan_gpt_to_mbr,
+    plan_mbr_to_gpt,
+};
+use create::{create_gpt, create_image, parse_size_in_sectors, PartitionSpec};
+use edit::{display_guid_changes, edit_gpt, regenerate_guids, GptEdit, GuidRegeneration};
+use gpt::{
+    display_gpt, display_gpt_header, display_gpt_verification, parse_gpt,
+    parse_validated_gpt_header, verify_gpt, GptHeaderCopy, DEFAULT_NUMBER_PARTITION_ENTRIES,
+};
+use guid::Guid;
+use hybrid::{compare_mbr_with_gpt, create_hybrid_mbr, display_mbr_gpt_comparison, MbrKind};
+use layout::{display_layout, Layout};
+use mbr::{display_mbr, display_mbr_tree};
 use mft::{display_mft, mft_to_csv, parse_pbr, timestomp_mft};
-use std::path::{Path, PathBuf};
+use partition_types::{
+    display_partition_types, init_registry, registry, MICROSOFT_BASIC_DATA_GUID,
+};
+use probe::{display_disk_probe, probe_schemes, PartitionScheme};
+use repair::{apply_repair_plan, display_repair_plan, plan_gpt_repair, save_overwritten_sectors};
+use scan::{display_gpt_candidates, scan_for_gpt};
+use sfdisk::{apply_sfdisk_script, dump_partition_table, parse_gpt_attributes, SfdiskScript};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
 
 #[cfg(test)]
 use std::io::Read;
 
 mod apm;
+mod apm_edit;
 mod bytestream;
+mod checksum;
+mod convert;
+mod create;
+mod edit;
 mod gpt;
+mod guid;
+mod hybrid;
+mod layout;
 mod mbr;
 mod mft;
+mod partition_types;
+mod probe;
+mod repair;
+mod scan;
+mod sfdisk;
 
 #[derive(Debug, Parser)]
 struct Arguments {
@@ -25,14 +62,23 @@ struct Arguments {
     show_chs: bool,
     #[arg(long)]
     extract_mft: bool,
+    /// Show the MBR and its chain of extended boot records as a tree
+    #[arg(long)]
+    tree: bool,
+    /// Show unallocated space and the alignment of each partition
+    #[arg(long)]
+    layout: bool,
     #[arg(long)]
     dump_mft: Option<String>,
+    /// CSV file of additional partition types (scheme,code,name,os_family,aliases)
+    #[arg(long)]
+    type_registry: Option<String>,
     #[command(subcommand)]
-    timestomp: Option<Timestomp>,
+    command: Option<Command>,
 }
 
 #[derive(Debug, Subcommand)]
-pub enum Timestomp {
+pub enum Command {
     /// Timestomp `file_name` with the `timestamp`
     Timestomp {
         /// Name of the file entry in the MFT
@@ -40,74 +86,670 @@ pub enum Timestomp {
         /// Unix epoch timestamp to timestomp with
         timestamp: u64,
     },
+    /// Print the MBR or GPT as an `sfdisk --dump` script
+    Dump,
+    /// Write the partition table described by an sfdisk script to the image
+    Restore {
+        /// Path to the sfdisk script
+        script_path: String,
+    },
+    /// List the known partition types with their names and aliases
+    Types,
+    /// Compare the primary and backup GPT and report every inconsistency
+    Verify,
+    /// Search every LBA for GPT headers, including orphaned copies and GPTs nested inside partitions
+    Scan,
+    /// List the APM, MBR and GPT present on the image and where their layouts conflict
+    Probe,
+    /// Rebuild both copies of the GPT from an intact copy and move the backup to the end of the image
+    Repair {
+        /// Copy to rebuild from, defaults to whichever copy is intact
+        #[arg(long, value_enum)]
+        from: Option<GptCopyArgument>,
+        /// Only show what would change
+        #[arg(long)]
+        dry_run: bool,
+        /// File to save the overwritten sectors to
+        #[arg(long)]
+        save: Option<String>,
+    },
+    /// Checksum the whole image or a single partition
+    Checksum {
+        /// GPT or MBR partition number, defaults to the whole image
+        #[arg(long)]
+        partition: Option<u32>,
+        #[arg(long, value_enum, default_value_t = ChecksumArgument::Crc32)]
+        algorithm: ChecksumArgument,
+    },
+    /// Write a protective MBR and a new GPT to the image
+    CreateGpt {
+        /// Create the image file with this size, in sectors or with a K, M, G or T suffix
+        #[arg(long)]
+        size: Option<String>,
+        /// Number of partition entries
+        #[arg(long, default_value_t = DEFAULT_NUMBER_PARTITION_ENTRIES)]
+        table_length: u32,
+        /// Partition to add as `size=SIZE,align=SIZE,type=TYPE,name=NAME`, every field is optional and
+        /// a partition without a size fills the rest of the disk. Partitions are placed in the given order
+        #[arg(long = "partition")]
+        partitions: Vec<String>,
+    },
+    /// Add a partition to the GPT
+    Add {
+        /// Partition number, defaults to the first unused entry
+        #[arg(long)]
+        number: Option<u32>,
+        /// Starting LBA, defaults to the first aligned LBA of the first free region large enough
+        #[arg(long)]
+        start: Option<u64>,
+        /// Size in sectors or with a K, M, G or T suffix, defaults to the rest of the free region
+        #[arg(long)]
+        size: Option<String>,
+        /// Alignment of the starting LBA when no start is given, in sectors or with a suffix
+        #[arg(long)]
+        align: Option<String>,
+        /// Partition type GUID, name or alias
+        #[arg(long = "type", default_value = "linux")]
+        partition_type: String,
+        #[arg(long, default_value = "")]
+        name: String,
+    },
+    /// Delete a GPT partition
+    Delete { number: u32 },
+    /// Change the size of a GPT partition, growing it into all free space following it without a size
+    Resize {
+        number: u32,
+        /// New size in sectors or with a K, M, G or T suffix
+        #[arg(long)]
+        size: Option<String>,
+    },
+    /// Change the name of a GPT partition
+    Rename { number: u32, name: String },
+    /// Change the type of a GPT partition
+    Retype {
+        number: u32,
+        /// Partition type GUID, name or alias
+        partition_type: String,
+    },
+    /// Replace the attributes of a GPT partition
+    SetAttributes {
+        number: u32,
+        /// Bit numbers or sfdisk attribute names separated by commas or spaces, or a hexadecimal value
+        attributes: String,
+    },
+    /// Assign random GUIDs to the disk and its partitions so clones of an image don't collide
+    RegenerateGuids {
+        /// Only regenerate the unique GUIDs of these partitions, defaults to every partition
+        #[arg(long = "partition", conflicts_with = "keep_partition_guids")]
+        partitions: Vec<u32>,
+        /// Keep the unique GUIDs of all partitions
+        #[arg(long)]
+        keep_partition_guids: bool,
+        /// Keep the disk GUID
+        #[arg(long)]
+        keep_disk_guid: bool,
+        /// Also replace the MBR disk signature
+        #[arg(long)]
+        mbr_signature: bool,
+    },
+    /// Write a hybrid MBR mirroring up to three GPT partitions
+    CreateHybrid {
+        /// GPT partition numbers
+        #[arg(required = true)]
+        numbers: Vec<u32>,
+    },
+    /// Write each driver of the Apple partition map's driver descriptor map to a file
+    ExtractApmDrivers {
+        /// Directory to write `driver_<number>.bin` files to
+        output_directory: String,
+    },
+    /// Write a driver descriptor map and an empty Apple partition map to the image
+    CreateApm {
+        /// Create the image file with this size, in sectors or with a K, M, G or T suffix
+        #[arg(long)]
+        size: Option<String>,
+        /// Bytes per block, 2048 for CDs
+        #[arg(long, default_value_t = SECTOR_SIZE as u16)]
+        block_size: u16,
+        /// Number of blocks reserved for the partition map
+        #[arg(long, default_value_t = DEFAULT_APM_MAP_ENTRIES)]
+        map_entries: u32,
+    },
+    /// Add a partition to the Apple partition map
+    ApmAdd {
+        /// Starting block, defaults to the first free region large enough
+        #[arg(long)]
+        start: Option<u64>,
+        /// Size in sectors or with a K, M, G or T suffix, defaults to the rest of the free region
+        #[arg(long)]
+        size: Option<String>,
+        /// Partition type, such as Apple_HFS or Apple_Driver43
+        #[arg(long = "type", default_value = "Apple_HFS")]
+        partition_type: String,
+        #[arg(long, default_value = "")]
+        name: String,
+    },
+    /// Delete a partition of the Apple partition map
+    ApmDelete { number: u32 },
+    /// Change the size of a partition of the Apple partition map, growing it into all free space following
+    /// it without a size
+    ApmResize {
+        number: u32,
+        /// New size in sectors or with a K, M, G or T suffix
+        #[arg(long)]
+        size: Option<String>,
+    },
+    /// Convert the partition table to another scheme without moving any partition
+    Convert {
+        #[arg(value_enum)]
+        to: PartitionSchemeArgument,
+        /// Only show the converted partition table
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum GptCopyArgument {
+    Primary,
+    Backup,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ChecksumArgument {
+    Crc32,
+    Crc32c,
+    /// Apple partition map boot code and driver checksum
+    Apple,
+    HfsJournal,
+    Fletcher64,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum PartitionSchemeArgument {
+    Gpt,
+    Mbr,
 }
 
 fn main() {
     let args = Arguments::parse();
     let path = Path::new(&args.image_path);
-    if is_apm_disk(&args.image_path).unwrap() {
-        let partitions = parse_apm(&args.image_path).unwrap();
-        display_apm_partitions(partitions);
-    } else {
-        // FIXME: This could all be done nicer if the signature is checked first.
-        let mbr = parse_mbr(path);
-        let mbr_node = match mbr {
-            Ok(root_node) => root_node,
-            Err(error) => panic!("Error parsing MBR: {}", error),
+    if let Err(error) = init_registry(args.type_registry.as_deref().map(Path::new)) {
+        panic!("Error loading partition type registry: {}", error);
+    }
+    match &args.command {
+        Some(Command::Types) => {
+            display_partition_types();
+            return;
+        }
+        Some(Command::Verify) => {
+            match verify_gpt(path) {
+                Ok(verification) => display_gpt_verification(&verification),
+                Err(error) => panic!("Error verifying GPT: {}", error),
+            }
+            return;
+        }
+        Some(Command::Probe) => {
+            match probe_schemes(path) {
+                Ok(probe) => display_disk_probe(&probe),
+                Err(error) => panic!("Error probing partitioning schemes: {}", error),
+            }
+            return;
+        }
+        Some(Command::Scan) => {
+            match scan_for_gpt(path) {
+                Ok(candidates) => display_gpt_candidates(&candidates),
+                Err(error) => panic!("Error scanning for GPT headers: {}", error),
+            }
+            return;
+        }
+        Some(Command::Repair {
+            from,
+            dry_run,
+            save,
+        }) => {
+            let source = from.map(|from| match from {
+                GptCopyArgument::Primary => GptHeaderCopy::Primary,
+                GptCopyArgument::Backup => GptHeaderCopy::Backup,
+            });
+            let plan = match plan_gpt_repair(path, source) {
+                Ok(plan) => plan,
+                Err(error) => panic!("Error repairing GPT: {}", error),
+            };
+            display_repair_plan(path, &plan).unwrap();
+            if let Some(save) = save {
+                save_overwritten_sectors(path, &plan, Path::new(save)).unwrap();
+                println!("\nSaved the overwritten sectors to {}", save);
+            }
+            if !dry_run {
+                if let Err(error) = apply_repair_plan(path, &plan) {
+                    panic!("Error writing GPT: {}", error);
+                }
+                println!("\nGPT repaired");
+            }
+            return;
+        }
+        Some(Command::Checksum {
+            partition,
+            algorithm,
+        }) => {
+            let algorithm = match algorithm {
+                ChecksumArgument::Crc32 => ChecksumAlgorithm::Crc32,
+                ChecksumArgument::Crc32c => ChecksumAlgorithm::Crc32c,
+                ChecksumArgument::Apple => ChecksumAlgorithm::Apple,
+                ChecksumArgument::HfsJournal => ChecksumAlgorithm::HfsJournal,
+                ChecksumArgument::Fletcher64 => ChecksumAlgorithm::Fletcher64,
+            };
+            let range = match partition {
+                Some(number) => partition_range(path, *number),
+                None => image_size_in_sectors(path).map(|sectors| (0, sectors.saturating_sub(1))),
+            };
+            let result = range.and_then(|(starting_lba, ending_lba)| {
+                checksum_sectors(path, starting_lba, ending_lba - starting_lba + 1, algorithm)
+                    .map(|checksum| (starting_lba, ending_lba, checksum))
+            });
+            match result {
+                Ok((starting_lba, ending_lba, checksum)) => {
+                    println!("{}  LBA {}-{}", checksum, starting_lba, ending_lba)
+                }
+                Err(error) => panic!("Error calculating checksum: {}", error),
+            }
+            return;
+        }
+        Some(Command::CreateGpt {
+            size,
+            table_length,
+            partitions,
+        }) => {
+            let partitions = match partitions
+                .iter()
+                .map(|partition| partition.parse::<PartitionSpec>())
+                .collect::<Result<Vec<_>, _>>()
+            {
+                Ok(partitions) => partitions,
+                Err(error) => panic!("Error parsing partition: {}", error),
+            };
+            if let Some(size) = size {
+                if let Err(error) =
+                    parse_size_in_sectors(size).and_then(|size| create_image(path, size))
+                {
+                    panic!("Error creating image: {}", error);
+                }
+            }
+            if let Err(error) = create_gpt(path, *table_length, &partitions) {
+                panic!("Error creating GPT: {}", error);
+            }
+            display_gpt_header(&parse_validated_gpt_header(path).unwrap());
+            display_gpt(parse_gpt(path).unwrap());
+            return;
+        }
+        Some(
+            command @ (Command::Add { .. }
+            | Command::Delete { .. }
+            | Command::Resize { .. }
+            | Command::Rename { .. }
+            | Command::Retype { .. }
+            | Command::SetAttributes { .. }),
+        ) => {
+            let edit = match gpt_edit(command) {
+                Ok(edit) => edit,
+                Err(error) => panic!("Error parsing arguments: {}", error),
+            };
+            if let Err(error) = edit_gpt(path, &edit) {
+                panic!("Error editing GPT: {}", error);
+            }
+            display_gpt(parse_gpt(path).unwrap());
+            return;
+        }
+        Some(Command::Convert { to, dry_run }) => {
+            let result = match to {
+                PartitionSchemeArgument::Gpt => plan_mbr_to_gpt(path).and_then(|conversion| {
+                    for note in conversion.notes.iter() {
+                        println!("{}", note);
+                    }
+                    display_gpt(conversion.entries.clone());
+                    if *dry_run {
+                        Ok(())
+                    } else {
+                        convert_mbr_to_gpt(path, &conversion)
+                    }
+                }),
+                PartitionSchemeArgument::Mbr => plan_gpt_to_mbr(path).and_then(|conversion| {
+                    for note in conversion.notes.iter() {
+                        println!("{}", note);
+                    }
+                    display_mbr_conversion(&conversion);
+                    if *dry_run {
+                        Ok(())
+                    } else {
+                        convert_gpt_to_mbr(path, &conversion)
+                    }
+                }),
+            };
+            if let Err(error) = result {
+                panic!("Error converting partition table: {}", error);
+            }
+            return;
+        }
+        Some(Command::RegenerateGuids {
+            partitions,
+            keep_partition_guids,
+            keep_disk_guid,
+            mbr_signature,
+        }) => {
+            let regeneration = GuidRegeneration {
+                disk_guid: !keep_disk_guid,
+                partitions: match (keep_partition_guids, partitions.is_empty()) {
+                    (true, _) => Some(Vec::new()),
+                    (false, true) => None,
+                    (false, false) => Some(partitions.clone()),
+                },
+                mbr_disk_signature: *mbr_signature,
+            };
+            match regenerate_guids(path, &regeneration) {
+                Ok(changes) => display_guid_changes(&changes),
+                Err(error) => panic!("Error regenerating GUIDs: {}", error),
+            }
+            return;
+        }
+        Some(Command::CreateHybrid { numbers }) => {
+            if let Err(error) = create_hybrid_mbr(path, numbers) {
+                panic!("Error creating hybrid MBR: {}", error);
+            }
+            display_mbr_gpt_comparison(&compare_mbr_with_gpt(path).unwrap());
+            return;
+        }
+        Some(Command::CreateApm {
+            size,
+            block_size,
+            map_entries,
+        }) => {
+            if let Some(size) = size {
+                if let Err(error) =
+                    parse_size_in_sectors(size).and_then(|size| create_image(path, size))
+                {
+                    panic!("Error creating image: {}", error);
+                }
+            }
+            if let Err(error) = create_apm(path, *block_size, *map_entries) {
+                panic!("Error creating Apple partition map: {}", error);
+            }
+            display_apm_partitions(&parse_apm(path).unwrap());
+            return;
+        }
+        Some(
+            command @ (Command::ApmAdd { .. }
+            | Command::ApmDelete { .. }
+            | Command::ApmResize { .. }),
+        ) => {
+            let edit = match apm_edit(command) {
+                Ok(edit) => edit,
+                Err(error) => panic!("Error parsing arguments: {}", error),
+            };
+            if let Err(error) = edit_apm(path, &edit) {
+                panic!("Error editing Apple partition map: {}", error);
+            }
+            display_apm_partitions(&parse_apm(path).unwrap());
+            return;
+        }
+        Some(Command::ExtractApmDrivers { output_directory }) => {
+            let files = parse_apm(path)
+                .and_then(|map| extract_apm_drivers(path, &map, Path::new(output_directory)));
+            match files {
+                Ok(files) => {
+                    for file in files {
+                        println!("Wrote {}", file.display());
+                    }
+                }
+                Err(error) => panic!("Error extracting APM drivers: {}", error),
+            }
+            return;
+        }
+        Some(Command::Dump) => {
+            match dump_partition_table(path, &args.image_path) {
+                Ok(dump) => print!("{}", dump),
+                Err(error) => panic!("Error dumping partition table: {}", error),
+            }
+            return;
+        }
+        Some(Command::Restore { script_path }) => {
+            let script = match fs::read_to_string(script_path)
+                .and_then(|script| script.parse::<SfdiskScript>())
+            {
+                Ok(script) => script,
+                Err(error) => panic!("Error reading sfdisk script: {}", error),
+            };
+            if let Err(error) = apply_sfdisk_script(path, &script) {
+                panic!("Error writing partition table: {}", error);
+            }
+            return;
+        }
+        _ => {}
+    }
+
+    let timestomp = match args.command {
+        Some(Command::Timestomp {
+            file_name,
+            timestamp,
+        }) => Some((file_name, timestamp)),
+        _ => None,
+    };
+    let probe = match probe_schemes(path) {
+        Ok(probe) => probe,
+        Err(error) => panic!("Error probing partitioning schemes: {}", error),
+    };
+    let showing_partitions = !(args.extract_mft || timestomp.is_some() || args.dump_mft.is_some());
+    if showing_partitions && probe.independent_schemes() > 1 {
+        display_disk_probe(&probe);
+        println!();
+    }
+    let mbr_or_gpt =
+        probe.is_present(PartitionScheme::Mbr) || probe.is_present(PartitionScheme::Gpt);
+    if probe.is_present(PartitionScheme::Apm) && (showing_partitions || !mbr_or_gpt) {
+        let map = parse_apm(path).unwrap();
+        if args.layout {
+            let total_sectors = image_size_in_sectors(path).unwrap();
+            display_layout(&Layout::from_apm(&map, total_sectors));
+        } else {
+            display_apm_partitions(&map);
+        }
+        if !mbr_or_gpt {
+            return;
+        }
+        println!();
+    }
+
+    // FIXME: This could all be done nicer if the signature is checked first.
+    let mbr = parse_mbr(path);
+    let mbr_node = match mbr {
+        Ok(root_node) => root_node,
+        Err(error) => panic!("Error parsing MBR: {}", error),
+    };
+
+    if mbr_node.is_gpt() {
+        let partition_table = match parse_gpt(path) {
+            Ok(partition_table) => partition_table,
+            Err(error) => panic!("Error parsing GPT: {}", error),
         };
 
-        if mbr_node.is_gpt() {
-            let partition_table = match parse_gpt(path) {
-                Ok(partition_table) => partition_table,
-                Err(error) => panic!("Error parsing GPT: {}", error),
+        if args.extract_mft || timestomp.is_some() || args.dump_mft.is_some() {
+            let ntfs_partition = partition_table
+                .into_iter()
+                .find(|(_, entry)| *entry.partition_type() == MICROSOFT_BASIC_DATA_GUID);
+            let mft_records = match ntfs_partition {
+                Some((_, partition)) => parse_pbr(path, partition.starting_lba()).unwrap(),
+                None => panic!("Could not find a `Microsoft basic data` partition."),
             };
-
-            if args.extract_mft || args.timestomp.is_some() || args.dump_mft.is_some() {
-                let ntfs_partition = partition_table.into_iter().find(|entry| {
-                    entry.get_partition_type_guid() == "EBD0A0A2-B9E5-4433-87C0-68B6B72699C7"
-                });
-                let mft_records = match ntfs_partition {
-                    Some(partition) => parse_pbr(path, partition.starting_lba()).unwrap(),
-                    None => panic!("Could not find a `Microsoft basic data` partition."),
-                };
-                if args.dump_mft.is_some() {
-                    mft_to_csv(mft_records, &args.dump_mft.unwrap()).unwrap();
-                } else if args.extract_mft {
-                    display_mft(mft_records);
-                } else {
-                    timestomp_mft(
-                        &PathBuf::from(args.image_path),
-                        mft_records,
-                        args.timestomp.unwrap(),
-                    );
-                }
+            if args.dump_mft.is_some() {
+                mft_to_csv(mft_records, &args.dump_mft.unwrap()).unwrap();
+            } else if args.extract_mft {
+                display_mft(mft_records);
             } else {
-                display_gpt(partition_table);
+                let (file_name, timestamp) = timestomp.unwrap();
+                timestomp_mft(
+                    &PathBuf::from(args.image_path),
+                    mft_records,
+                    &file_name,
+                    timestamp,
+                );
             }
         } else {
-            if args.extract_mft || args.timestomp.is_some() || args.dump_mft.is_some() {
-                let first_child = mbr_node.children.unwrap();
-                let first_partition = first_child.get(0).unwrap();
-                let mft_records = parse_pbr(path, first_partition.starting_lba() as u64).unwrap();
-
-                if args.dump_mft.is_some() {
-                    mft_to_csv(mft_records, &args.dump_mft.unwrap()).unwrap();
-                } else if args.extract_mft {
-                    display_mft(mft_records);
-                } else {
-                    timestomp_mft(
-                        &PathBuf::from(args.image_path),
-                        mft_records,
-                        args.timestomp.unwrap(),
-                    );
+            let validated = match parse_validated_gpt_header(path) {
+                Ok(validated) => validated,
+                Err(error) => panic!("Error parsing GPT: {}", error),
+            };
+            if args.layout {
+                println!("{}", validated.describe());
+                display_layout(&Layout::from_gpt(&validated.header, &partition_table));
+            } else {
+                display_gpt_header(&validated);
+                display_gpt(partition_table);
+                let comparison = match compare_mbr_with_gpt(path) {
+                    Ok(comparison) => comparison,
+                    Err(error) => panic!("Error comparing MBR and GPT: {}", error),
+                };
+                if comparison.kind == MbrKind::Hybrid || !comparison.warnings.is_empty() {
+                    println!();
+                    display_mbr_gpt_comparison(&comparison);
                 }
+            }
+        }
+    } else {
+        if args.extract_mft || timestomp.is_some() || args.dump_mft.is_some() {
+            let first_child = mbr_node.children.unwrap();
+            let first_partition = first_child.get(0).unwrap();
+            let mft_records = parse_pbr(path, first_partition.starting_lba() as u64).unwrap();
+
+            if args.dump_mft.is_some() {
+                mft_to_csv(mft_records, &args.dump_mft.unwrap()).unwrap();
+            } else if args.extract_mft {
+                display_mft(mft_records);
             } else {
-                display_mbr(mbr_node, args.show_chs);
+                let (file_name, timestamp) = timestomp.unwrap();
+                timestomp_mft(
+                    &PathBuf::from(args.image_path),
+                    mft_records,
+                    &file_name,
+                    timestamp,
+                );
             }
+        } else if args.layout {
+            let total_sectors = image_size_in_sectors(path).unwrap();
+            display_layout(&Layout::from_mbr(&mbr_node, total_sectors));
+        } else if args.tree {
+            display_mbr_tree(&mbr_node);
+        } else {
+            display_mbr(mbr_node, args.show_chs);
         }
     }
 }
 
+// The LBA range of a partition, numbered the GPT way on GPT disks and the MBR way otherwise
+fn partition_range(path: &Path, number: u32) -> io::Result<(u64, u64)> {
+    let not_found = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Partition {} does not exist", number),
+        )
+    };
+    let mbr = parse_mbr(path)?;
+    if mbr.is_gpt() {
+        parse_gpt(path)?
+            .into_iter()
+            .find(|(index, _)| *index + 1 == number as usize)
+            .map(|(_, entry)| (entry.starting_lba(), entry.ending_lba()))
+            .ok_or_else(not_found)
+    } else {
+        mbr.partitions()
+            .into_iter()
+            .find(|partition| partition.number == number && !partition.is_extended())
+            .map(|partition| (partition.starting_lba, partition.ending_lba()))
+            .ok_or_else(not_found)
+    }
+}
+
+fn parse_gpt_type(partition_type: &str) -> io::Result<Guid> {
+    registry().find_gpt(partition_type).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Unknown GPT partition type `{}`", partition_type),
+        )
+    })
+}
+
+fn gpt_edit(command: &Command) -> io::Result<GptEdit> {
+    let parse_size = |size: &Option<String>| size.as_deref().map(parse_size_in_sectors).transpose();
+    Ok(match command {
+        Command::Add {
+            number,
+            start,
+            size,
+            align,
+            partition_type,
+            name,
+        } => GptEdit::Add {
+            number: *number,
+            starting_lba: *start,
+            size: parse_size(size)?,
+            alignment: parse_size(align)?,
+            partition_type: parse_gpt_type(partition_type)?,
+            name: name.clone(),
+        },
+        Command::Delete { number } => GptEdit::Delete { number: *number },
+        Command::Resize { number, size } => GptEdit::Resize {
+            number: *number,
+            size: parse_size(size)?,
+        },
+        Command::Rename { number, name } => GptEdit::Rename {
+            number: *number,
+            name: name.clone(),
+        },
+        Command::Retype {
+            number,
+            partition_type,
+        } => GptEdit::Retype {
+            number: *number,
+            partition_type: parse_gpt_type(partition_type)?,
+        },
+        Command::SetAttributes { number, attributes } => GptEdit::SetAttributes {
+            number: *number,
+            attributes: match attributes.strip_prefix("0x") {
+                Some(hex) => u64::from_str_radix(hex, 16).map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("Invalid GPT attributes `{}`", attributes),
+                    )
+                })?,
+                None => parse_gpt_attributes(attributes)?,
+            },
+        },
+        _ => unreachable!("Not a GPT edit"),
+    })
+}
+
+fn apm_edit(command: &Command) -> io::Result<ApmEdit> {
+    let parse_size = |size: &Option<String>| size.as_deref().map(parse_size_in_sectors).transpose();
+    Ok(match command {
+        Command::ApmAdd {
+            start,
+            size,
+            partition_type,
+            name,
+        } => ApmEdit::Add {
+            starting_block: *start,
+            size: parse_size(size)?,
+            partition_type: partition_type.clone(),
+            name: name.clone(),
+        },
+        Command::ApmDelete { number } => ApmEdit::Delete { number: *number },
+        Command::ApmResize { number, size } => ApmEdit::Resize {
+            number: *number,
+            size: parse_size(size)?,
+        },
+        _ => unreachable!("Not an APM edit"),
+    })
+}
+
 #[test]
 pub fn test_open_drive() {
     use std::fs::OpenOptions;
@@ -1,17 +1,26 @@
-use crate::bytestream::{ByteStream, Readable, SECTOR_SIZE};
+use crate::{
+    bytestream::{write_sectors_to_file, ByteStream, Readable, Writable, SECTOR_SIZE},
+    partition_types::mbr_type_name,
+};
+use byteorder::{LittleEndian, WriteBytesExt};
 use prettytable::{row, Row, Table};
 use std::{
-    io::{self},
+    io::{self, Write},
     path::Path,
 };
 
 const BOOTSTRAPER_LENGTH: u64 = 446;
+const DISK_SIGNATURE_OFFSET: u64 = 440;
+// Geometry used to fill in CHS addresses, the same one fdisk and sfdisk assume
+const CHS_HEADS: u64 = 255;
+const CHS_SECTORS_PER_TRACK: u64 = 63;
+const CHS_MAX_CYLINDER: u64 = 1023;
 const CHS_SECTOR_BIT_SIZE: u8 = 6;
 const FIRST_TWO_BIT_MASK: u16 = 0b11000000;
 pub const BOOT_SIGNATURE: [u8; 2] = [0x55, 0xAA];
 pub const GPT_PARTITION_TYPE: u8 = 0xee;
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct MbrPartitionTableEntry {
     bootable: u8,
     starting_chs: [u8; 3],
@@ -37,7 +46,66 @@ impl Readable for MbrPartitionTableEntry {
     }
 }
 
+impl Writable for MbrPartitionTableEntry {
+    fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_u8(self.bootable)?;
+        writer.write_all(&self.starting_chs)?;
+        writer.write_u8(self.partition_type)?;
+        writer.write_all(&self.ending_chs)?;
+        writer.write_u32::<LittleEndian>(self.lba_start)?;
+        writer.write_u32::<LittleEndian>(self.num_sectors)
+    }
+}
+
+// https://en.wikipedia.org/wiki/Partition_type#List_of_partition_IDs
+// Every type whose partition holds a chain of EBRs, hidden and secured flavours included.
+const EXTENDED_PARTITION_TYPES: [u8; 10] = [
+    0x05, // Extended (CHS)
+    0x0F, // W95 Extended (LBA)
+    0x15, // Hidden extended (CHS)
+    0x1F, // Hidden W95 extended (LBA)
+    0x85, // Linux extended
+    0x91, // FreeDOS hidden extended (CHS)
+    0x9B, // FreeDOS hidden W95 extended (LBA)
+    0xC5, // DR-DOS secured extended (CHS)
+    0xCF, // DR-DOS secured extended (LBA)
+    0xD5, // Multiuser DOS secured extended
+];
+
+/// Whether `partition_type` is a container for a chain of EBRs.
+pub fn is_extended_partition_type(partition_type: u8) -> bool {
+    EXTENDED_PARTITION_TYPES.contains(&partition_type)
+}
+
+/// Encodes `lba` as a CHS address, addresses past the last cylinder are clamped to (1023, 254, 63).
+fn lba_to_chs(lba: u64) -> [u8; 3] {
+    let cylinder = lba / (CHS_HEADS * CHS_SECTORS_PER_TRACK);
+    if cylinder > CHS_MAX_CYLINDER {
+        return [0xFE, 0xFF, 0xFF];
+    }
+    let head = (lba / CHS_SECTORS_PER_TRACK) % CHS_HEADS;
+    let sector = (lba % CHS_SECTORS_PER_TRACK) + 1;
+    [
+        head as u8,
+        sector as u8 | ((cylinder >> 2) as u8 & FIRST_TWO_BIT_MASK as u8),
+        cylinder as u8,
+    ]
+}
+
 impl MbrPartitionTableEntry {
+    /// An entry whose CHS addresses are derived from `lba_start` and `num_sectors`.
+    pub fn new(bootable: bool, partition_type: u8, lba_start: u32, num_sectors: u32) -> Self {
+        let lba_end = (lba_start as u64 + num_sectors as u64).saturating_sub(1);
+        Self {
+            bootable: if bootable { 0x80 } else { 0x00 },
+            starting_chs: lba_to_chs(lba_start as u64),
+            partition_type,
+            ending_chs: lba_to_chs(lba_end),
+            lba_start,
+            num_sectors,
+        }
+    }
+
     fn is_empty(&self) -> bool {
         self.bootable == 0
             && self.starting_chs.iter().all(|byte| *byte == 0)
@@ -48,7 +116,7 @@ impl MbrPartitionTableEntry {
     }
 
     fn is_extended_partition(&self) -> bool {
-        self.partition_type == 0x05 || self.partition_type == 0x0F
+        is_extended_partition_type(self.partition_type)
     }
 
     fn starting_lba(&self) -> u32 {
@@ -59,6 +127,14 @@ impl MbrPartitionTableEntry {
         self.num_sectors
     }
 
+    fn type_description(&self) -> String {
+        format!(
+            "{:#04x} :: {}",
+            self.partition_type,
+            mbr_type_name(self.partition_type)
+        )
+    }
+
     fn table_row(&self, image_offset_sectors: u64, show_chs: bool) -> Row {
         let partition_table_starting_lba = image_offset_sectors + self.starting_lba() as u64;
         let size = self.num_sectors() as u64;
@@ -78,7 +154,7 @@ impl MbrPartitionTableEntry {
                 format!(
                     "{:#04x} :: {}",
                     self.partition_type,
-                    lookup_partition_type(self.partition_type)
+                    mbr_type_name(self.partition_type)
                 ),
             ]
         } else {
@@ -90,7 +166,7 @@ impl MbrPartitionTableEntry {
                 format!(
                     "{:#04x} :: {}",
                     self.partition_type,
-                    lookup_partition_type(self.partition_type)
+                    mbr_type_name(self.partition_type)
                 ),
             ]
         }
@@ -125,6 +201,41 @@ impl MbrPartitionTableEntry {
     }
 }
 
+/// A partition with its absolute LBA, numbered the way Linux numbers them:
+/// primary entries 1-4 by their slot and logical partitions from 5 in chain order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MbrPartition {
+    pub number: u32,
+    pub bootable: bool,
+    pub partition_type: u8,
+    pub starting_lba: u64,
+    pub num_sectors: u64,
+}
+
+impl MbrPartition {
+    fn from_entry(number: u32, entry: &MbrPartitionTableEntry, image_offset_sectors: u64) -> Self {
+        Self {
+            number,
+            bootable: entry.bootable == 0x80,
+            partition_type: entry.partition_type,
+            starting_lba: image_offset_sectors + entry.starting_lba() as u64,
+            num_sectors: entry.num_sectors() as u64,
+        }
+    }
+
+    pub fn is_extended(&self) -> bool {
+        is_extended_partition_type(self.partition_type)
+    }
+
+    pub fn is_logical(&self) -> bool {
+        self.number > 4
+    }
+
+    pub fn ending_lba(&self) -> u64 {
+        (self.starting_lba + self.num_sectors).saturating_sub(1)
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct MbrPartitionTableEntryNode {
     partition_table_entry: Option<MbrPartitionTableEntry>,
@@ -183,6 +294,15 @@ impl MbrPartitionTableEntryNode {
         }
     }
 
+    /// Whether the MBR protects a GPT and also describes partitions of its own, which makes it a hybrid MBR.
+    pub fn is_hybrid(&self) -> bool {
+        self.is_gpt()
+            && self
+                .partitions()
+                .iter()
+                .any(|partition| partition.partition_type != GPT_PARTITION_TYPE)
+    }
+
     pub fn starting_lba(&self) -> u32 {
         if let Some(entry) = &self.partition_table_entry {
             entry.starting_lba()
@@ -190,6 +310,40 @@ impl MbrPartitionTableEntryNode {
             0
         }
     }
+
+    /// Flattens the tree into the primary partitions followed by the logical partitions of the EBR chain.
+    pub fn partitions(&self) -> Vec<MbrPartition> {
+        let mut partitions = Vec::new();
+        let mut next_logical_number = 5;
+        for (index, child) in self.children.iter().flatten().enumerate() {
+            if let Some(entry) = &child.partition_table_entry {
+                partitions.push(MbrPartition::from_entry(
+                    index as u32 + 1,
+                    entry,
+                    child.image_offset_sectors,
+                ));
+                if entry.is_extended_partition() {
+                    child.collect_logical_partitions(&mut partitions, &mut next_logical_number);
+                }
+            }
+        }
+        partitions
+    }
+
+    fn collect_logical_partitions(&self, partitions: &mut Vec<MbrPartition>, number: &mut u32) {
+        for child in self.children.iter().flatten() {
+            if child.is_extended_partition() {
+                child.collect_logical_partitions(partitions, number);
+            } else if let Some(entry) = &child.partition_table_entry {
+                partitions.push(MbrPartition::from_entry(
+                    *number,
+                    entry,
+                    child.image_offset_sectors,
+                ));
+                *number += 1;
+            }
+        }
+    }
 }
 
 fn print_nodes(
@@ -249,9 +403,10 @@ fn parse_sector(
                 // If this is the first extended partition table entry in the MBR, parse the next EBR at `start_lba` and set
                 // `first_ebr_lba` to the start of the first EBR since all following EBR's starting LBA's are relative to the first EBR's LBA
                 parse_sector(&mut next_node, path, false, start_lba, start_lba)?;
-            } else {
+            } else if first_ebr_lba + start_lba > image_offset_sector {
                 // If this is not the first extended partition table entry, parse the next EBR at `first_ebr_lba` + the `start_lba`
                 // (relative to the first EBR's LBA) of this partition table entry. Leave the first EBR's LBA unchanged.
+                // Links that do not point forward are not followed since they would loop over the chain forever.
                 parse_sector(
                     &mut next_node,
                     path,
@@ -270,111 +425,310 @@ fn parse_sector(
     Ok(())
 }
 
-fn lookup_partition_type(partition_type: u8) -> String {
-    match partition_type {
-        0x0 => "Empty",
-        0x1 => "FAT12",
-        0x2 => "XENIX root",
-        0x3 => "XENIX usr",
-        0x4 => "FAT16 <32M",
-        0x5 => "Extended",
-        0x6 => "FAT16",
-        0x7 => "HPFS/NTFS/exFAT",
-        0x8 => "AIX",
-        0x9 => "AIX bootable",
-        0xa => "OS/2 Boot Manag",
-        0xb => "W95 FAT32",
-        0xc => "W95 FAT32 (LBA)",
-        0xe => "W95 FAT16 (LBA)",
-        0xf => "W95 Ext'd (LBA)",
-        0x10 => "OPUS",
-        0x11 => "Hidden FAT12",
-        0x12 => "Compaq diagnost",
-        0x14 => "Hidden FAT16 <3",
-        0x16 => "Hidden FAT16",
-        0x17 => "Hidden HPFS/NTF",
-        0x18 => "AST SmartSleep",
-        0x1b => "Hidden W95 FAT3",
-        0x1c => "Hidden W95 FAT3",
-        0x1e => "Hidden W95 FAT1",
-        0x24 => "NEC DOS",
-        0x27 => "Hidden NTFS Win",
-        0x39 => "Plan 9",
-        0x3c => "PartitionMagic",
-        0x40 => "Venix 80286",
-        0x41 => "PPC PReP Boot",
-        0x42 => "SFS",
-        0x4d => "QNX4.x",
-        0x4e => "QNX4.x 2nd part",
-        0x4f => "QNX4.x 3rd part",
-        0x50 => "OnTrack DM",
-        0x51 => "OnTrack DM6 Aux",
-        0x52 => "CP/M",
-        0x53 => "OnTrack DM6 Aux",
-        0x54 => "OnTrackDM6",
-        0x55 => "EZ-Drive",
-        0x56 => "Golden Bow",
-        0x5c => "Priam Edisk",
-        0x61 => "SpeedStor",
-        0x63 => "GNU HURD or Sys",
-        0x64 => "Novell Netware",
-        0x65 => "Novell Netware",
-        0x70 => "DiskSecure Mult",
-        0x75 => "PC/IX",
-        0x80 => "Old Minix",
-        0x81 => "Minix / old Lin",
-        0x82 => "Linux swap / So",
-        0x83 => "Linux",
-        0x84 => "OS/2 hidden or",
-        0x85 => "Linux extended",
-        0x86 => "NTFS volume set",
-        0x87 => "NTFS volume set",
-        0x88 => "Linux plaintext",
-        0x8e => "Linux LVM",
-        0x93 => "Amoeba",
-        0x94 => "Amoeba BBT",
-        0x9f => "BSD/OS",
-        0xa0 => "IBM Thinkpad hi",
-        0xa5 => "FreeBSD",
-        0xa6 => "OpenBSD",
-        0xa7 => "NeXTSTEP",
-        0xa8 => "Darwin UFS",
-        0xa9 => "NetBSD",
-        0xab => "Darwin boot",
-        0xaf => "HFS / HFS+",
-        0xb7 => "BSDI fs",
-        0xb8 => "BSDI swap",
-        0xbb => "Boot Wizard hid",
-        0xbc => "Acronis FAT32 L",
-        0xbe => "Solaris boot",
-        0xbf => "Solaris",
-        0xc1 => "DRDOS/sec (FAT-",
-        0xc4 => "DRDOS/sec (FAT-",
-        0xc6 => "DRDOS/sec (FAT-",
-        0xc7 => "Syrinx",
-        0xda => "Non-FS data",
-        0xdb => "CP/M / CTOS / .",
-        0xde => "Dell Utility",
-        0xdf => "BootIt",
-        0xe1 => "DOS access",
-        0xe3 => "DOS R/O",
-        0xe4 => "SpeedStor",
-        0xea => "Rufus alignment",
-        0xeb => "BeOS fs",
-        0xee => "GPT",
-        0xef => "EFI (FAT-12/16/",
-        0xf0 => "Linux/PA-RISC b",
-        0xf1 => "SpeedStor",
-        0xf4 => "SpeedStor",
-        0xf2 => "DOS secondary",
-        0xfb => "VMware VMFS",
-        0xfc => "VMware VMKCORE",
-        0xfd => "Linux raid auto",
-        0xfe => "LANstep",
-        0xff => "BBT",
-        _ => "Unknown Partition Type",
-    }
-    .into()
+fn tree_range(start: u64, num_sectors: u32) -> String {
+    format!(
+        "LBA {}-{} ({} sectors)",
+        start,
+        (start + num_sectors as u64).saturating_sub(1),
+        num_sectors
+    )
+}
+
+fn tree_container_note(start: u64, num_sectors: u32, container: (u64, u64)) -> &'static str {
+    let end = start + num_sectors as u64;
+    if start < container.0 || end > container.1 {
+        " [outside extended partition]"
+    } else {
+        ""
+    }
+}
+
+// Renders the entries of the EBR at `ebr_lba`. Data entries are relative to the EBR itself while
+// link entries are relative to the first EBR, `container` is the extended partition's [start, end) range.
+fn render_ebr(
+    output: &mut String,
+    node: &MbrPartitionTableEntryNode,
+    prefix: &str,
+    ebr_lba: u64,
+    first_ebr_lba: u64,
+    container: (u64, u64),
+) {
+    let children = match &node.children {
+        Some(children) => children,
+        None => {
+            output.push_str(&format!("{}└─ (empty)\n", prefix));
+            return;
+        }
+    };
+    for (index, child) in children.iter().enumerate() {
+        let entry = match &child.partition_table_entry {
+            Some(entry) => entry,
+            None => continue,
+        };
+        let is_last = index + 1 == children.len();
+        let connector = if is_last { "└─" } else { "├─" };
+        let child_prefix = format!("{}{}", prefix, if is_last { "   " } else { "│  " });
+        let relative = entry.starting_lba() as u64;
+        if entry.is_extended_partition() {
+            let next_ebr_lba = first_ebr_lba + relative;
+            output.push_str(&format!(
+                "{}{} Link: {}, relative LBA {} (to first EBR at {}) -> {}{}\n",
+                prefix,
+                connector,
+                entry.type_description(),
+                relative,
+                first_ebr_lba,
+                tree_range(next_ebr_lba, entry.num_sectors()),
+                tree_container_note(next_ebr_lba, entry.num_sectors(), container)
+            ));
+            if next_ebr_lba <= ebr_lba {
+                output.push_str(&format!(
+                    "{}└─ EBR (LBA {}) [link does not point forward, chain not followed]\n",
+                    child_prefix, next_ebr_lba
+                ));
+            } else {
+                output.push_str(&format!("{}└─ EBR (LBA {})\n", child_prefix, next_ebr_lba));
+                render_ebr(
+                    output,
+                    child,
+                    &format!("{}   ", child_prefix),
+                    next_ebr_lba,
+                    first_ebr_lba,
+                    container,
+                );
+            }
+        } else {
+            let absolute = ebr_lba + relative;
+            output.push_str(&format!(
+                "{}{} Data: {}, relative LBA {} (to EBR at {}) -> {}{}\n",
+                prefix,
+                connector,
+                entry.type_description(),
+                relative,
+                ebr_lba,
+                tree_range(absolute, entry.num_sectors()),
+                tree_container_note(absolute, entry.num_sectors(), container)
+            ));
+        }
+    }
+}
+
+/// Renders the MBR and the EBR chain of its extended partition as a tree, showing every EBR's
+/// absolute LBA, both of its entries as stored on disk and the absolute ranges they resolve to.
+pub fn render_mbr_tree(root: &MbrPartitionTableEntryNode) -> String {
+    let mut output = String::from("MBR (LBA 0)\n");
+    let children = match &root.children {
+        Some(children) => children,
+        None => return output,
+    };
+    for (index, child) in children.iter().enumerate() {
+        let entry = match &child.partition_table_entry {
+            Some(entry) => entry,
+            None => continue,
+        };
+        let is_last = index + 1 == children.len();
+        let connector = if is_last { "└─" } else { "├─" };
+        let start = entry.starting_lba() as u64;
+        output.push_str(&format!(
+            "{} Entry {}: {}{}, {}\n",
+            connector,
+            index + 1,
+            entry.type_description(),
+            if entry.bootable == 0x80 {
+                " (bootable)"
+            } else {
+                ""
+            },
+            tree_range(start, entry.num_sectors())
+        ));
+        if entry.is_extended_partition() {
+            let prefix = if is_last { "   " } else { "│  " };
+            let container = (start, start + entry.num_sectors() as u64);
+            output.push_str(&format!("{}└─ EBR (LBA {})\n", prefix, start));
+            render_ebr(
+                &mut output,
+                child,
+                &format!("{}   ", prefix),
+                start,
+                start,
+                container,
+            );
+        }
+    }
+    output
+}
+
+pub fn display_mbr_tree(root: &MbrPartitionTableEntryNode) {
+    print!("{}", render_mbr_tree(root));
+}
+
+pub fn read_disk_signature(path: &Path) -> io::Result<u32> {
+    let mut stream = ByteStream::new(path, SECTOR_SIZE, 0)?;
+    stream.skip_bytes(DISK_SIGNATURE_OFFSET)?;
+    stream.read_le::<u32>()
+}
+
+/// Replaces the disk signature of the MBR, leaving the rest of the sector untouched.
+pub fn write_disk_signature(path: &Path, disk_signature: u32) -> io::Result<()> {
+    let mut sector = ByteStream::new(path, SECTOR_SIZE, 0)?.read_raw(SECTOR_SIZE)?;
+    let offset = DISK_SIGNATURE_OFFSET as usize;
+    sector[offset..offset + 4].copy_from_slice(&disk_signature.to_le_bytes());
+    write_sectors_to_file(path, 0, &sector)
+}
+
+// Writes the (up to four) `entries` and the boot signature of the boot record at `lba`. The boot code and disk
+// signature of the MBR are kept unless `disk_signature` is given, EBRs are written out from a zeroed sector.
+fn write_boot_record(
+    path: &Path,
+    lba: u64,
+    entries: &[MbrPartitionTableEntry],
+    disk_signature: Option<u32>,
+) -> io::Result<()> {
+    let sector = boot_record_sector(path, lba, entries, disk_signature)?;
+    write_sectors_to_file(path, lba, &sector)
+}
+
+/// The boot record at `lba` with its partition table replaced by `entries`, keeping the boot code of the MBR.
+fn boot_record_sector(
+    path: &Path,
+    lba: u64,
+    entries: &[MbrPartitionTableEntry],
+    disk_signature: Option<u32>,
+) -> io::Result<Vec<u8>> {
+    let mut sector = if lba == 0 {
+        ByteStream::new(path, SECTOR_SIZE, 0)?.read_raw(SECTOR_SIZE)?
+    } else {
+        vec![0u8; SECTOR_SIZE]
+    };
+    if let Some(disk_signature) = disk_signature {
+        let offset = DISK_SIGNATURE_OFFSET as usize;
+        sector[offset..offset + 4].copy_from_slice(&disk_signature.to_le_bytes());
+    }
+    let mut table = Vec::with_capacity(64);
+    for entry in entries.iter().take(4) {
+        entry.write(&mut table)?;
+    }
+    table.resize(64, 0);
+    sector[BOOTSTRAPER_LENGTH as usize..510].copy_from_slice(&table);
+    sector[510..].copy_from_slice(&BOOT_SIGNATURE);
+    Ok(sector)
+}
+
+fn to_u32_lba(value: u64, partition: &MbrPartition) -> io::Result<u32> {
+    u32::try_from(value).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Partition {} does not fit below 2 TiB", partition.number),
+        )
+    })
+}
+
+/// Writes `partitions` as the MBR partition table. Partitions numbered 1-4 go into the matching MBR slot and
+/// logical partitions (5 and up) are chained through EBRs inside the extended partition, each EBR placed in
+/// the first sector after the previous logical partition the way fdisk lays them out.
+pub fn write_mbr(
+    path: &Path,
+    disk_signature: Option<u32>,
+    partitions: &[MbrPartition],
+) -> io::Result<()> {
+    let mut primary_entries: Vec<MbrPartitionTableEntry> =
+        (0..4).map(|_| MbrPartitionTableEntry::default()).collect();
+    for partition in partitions
+        .iter()
+        .filter(|partition| !partition.is_logical())
+    {
+        if partition.number == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Partition numbers start at 1",
+            ));
+        }
+        primary_entries[partition.number as usize - 1] = MbrPartitionTableEntry::new(
+            partition.bootable,
+            partition.partition_type,
+            to_u32_lba(partition.starting_lba, partition)?,
+            to_u32_lba(partition.num_sectors, partition)?,
+        );
+    }
+
+    let mut logical_partitions: Vec<&MbrPartition> = partitions
+        .iter()
+        .filter(|partition| partition.is_logical())
+        .collect();
+    logical_partitions.sort_by_key(|partition| partition.number);
+    let mut extended_boot_records = Vec::new();
+    if !logical_partitions.is_empty() {
+        let extended = partitions
+            .iter()
+            .find(|partition| !partition.is_logical() && partition.is_extended())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Logical partitions require an extended partition",
+                )
+            })?;
+        let mut ebr_lbas = Vec::with_capacity(logical_partitions.len());
+        let mut next_ebr_lba = extended.starting_lba;
+        for logical in logical_partitions.iter() {
+            if next_ebr_lba >= logical.starting_lba
+                || logical.starting_lba + logical.num_sectors
+                    > extended.starting_lba + extended.num_sectors
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "Logical partition {} must lie inside the extended partition after its EBR at LBA {}",
+                        logical.number, next_ebr_lba
+                    ),
+                ));
+            }
+            ebr_lbas.push(next_ebr_lba);
+            next_ebr_lba = logical.starting_lba + logical.num_sectors;
+        }
+
+        for (index, logical) in logical_partitions.iter().enumerate() {
+            let ebr_lba = ebr_lbas[index];
+            let mut entries = vec![MbrPartitionTableEntry::new(
+                logical.bootable,
+                logical.partition_type,
+                to_u32_lba(logical.starting_lba - ebr_lba, logical)?,
+                to_u32_lba(logical.num_sectors, logical)?,
+            )];
+            if let Some(next) = logical_partitions.get(index + 1) {
+                let next_ebr_lba = ebr_lbas[index + 1];
+                // Links are relative to the first EBR, which sits at the start of the extended partition
+                entries.push(MbrPartitionTableEntry::new(
+                    false,
+                    0x05,
+                    to_u32_lba(next_ebr_lba - extended.starting_lba, next)?,
+                    to_u32_lba(next.starting_lba + next.num_sectors - next_ebr_lba, next)?,
+                ));
+            }
+            extended_boot_records.push((ebr_lba, entries));
+        }
+    }
+
+    write_boot_record(path, 0, &primary_entries, disk_signature)?;
+    for (ebr_lba, entries) in extended_boot_records {
+        write_boot_record(path, ebr_lba, &entries, None)?;
+    }
+    Ok(())
+}
+
+/// Writes an MBR with a single 0xEE entry covering the disk (capped at 2 TiB), keeping the boot code.
+pub fn write_protective_mbr(path: &Path, total_sectors: u64) -> io::Result<()> {
+    let sector = protective_mbr_sector(path, total_sectors)?;
+    write_sectors_to_file(path, 0, &sector)
+}
+
+/// The MBR with its partition table replaced by a single protective partition covering `total_sectors`.
+pub fn protective_mbr_sector(path: &Path, total_sectors: u64) -> io::Result<Vec<u8>> {
+    let num_sectors = (total_sectors - 1).min(u32::MAX as u64) as u32;
+    let mut entry = MbrPartitionTableEntry::new(false, GPT_PARTITION_TYPE, 1, num_sectors);
+    if total_sectors - 1 > u32::MAX as u64 {
+        entry.ending_chs = [0xFF, 0xFF, 0xFF];
+    }
+    boot_record_sector(path, 0, &[entry], None)
 }
 
 pub fn parse_mbr(path: &Path) -> io::Result<MbrPartitionTableEntryNode> {
@@ -408,3 +762,95 @@ pub fn display_mbr(root: MbrPartitionTableEntryNode, show_chs: bool) {
     print_nodes(&mut table, root, show_chs, true);
     table.printstd();
 }
+
+#[cfg(test)]
+fn write_test_entry(
+    image: &mut [u8],
+    table_lba: u64,
+    slot: usize,
+    partition_type: u8,
+    lba_start: u32,
+    num_sectors: u32,
+) {
+    let table_offset = table_lba as usize * SECTOR_SIZE;
+    let offset = table_offset + BOOTSTRAPER_LENGTH as usize + slot * 16;
+    image[offset + 4] = partition_type;
+    image[offset + 8..offset + 12].copy_from_slice(&lba_start.to_le_bytes());
+    image[offset + 12..offset + 16].copy_from_slice(&num_sectors.to_le_bytes());
+    image[table_offset + 510..table_offset + 512].copy_from_slice(&BOOT_SIGNATURE);
+}
+
+#[test]
+fn test_mbr_tree() {
+    let mut image = vec![0u8; 4096 * SECTOR_SIZE];
+    write_test_entry(&mut image, 0, 0, 0x83, 2048, 1024);
+    write_test_entry(&mut image, 0, 1, 0x05, 3072, 1024);
+    // First EBR: data partition relative to the EBR, link relative to the first EBR
+    write_test_entry(&mut image, 3072, 0, 0x83, 64, 256);
+    write_test_entry(&mut image, 3072, 1, 0x05, 512, 512);
+    // Second EBR at 3072 + 512
+    write_test_entry(&mut image, 3584, 0, 0x07, 64, 448);
+    let path = crate::bytestream::write_test_image("mbr_tree", &image);
+
+    let root = parse_mbr(&path).unwrap();
+    let tree = render_mbr_tree(&root);
+    std::fs::remove_file(&path).unwrap();
+    let expected = "\
+MBR (LBA 0)
+├─ Entry 1: 0x83 :: Linux, LBA 2048-3071 (1024 sectors)
+└─ Entry 2: 0x05 :: Extended, LBA 3072-4095 (1024 sectors)
+   └─ EBR (LBA 3072)
+      ├─ Data: 0x83 :: Linux, relative LBA 64 (to EBR at 3072) -> LBA 3136-3391 (256 sectors)
+      └─ Link: 0x05 :: Extended, relative LBA 512 (to first EBR at 3072) -> LBA 3584-4095 (512 sectors)
+         └─ EBR (LBA 3584)
+            └─ Data: 0x07 :: HPFS/NTFS/exFAT, relative LBA 64 (to EBR at 3584) -> LBA 3648-4095 (448 sectors)
+";
+    assert_eq!(tree, expected);
+}
+
+#[test]
+fn test_mbr_tree_backwards_link() {
+    let mut image = vec![0u8; 4096 * SECTOR_SIZE];
+    write_test_entry(&mut image, 0, 0, 0x05, 2048, 2048);
+    write_test_entry(&mut image, 2048, 0, 0x83, 64, 256);
+    // Link pointing back at the first EBR
+    write_test_entry(&mut image, 2048, 1, 0x05, 0, 512);
+    let path = crate::bytestream::write_test_image("mbr_tree_loop", &image);
+
+    let root = parse_mbr(&path).unwrap();
+    let tree = render_mbr_tree(&root);
+    std::fs::remove_file(&path).unwrap();
+    assert!(tree.contains("EBR (LBA 2048) [link does not point forward, chain not followed]"));
+}
+
+#[test]
+fn test_extended_partition_types() {
+    for partition_type in EXTENDED_PARTITION_TYPES {
+        let mut image = vec![0u8; 4096 * SECTOR_SIZE];
+        write_test_entry(&mut image, 0, 0, partition_type, 2048, 2048);
+        write_test_entry(&mut image, 2048, 0, 0x83, 64, 512);
+        write_test_entry(&mut image, 2048, 1, partition_type, 576, 1472);
+        write_test_entry(&mut image, 2624, 0, 0x82, 64, 1408);
+        let path = crate::bytestream::write_test_image(
+            &format!("extended_{:02x}", partition_type),
+            &image,
+        );
+
+        let root = parse_mbr(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let partitions = root.partitions();
+        assert_eq!(
+            partitions
+                .iter()
+                .map(|partition| (partition.number, partition.starting_lba))
+                .collect::<Vec<_>>(),
+            vec![(1, 2048), (5, 2112), (6, 2688)],
+            "logical partitions of extended partition type {:#04x}",
+            partition_type
+        );
+        assert_ne!(
+            mbr_type_name(partition_type),
+            crate::partition_types::UNKNOWN_PARTITION_TYPE
+        );
+    }
+}
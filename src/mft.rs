@@ -1,7 +1,7 @@
 use crate::{
     bytestream::{interpret_bytes_as_utf16, ByteStream, Readable, SECTOR_SIZE},
+    checksum::{ntfs_boot_sector_checksum, stored_ntfs_boot_sector_checksum},
     mbr::{MbrPartitionTableEntryNode, BOOT_SIGNATURE},
-    Timestomp,
 };
 use byteorder::{LittleEndian, WriteBytesExt};
 use chrono::{offset, DateTime, Local};
@@ -101,6 +101,12 @@ pub fn parse_pbr(path: &Path, starting_lba: u64) -> io::Result<Vec<MftFileRecord
     let partition_boot_record = stream.read::<NtfsPartitionBootRecord>()?;
     match partition_boot_record.oem_id_str().as_deref() {
         Ok("NTFS") => {
+            let boot_sector =
+                ByteStream::new(path, SECTOR_SIZE, starting_lba)?.read_raw(SECTOR_SIZE)?;
+            let checksum = stored_ntfs_boot_sector_checksum(&boot_sector);
+            if checksum != 0 && checksum != ntfs_boot_sector_checksum(&boot_sector) {
+                eprintln!("NTFS boot sector checksum mismatch.")
+            }
             // 510(Sector size - signature) - 84 (PBR btyes read) = 426 Boot code
             let _ = stream.read_byte_array::<426>()?;
             assert_eq!(
@@ -881,15 +887,10 @@ pub fn display_mft(records: Vec<MftFileRecord>) {
     }
 }
 
-pub fn timestomp_mft(path: &Path, records: Vec<MftFileRecord>, timestomp: Timestomp) {
-    let Timestomp::Timestomp {
-        file_name,
-        timestamp,
-    } = timestomp;
-
+pub fn timestomp_mft(path: &Path, records: Vec<MftFileRecord>, file_name: &str, timestamp: u64) {
     let records_with_file_name: Vec<MftFileRecord> = records
         .into_iter()
-        .filter(|attrib| attrib.has_file_name_attribute(&file_name))
+        .filter(|attrib| attrib.has_file_name_attribute(file_name))
         .collect();
     if records_with_file_name.len() > 0 {
         let record = &records_with_file_name[0];
@@ -0,0 +1,1473 @@
+use crate::guid::Guid;
+use prettytable::{row, Table};
+use std::{fmt::Display, io, path::Path, sync::OnceLock};
+
+pub const UNKNOWN_PARTITION_TYPE: &str = "Unknown Partition Type";
+
+// Well-known GPT partition type GUIDs the code refers to by name
+pub const EFI_SYSTEM_PARTITION_GUID: Guid =
+    Guid::from_fields(0xC12A7328, 0xF81F, 0x11D2, 0xBA4B, 0x00A0C93EC93B);
+pub const MICROSOFT_BASIC_DATA_GUID: Guid =
+    Guid::from_fields(0xEBD0A0A2, 0xB9E5, 0x4433, 0x87C0, 0x68B6B72699C7);
+pub const WINDOWS_RECOVERY_GUID: Guid =
+    Guid::from_fields(0xDE94BBA4, 0x06D1, 0x4D40, 0xA16A, 0xBFD50179D6AC);
+pub const LDM_DATA_GUID: Guid =
+    Guid::from_fields(0xAF9B60A0, 0x1431, 0x4F62, 0xBC68, 0x3311714A69AD);
+pub const LINUX_FILESYSTEM_GUID: Guid =
+    Guid::from_fields(0x0FC63DAF, 0x8483, 0x4772, 0x8E79, 0x3D69D8477DE4);
+pub const LINUX_SWAP_GUID: Guid =
+    Guid::from_fields(0x0657FD6D, 0xA4AB, 0x43C4, 0x84E5, 0x0933C84B4F4F);
+pub const LINUX_LVM_GUID: Guid =
+    Guid::from_fields(0xE6D6D379, 0xF507, 0x44C2, 0xA23C, 0x238F2A3DF928);
+pub const LINUX_RAID_GUID: Guid =
+    Guid::from_fields(0xA19D880F, 0x05FC, 0x4D3B, 0xA006, 0x743F0F84911E);
+pub const FREEBSD_DATA_GUID: Guid =
+    Guid::from_fields(0x516E7CB4, 0x6ECF, 0x11D6, 0x8FF8, 0x00022D09712B);
+pub const OPENBSD_DATA_GUID: Guid =
+    Guid::from_fields(0x824CC7A0, 0x36A8, 0x11E3, 0x890A, 0x952519AD3F61);
+pub const NETBSD_FFS_GUID: Guid =
+    Guid::from_fields(0x49F48D5A, 0xB10E, 0x11DC, 0xB99B, 0x0019D1879648);
+pub const APPLE_HFS_PLUS_GUID: Guid =
+    Guid::from_fields(0x48465300, 0x0000, 0x11AA, 0xAA11, 0x00306543ECAC);
+pub const APPLE_UFS_GUID: Guid =
+    Guid::from_fields(0x55465300, 0x0000, 0x11AA, 0xAA11, 0x00306543ECAC);
+pub const CHROMEOS_KERNEL_GUID: Guid =
+    Guid::from_fields(0xFE3A2A5D, 0x4F32, 0x41A7, 0xB725, 0xACCC3285A309);
+
+// https://en.wikipedia.org/wiki/Partition_type#List_of_partition_IDs
+// Names follow util-linux's fdisk without its 15 character truncation.
+// code, name, OS family, aliases
+const MBR_PARTITION_TYPES: &[(u8, &str, &str, &[&str])] = &[
+    (0x00, "Empty", "Generic", &["empty"]),
+    (0x01, "FAT12", "DOS", &["fat12"]),
+    (0x02, "XENIX root", "XENIX", &[]),
+    (0x03, "XENIX usr", "XENIX", &[]),
+    (0x04, "FAT16 <32M", "DOS", &["fat16-small"]),
+    (0x05, "Extended", "DOS", &["extended", "E", "Ex"]),
+    (0x06, "FAT16", "DOS", &["fat16"]),
+    (
+        0x07,
+        "HPFS/NTFS/exFAT",
+        "Windows",
+        &["ntfs", "exfat", "hpfs"],
+    ),
+    (0x08, "AIX", "AIX", &[]),
+    (0x09, "AIX bootable", "AIX", &[]),
+    (0x0a, "OS/2 Boot Manager", "OS/2", &[]),
+    (0x0b, "W95 FAT32", "Windows", &["fat32-chs"]),
+    (0x0c, "W95 FAT32 (LBA)", "Windows", &["fat32", "vfat"]),
+    (0x0e, "W95 FAT16 (LBA)", "Windows", &["fat16-lba"]),
+    (0x0f, "W95 Extended (LBA)", "Windows", &["extended-lba"]),
+    (0x10, "OPUS", "OPUS", &[]),
+    (0x11, "Hidden FAT12", "DOS", &[]),
+    (0x12, "Compaq diagnostics", "Generic", &[]),
+    (0x14, "Hidden FAT16 <32M", "DOS", &[]),
+    (0x15, "Hidden Extended", "DOS", &["hidden-extended"]),
+    (0x16, "Hidden FAT16", "DOS", &[]),
+    (0x17, "Hidden HPFS/NTFS", "Windows", &[]),
+    (0x18, "AST SmartSleep", "Generic", &[]),
+    (0x1b, "Hidden W95 FAT32", "Windows", &[]),
+    (0x1c, "Hidden W95 FAT32 (LBA)", "Windows", &[]),
+    (0x1e, "Hidden W95 FAT16 (LBA)", "Windows", &[]),
+    (
+        0x1f,
+        "Hidden W95 Extended (LBA)",
+        "Windows",
+        &["hidden-extended-lba"],
+    ),
+    (0x24, "NEC DOS", "DOS", &[]),
+    (0x27, "Hidden NTFS WinRE", "Windows", &["winre"]),
+    (0x39, "Plan 9", "Plan 9", &[]),
+    (0x3c, "PartitionMagic recovery", "Generic", &[]),
+    (0x40, "Venix 80286", "Venix", &[]),
+    (0x41, "PPC PReP Boot", "PowerPC", &["prep"]),
+    (0x42, "SFS", "Windows", &[]),
+    (0x4d, "QNX4.x", "QNX", &[]),
+    (0x4e, "QNX4.x 2nd part", "QNX", &[]),
+    (0x4f, "QNX4.x 3rd part", "QNX", &[]),
+    (0x50, "OnTrack DM", "Generic", &[]),
+    (0x51, "OnTrack DM6 Aux1", "Generic", &[]),
+    (0x52, "CP/M", "CP/M", &[]),
+    (0x53, "OnTrack DM6 Aux3", "Generic", &[]),
+    (0x54, "OnTrack DM6 DDO", "Generic", &[]),
+    (0x55, "EZ-Drive", "Generic", &[]),
+    (0x56, "Golden Bow", "Generic", &[]),
+    (0x5c, "Priam Edisk", "Generic", &[]),
+    (0x61, "SpeedStor", "Generic", &[]),
+    (0x63, "GNU HURD or SysV", "Unix", &[]),
+    (0x64, "Novell Netware 286", "NetWare", &[]),
+    (0x65, "Novell Netware 386", "NetWare", &[]),
+    (0x70, "DiskSecure Multi-Boot", "Generic", &[]),
+    (0x75, "PC/IX", "Unix", &[]),
+    (0x80, "Old Minix", "Minix", &[]),
+    (0x81, "Minix / old Linux", "Minix", &[]),
+    (0x82, "Linux swap / Solaris", "Linux", &["swap", "S"]),
+    (0x83, "Linux", "Linux", &["linux", "L"]),
+    (0x84, "OS/2 hidden or Intel hibernation", "OS/2", &[]),
+    (0x85, "Linux extended", "Linux", &["linux-extended", "X"]),
+    (0x86, "NTFS volume set", "Windows", &[]),
+    (0x87, "NTFS volume set", "Windows", &[]),
+    (0x88, "Linux plaintext", "Linux", &[]),
+    (0x8e, "Linux LVM", "Linux", &["lvm", "V"]),
+    (0x91, "Hidden Extended (FreeDOS)", "DOS", &[]),
+    (0x93, "Amoeba", "Amoeba", &[]),
+    (0x94, "Amoeba BBT", "Amoeba", &[]),
+    (0x9b, "Hidden W95 Extended (LBA, FreeDOS)", "DOS", &[]),
+    (0x9f, "BSD/OS", "BSD", &[]),
+    (0xa0, "IBM Thinkpad hibernation", "Generic", &[]),
+    (0xa5, "FreeBSD", "FreeBSD", &["freebsd"]),
+    (0xa6, "OpenBSD", "OpenBSD", &["openbsd"]),
+    (0xa7, "NeXTSTEP", "NeXTSTEP", &[]),
+    (0xa8, "Darwin UFS", "macOS", &[]),
+    (0xa9, "NetBSD", "NetBSD", &["netbsd"]),
+    (0xab, "Darwin boot", "macOS", &[]),
+    (0xaf, "HFS / HFS+", "macOS", &["hfs", "hfsplus"]),
+    (0xb7, "BSDI fs", "BSD", &[]),
+    (0xb8, "BSDI swap", "BSD", &[]),
+    (0xbb, "Boot Wizard hidden", "Generic", &[]),
+    (0xbc, "Acronis FAT32 LBA", "Generic", &[]),
+    (0xbe, "Solaris boot", "Solaris", &[]),
+    (0xbf, "Solaris", "Solaris", &["solaris"]),
+    (0xc1, "DRDOS/sec (FAT-12)", "DOS", &[]),
+    (0xc4, "DRDOS/sec (FAT-16 < 32M)", "DOS", &[]),
+    (0xc5, "DRDOS/sec Extended", "DOS", &[]),
+    (0xc6, "DRDOS/sec (FAT-16)", "DOS", &[]),
+    (0xc7, "Syrinx", "Syrinx", &[]),
+    (0xcf, "DRDOS/sec Extended (LBA)", "DOS", &[]),
+    (0xd5, "Multiuser DOS Extended", "DOS", &[]),
+    (0xda, "Non-FS data", "Generic", &[]),
+    (0xdb, "CP/M / CTOS / ...", "CP/M", &[]),
+    (0xde, "Dell Utility", "Generic", &[]),
+    (0xdf, "BootIt", "Generic", &[]),
+    (0xe1, "DOS access", "DOS", &[]),
+    (0xe3, "DOS R/O", "DOS", &[]),
+    (0xe4, "SpeedStor", "Generic", &[]),
+    (0xea, "Linux extended boot", "Linux", &["xbootldr"]),
+    (0xeb, "BeOS fs", "BeOS", &[]),
+    (0xee, "GPT", "Generic", &["gpt", "protective"]),
+    (0xef, "EFI (FAT-12/16/32)", "Generic", &["uefi", "esp", "U"]),
+    (0xf0, "Linux/PA-RISC boot", "Linux", &[]),
+    (0xf1, "SpeedStor", "Generic", &[]),
+    (0xf2, "DOS secondary", "DOS", &[]),
+    (0xf4, "SpeedStor", "Generic", &[]),
+    (0xf8, "EBBR protective", "Generic", &[]),
+    (0xfb, "VMware VMFS", "VMware ESX", &["vmfs"]),
+    (0xfc, "VMware VMKCORE", "VMware ESX", &[]),
+    (0xfd, "Linux raid autodetect", "Linux", &["raid", "R"]),
+    (0xfe, "LANstep", "Generic", &[]),
+    (0xff, "BBT", "Generic", &[]),
+];
+
+// https://en.wikipedia.org/wiki/GUID_Partition_Table#Partition_type_GUIDs
+// GUID, name, OS family, aliases
+const GPT_PARTITION_TYPES: &[(&str, &str, &str, &[&str])] = &[
+    (
+        "00000000-0000-0000-0000-000000000000",
+        "Unused entry",
+        "Generic",
+        &[],
+    ),
+    (
+        "024DEE41-33E7-11D3-9D69-0008C781F39F",
+        "MBR partition scheme",
+        "Generic",
+        &[],
+    ),
+    (
+        "C12A7328-F81F-11D2-BA4B-00A0C93EC93B",
+        "EFI System",
+        "Generic",
+        &["esp", "uefi", "U"],
+    ),
+    (
+        "21686148-6449-6E6F-744E-656564454649",
+        "BIOS boot",
+        "Generic",
+        &["bios-boot"],
+    ),
+    (
+        "D3BFE2DE-3DAF-11DF-BA40-E3A556D89593",
+        "Intel Fast Flash",
+        "Generic",
+        &[],
+    ),
+    (
+        "F4019732-066E-4E12-8273-346C5641494F",
+        "Sony boot partition",
+        "Generic",
+        &[],
+    ),
+    (
+        "BFBFAFE7-A34F-448A-9A5B-6213EB736C22",
+        "Lenovo boot partition",
+        "Generic",
+        &[],
+    ),
+    (
+        "9E1A2D38-C612-4316-AA26-8B49521E5A8B",
+        "PowerPC PReP boot",
+        "PowerPC",
+        &["prep"],
+    ),
+    (
+        "7412F7D5-A156-4B13-81DC-867174929325",
+        "ONIE boot",
+        "ONIE",
+        &[],
+    ),
+    (
+        "D4E6E2CD-4469-46F3-B5CB-1BFF57AFC149",
+        "ONIE config",
+        "ONIE",
+        &[],
+    ),
+    (
+        "E3C9E316-0B5C-4DB8-817D-F92DF00215AE",
+        "Microsoft reserved",
+        "Windows",
+        &["msr"],
+    ),
+    (
+        "EBD0A0A2-B9E5-4433-87C0-68B6B72699C7",
+        "Microsoft basic data",
+        "Windows",
+        &["basic-data", "ntfs"],
+    ),
+    (
+        "5808C8AA-7E8F-42E0-85D2-E1E90434CFB3",
+        "Microsoft LDM metadata",
+        "Windows",
+        &[],
+    ),
+    (
+        "AF9B60A0-1431-4F62-BC68-3311714A69AD",
+        "Microsoft LDM data",
+        "Windows",
+        &[],
+    ),
+    (
+        "DE94BBA4-06D1-4D40-A16A-BFD50179D6AC",
+        "Windows recovery environment",
+        "Windows",
+        &["winre"],
+    ),
+    (
+        "37AFFC90-EF7D-4E96-91C3-2D7AE055B174",
+        "IBM General Parallel Fs",
+        "Windows",
+        &[],
+    ),
+    (
+        "E75CAF8F-F680-4CEE-AFA3-B001E56EFC2D",
+        "Microsoft Storage Spaces",
+        "Windows",
+        &[],
+    ),
+    (
+        "558D43C5-A1AC-43C0-AAC8-D1472B2923D1",
+        "Microsoft Storage Replica",
+        "Windows",
+        &[],
+    ),
+    (
+        "75894C1E-3AEB-11D3-B7C1-7B03A0000000",
+        "HP-UX data",
+        "HP-UX",
+        &[],
+    ),
+    (
+        "E2A1E728-32E3-11D6-A682-7B03A0000000",
+        "HP-UX service",
+        "HP-UX",
+        &[],
+    ),
+    (
+        "0FC63DAF-8483-4772-8E79-3D69D8477DE4",
+        "Linux filesystem",
+        "Linux",
+        &["linux", "linux-generic", "L"],
+    ),
+    (
+        "0657FD6D-A4AB-43C4-84E5-0933C84B4F4F",
+        "Linux swap",
+        "Linux",
+        &["swap", "S"],
+    ),
+    (
+        "933AC7E1-2EB4-4F13-B844-0E14E2AEF915",
+        "Linux home",
+        "Linux",
+        &["home", "H"],
+    ),
+    (
+        "3B8F8425-20E0-4F3B-907F-1A25A76F98E8",
+        "Linux server data",
+        "Linux",
+        &["srv"],
+    ),
+    (
+        "4D21B016-B534-45C2-A9FB-5C16E091FD2D",
+        "Linux variable data",
+        "Linux",
+        &["var"],
+    ),
+    (
+        "7EC6F557-3BC5-4ACA-B293-16EF5DF639D1",
+        "Linux temporary data",
+        "Linux",
+        &["tmp"],
+    ),
+    (
+        "773F91EF-66D4-49B5-BD83-D683BF40AD16",
+        "Linux user's home",
+        "Linux",
+        &["user-home"],
+    ),
+    (
+        "BC13C2FF-59E6-4262-A352-B275FD6F7172",
+        "Linux extended boot",
+        "Linux",
+        &["xbootldr"],
+    ),
+    (
+        "8DA63339-0007-60C0-C436-083AC8230908",
+        "Linux reserved",
+        "Linux",
+        &[],
+    ),
+    (
+        "A19D880F-05FC-4D3B-A006-743F0F84911E",
+        "Linux RAID",
+        "Linux",
+        &["raid", "R"],
+    ),
+    (
+        "E6D6D379-F507-44C2-A23C-238F2A3DF928",
+        "Linux LVM",
+        "Linux",
+        &["lvm", "V"],
+    ),
+    (
+        "7FFEC5C9-2D00-49B7-8941-3EA10A5586B7",
+        "Linux plain dm-crypt",
+        "Linux",
+        &["dm-crypt"],
+    ),
+    (
+        "CA7D7CCB-63ED-4C53-861C-1742536059CC",
+        "Linux LUKS",
+        "Linux",
+        &["luks"],
+    ),
+    (
+        "516E7CB4-6ECF-11D6-8FF8-00022D09712B",
+        "FreeBSD data",
+        "FreeBSD",
+        &[],
+    ),
+    (
+        "83BD6B9D-7F41-11DC-BE0B-001560B84F0F",
+        "FreeBSD boot",
+        "FreeBSD",
+        &[],
+    ),
+    (
+        "516E7CB5-6ECF-11D6-8FF8-00022D09712B",
+        "FreeBSD swap",
+        "FreeBSD",
+        &[],
+    ),
+    (
+        "516E7CB6-6ECF-11D6-8FF8-00022D09712B",
+        "FreeBSD UFS",
+        "FreeBSD",
+        &[],
+    ),
+    (
+        "516E7CBA-6ECF-11D6-8FF8-00022D09712B",
+        "FreeBSD ZFS",
+        "FreeBSD",
+        &[],
+    ),
+    (
+        "516E7CB8-6ECF-11D6-8FF8-00022D09712B",
+        "FreeBSD Vinum",
+        "FreeBSD",
+        &[],
+    ),
+    (
+        "74BA7DD9-A689-11E1-BD04-00E081286ACF",
+        "FreeBSD nandfs",
+        "FreeBSD",
+        &[],
+    ),
+    (
+        "48465300-0000-11AA-AA11-00306543ECAC",
+        "Apple HFS/HFS+",
+        "macOS",
+        &["hfs", "hfsplus"],
+    ),
+    (
+        "7C3457EF-0000-11AA-AA11-00306543ECAC",
+        "Apple APFS",
+        "macOS",
+        &["apfs"],
+    ),
+    (
+        "55465300-0000-11AA-AA11-00306543ECAC",
+        "Apple UFS",
+        "macOS",
+        &[],
+    ),
+    (
+        "52414944-0000-11AA-AA11-00306543ECAC",
+        "Apple RAID",
+        "macOS",
+        &[],
+    ),
+    (
+        "52414944-5F4F-11AA-AA11-00306543ECAC",
+        "Apple RAID offline",
+        "macOS",
+        &[],
+    ),
+    (
+        "426F6F74-0000-11AA-AA11-00306543ECAC",
+        "Apple boot",
+        "macOS",
+        &[],
+    ),
+    (
+        "4C616265-6C00-11AA-AA11-00306543ECAC",
+        "Apple label",
+        "macOS",
+        &[],
+    ),
+    (
+        "5265636F-7665-11AA-AA11-00306543ECAC",
+        "Apple TV recovery",
+        "macOS",
+        &[],
+    ),
+    (
+        "53746F72-6167-11AA-AA11-00306543ECAC",
+        "Apple Core storage",
+        "macOS",
+        &[],
+    ),
+    (
+        "69646961-6700-11AA-AA11-00306543ECAC",
+        "Apple APFS preboot",
+        "macOS",
+        &[],
+    ),
+    (
+        "52637672-7900-11AA-AA11-00306543ECAC",
+        "Apple APFS recovery",
+        "macOS",
+        &[],
+    ),
+    (
+        "B6FA30DA-92D2-4A9A-96F1-871EC6486200",
+        "Apple SoftRAID Status",
+        "macOS",
+        &[],
+    ),
+    (
+        "2E313465-19B9-463F-8126-8A7993773801",
+        "Apple SoftRAID Scratch",
+        "macOS",
+        &[],
+    ),
+    (
+        "FA709C7E-65B1-4593-BFD5-E71D61DE9B02",
+        "Apple SoftRAID Volume",
+        "macOS",
+        &[],
+    ),
+    (
+        "BBBA6DF5-F46F-4A89-8F59-8765B2727503",
+        "Apple SoftRAID Cache",
+        "macOS",
+        &[],
+    ),
+    (
+        "6A82CB45-1DD2-11B2-99A6-080020736631",
+        "Solaris boot",
+        "Solaris",
+        &[],
+    ),
+    (
+        "6A85CF4D-1DD2-11B2-99A6-080020736631",
+        "Solaris root",
+        "Solaris",
+        &[],
+    ),
+    (
+        "6A898CC3-1DD2-11B2-99A6-080020736631",
+        "Solaris /usr & Apple ZFS",
+        "Solaris",
+        &["zfs"],
+    ),
+    (
+        "6A87C46F-1DD2-11B2-99A6-080020736631",
+        "Solaris swap",
+        "Solaris",
+        &[],
+    ),
+    (
+        "6A8B642B-1DD2-11B2-99A6-080020736631",
+        "Solaris backup",
+        "Solaris",
+        &[],
+    ),
+    (
+        "6A8EF2E9-1DD2-11B2-99A6-080020736631",
+        "Solaris /var",
+        "Solaris",
+        &[],
+    ),
+    (
+        "6A90BA39-1DD2-11B2-99A6-080020736631",
+        "Solaris /home",
+        "Solaris",
+        &[],
+    ),
+    (
+        "6A9283A5-1DD2-11B2-99A6-080020736631",
+        "Solaris alternate sector",
+        "Solaris",
+        &[],
+    ),
+    (
+        "6A945A3B-1DD2-11B2-99A6-080020736631",
+        "Solaris reserved 1",
+        "Solaris",
+        &[],
+    ),
+    (
+        "6A9630D1-1DD2-11B2-99A6-080020736631",
+        "Solaris reserved 2",
+        "Solaris",
+        &[],
+    ),
+    (
+        "6A980767-1DD2-11B2-99A6-080020736631",
+        "Solaris reserved 3",
+        "Solaris",
+        &[],
+    ),
+    (
+        "6A96237F-1DD2-11B2-99A6-080020736631",
+        "Solaris reserved 4",
+        "Solaris",
+        &[],
+    ),
+    (
+        "6A8D2AC7-1DD2-11B2-99A6-080020736631",
+        "Solaris reserved 5",
+        "Solaris",
+        &[],
+    ),
+    (
+        "49F48D32-B10E-11DC-B99B-0019D1879648",
+        "NetBSD swap",
+        "NetBSD",
+        &[],
+    ),
+    (
+        "49F48D5A-B10E-11DC-B99B-0019D1879648",
+        "NetBSD FFS",
+        "NetBSD",
+        &[],
+    ),
+    (
+        "49F48D82-B10E-11DC-B99B-0019D1879648",
+        "NetBSD LFS",
+        "NetBSD",
+        &[],
+    ),
+    (
+        "2DB519C4-B10F-11DC-B99B-0019D1879648",
+        "NetBSD concatenated",
+        "NetBSD",
+        &[],
+    ),
+    (
+        "2DB519EC-B10F-11DC-B99B-0019D1879648",
+        "NetBSD encrypted",
+        "NetBSD",
+        &[],
+    ),
+    (
+        "49F48DAA-B10E-11DC-B99B-0019D1879648",
+        "NetBSD RAID",
+        "NetBSD",
+        &[],
+    ),
+    (
+        "FE3A2A5D-4F32-41A7-B725-ACCC3285A309",
+        "ChromeOS kernel",
+        "ChromeOS",
+        &["chromeos-kernel"],
+    ),
+    (
+        "3CB8E202-3B7E-47DD-8A3C-7FF2A13CFCEC",
+        "ChromeOS root fs",
+        "ChromeOS",
+        &["chromeos-root"],
+    ),
+    (
+        "CAB6E88E-ABF3-4102-A07A-D4BB9BE3C1D3",
+        "ChromeOS firmware",
+        "ChromeOS",
+        &[],
+    ),
+    (
+        "2E0A753D-9E48-43B0-8337-B15192CB1B5E",
+        "ChromeOS reserved",
+        "ChromeOS",
+        &[],
+    ),
+    (
+        "09845860-705F-4BB5-B16C-8A8A099CAF52",
+        "ChromeOS miniOS",
+        "ChromeOS",
+        &[],
+    ),
+    (
+        "3F0F8318-F146-4E6B-8222-C28C8F02E0D5",
+        "ChromeOS hibernate",
+        "ChromeOS",
+        &[],
+    ),
+    (
+        "5DFBF5F4-2848-4BAC-AA5E-0D9A20B745A6",
+        "Container Linux /usr",
+        "Container Linux",
+        &[],
+    ),
+    (
+        "3884DD41-8582-4404-B9A8-E9B84F2DF50E",
+        "Container Linux resizable rootfs",
+        "Container Linux",
+        &[],
+    ),
+    (
+        "C95DC21A-DF0E-4340-8D7B-26CBFA9A03E0",
+        "Container Linux OEM customizations",
+        "Container Linux",
+        &[],
+    ),
+    (
+        "BE9067B9-EA49-4F15-B4F6-F36F8C9E1818",
+        "Container Linux root on RAID",
+        "Container Linux",
+        &[],
+    ),
+    (
+        "42465331-3BA3-10F1-802A-4861696B7521",
+        "Haiku BFS",
+        "Haiku",
+        &[],
+    ),
+    (
+        "85D5E45A-237C-11E1-B4B3-E89A8F7FC3A7",
+        "MidnightBSD data",
+        "MidnightBSD",
+        &[],
+    ),
+    (
+        "85D5E45E-237C-11E1-B4B3-E89A8F7FC3A7",
+        "MidnightBSD boot",
+        "MidnightBSD",
+        &[],
+    ),
+    (
+        "85D5E45B-237C-11E1-B4B3-E89A8F7FC3A7",
+        "MidnightBSD swap",
+        "MidnightBSD",
+        &[],
+    ),
+    (
+        "0394EF8B-237E-11E1-B4B3-E89A8F7FC3A7",
+        "MidnightBSD UFS",
+        "MidnightBSD",
+        &[],
+    ),
+    (
+        "85D5E45D-237C-11E1-B4B3-E89A8F7FC3A7",
+        "MidnightBSD ZFS",
+        "MidnightBSD",
+        &[],
+    ),
+    (
+        "85D5E45C-237C-11E1-B4B3-E89A8F7FC3A7",
+        "MidnightBSD Vinum",
+        "MidnightBSD",
+        &[],
+    ),
+    (
+        "45B0969E-9B03-4F30-B4C6-B4B80CEFF106",
+        "Ceph Journal",
+        "Ceph",
+        &[],
+    ),
+    (
+        "45B0969E-9B03-4F30-B4C6-5EC00CEFF106",
+        "Ceph Encrypted Journal",
+        "Ceph",
+        &[],
+    ),
+    (
+        "4FBD7E29-9D25-41B8-AFD0-062C0CEFF05D",
+        "Ceph OSD",
+        "Ceph",
+        &[],
+    ),
+    (
+        "4FBD7E29-9D25-41B8-AFD0-5EC00CEFF05D",
+        "Ceph crypt OSD",
+        "Ceph",
+        &[],
+    ),
+    (
+        "89C57F98-2FE5-4DC0-89C1-F3AD0CEFF2BE",
+        "Ceph disk in creation",
+        "Ceph",
+        &[],
+    ),
+    (
+        "89C57F98-2FE5-4DC0-89C1-5EC00CEFF2BE",
+        "Ceph crypt disk in creation",
+        "Ceph",
+        &[],
+    ),
+    (
+        "CAFECAFE-9B03-4F30-B4C6-B4B80CEFF106",
+        "Ceph block",
+        "Ceph",
+        &[],
+    ),
+    (
+        "30CD0809-C2B2-499C-8879-2D6B78529876",
+        "Ceph block DB",
+        "Ceph",
+        &[],
+    ),
+    (
+        "5CE17FCE-4087-4169-B7FF-056CC58473F9",
+        "Ceph block write-ahead log",
+        "Ceph",
+        &[],
+    ),
+    (
+        "AA31E02A-400F-11DB-9590-000C2911D1B8",
+        "VMware VMFS",
+        "VMware ESX",
+        &["vmfs"],
+    ),
+    (
+        "9D275380-40AD-11DB-BF97-000C2911D1B8",
+        "VMware Diagnostic",
+        "VMware ESX",
+        &[],
+    ),
+    (
+        "381CFCCC-7288-11E0-92EE-000C2911D0B2",
+        "VMware Virtual SAN",
+        "VMware ESX",
+        &[],
+    ),
+    (
+        "77719A0C-A4A0-11E3-A47E-000C29745A24",
+        "VMware Virsto",
+        "VMware ESX",
+        &[],
+    ),
+    (
+        "9198EFFC-31C0-11DB-8F78-000C2911D1B8",
+        "VMware Reserved",
+        "VMware ESX",
+        &[],
+    ),
+    (
+        "824CC7A0-36A8-11E3-890A-952519AD3F61",
+        "OpenBSD data",
+        "OpenBSD",
+        &[],
+    ),
+    (
+        "CEF5A9AD-73BC-4601-89F3-CDEEEEE321A1",
+        "QNX6 file system",
+        "QNX",
+        &[],
+    ),
+    (
+        "C91818F9-8025-47AF-89D2-F030D7000C2C",
+        "Plan 9 partition",
+        "Plan 9",
+        &[],
+    ),
+    (
+        "2568845D-2332-4675-BC39-8FA5A4748D15",
+        "Android bootloader",
+        "Android",
+        &[],
+    ),
+    (
+        "114EAFFE-1552-4022-B26E-9B053604CF84",
+        "Android bootloader 2",
+        "Android",
+        &[],
+    ),
+    (
+        "49A4D17F-93A3-45C1-A0DE-F50B2EBE2599",
+        "Android boot",
+        "Android",
+        &[],
+    ),
+    (
+        "4177C722-9E92-4AAB-8644-43502BFD5506",
+        "Android recovery",
+        "Android",
+        &[],
+    ),
+    (
+        "EF32A33B-A409-486C-9141-9FFB711F6266",
+        "Android misc",
+        "Android",
+        &[],
+    ),
+    (
+        "20AC26BE-20B7-11E3-84C5-6CFDB94711E9",
+        "Android metadata",
+        "Android",
+        &[],
+    ),
+    (
+        "38F428E6-D326-425D-9140-6E0EA133647C",
+        "Android system",
+        "Android",
+        &[],
+    ),
+    (
+        "A893EF21-E428-470A-9E55-0668FD91A2D9",
+        "Android cache",
+        "Android",
+        &[],
+    ),
+    (
+        "DC76DDA9-5AC1-491C-AF42-A82591580C0D",
+        "Android data",
+        "Android",
+        &[],
+    ),
+    (
+        "EBC597D0-2053-4B15-8B64-E0AAC75F4DB1",
+        "Android persistent",
+        "Android",
+        &[],
+    ),
+    (
+        "C5A0AEEC-13EA-11E5-A1B1-001E67CA0C3C",
+        "Android vendor",
+        "Android",
+        &[],
+    ),
+    (
+        "BD59408B-4514-490D-BF12-9878D963F378",
+        "Android config",
+        "Android",
+        &[],
+    ),
+    (
+        "8F68CC74-C5E5-48DA-BE91-A0C8C15E9C80",
+        "Android factory",
+        "Android",
+        &[],
+    ),
+    (
+        "9FDAA6EF-4B3F-40D2-BA8D-BFF16BFB887B",
+        "Android factory (alt)",
+        "Android",
+        &[],
+    ),
+    (
+        "767941D0-2085-11E3-AD3B-6CFDB94711E9",
+        "Android fastboot/tertiary",
+        "Android",
+        &[],
+    ),
+    (
+        "AC6D7924-EB71-4DF8-B48D-E267B27148FF",
+        "Android OEM",
+        "Android",
+        &[],
+    ),
+    (
+        "734E5AFE-F61A-11E6-BC64-92361F002671",
+        "Atari TOS basic data",
+        "Atari TOS",
+        &[],
+    ),
+    (
+        "8C8F8EFF-AC95-4770-814A-21994F2DBC8F",
+        "VeraCrypt encrypted data",
+        "VeraCrypt",
+        &[],
+    ),
+    (
+        "90B6FF38-B98F-4358-A21F-48F35B4A8AD3",
+        "ArcaOS Type 1",
+        "OS/2",
+        &[],
+    ),
+    (
+        "7C5222BD-8F5D-4087-9C00-BF9843C7B58C",
+        "SPDK block device",
+        "SPDK",
+        &[],
+    ),
+    (
+        "4778ED65-BF42-45FA-9C5B-287A1DC4AAB1",
+        "barebox-state",
+        "barebox",
+        &[],
+    ),
+    (
+        "3DE21764-95BD-54BD-A5C3-4ABE786F38A8",
+        "U-Boot environment",
+        "U-Boot",
+        &[],
+    ),
+    (
+        "5B193300-FC78-40CD-8002-E86C45580B47",
+        "HiFive Unleashed FSBL",
+        "SiFive",
+        &[],
+    ),
+    (
+        "2E54B353-1271-4842-806F-E436D6AF6985",
+        "HiFive Unleashed BBL",
+        "SiFive",
+        &[],
+    ),
+];
+
+// https://uapi-group.org/specifications/specs/discoverable_partitions_specification/
+// Architecture, alias suffix, GUIDs of root, /usr, root verity, /usr verity, root verity signature and
+// /usr verity signature partitions.
+const DISCOVERABLE_PARTITION_ARCHITECTURES: &[(&str, &str, [&str; 6])] = &[
+    (
+        "x86",
+        "x86",
+        [
+            "44479540-F297-41B2-9AF7-D131D5F0458A",
+            "75250D76-8CC6-458E-BD66-BD47CC81A812",
+            "D13C5D3B-B5D1-422A-B29F-9454FDC89D76",
+            "8F461B0D-14EE-4E81-9AA9-049B6FB97ABD",
+            "5996FC05-109C-48DE-808B-23FA0830B676",
+            "974A71C0-DE41-43C3-BE5D-5C5CCD1AD2C0",
+        ],
+    ),
+    (
+        "x86-64",
+        "x86-64",
+        [
+            "4F68BCE3-E8CD-4DB1-96E7-FBCAF984B709",
+            "8484680C-9521-48C6-9C11-B0720656F69E",
+            "2C7357ED-EBD2-46D9-AEC1-23D437EC2BF5",
+            "77FF5F63-E7B6-4633-ACF4-1565B864C0E6",
+            "41092B05-9FC8-4523-994F-2DEF0408B176",
+            "E7BB33FB-06CF-4E81-8273-E543B413E2E2",
+        ],
+    ),
+    (
+        "Alpha",
+        "alpha",
+        [
+            "6523F8AE-3EB1-4E2A-A05A-18B695AE656F",
+            "E18CF08C-33EC-4C0D-8246-C6C6FB3DA024",
+            "FC56D9E9-E6E5-4C06-BE32-E74407CE09A5",
+            "8CCE0D25-C0D0-4A44-BD87-46331BF1DF67",
+            "D46495B7-A053-414F-80F7-700C99921EF8",
+            "5C6E1C76-076A-457A-A0FE-F3B4CD21CE6E",
+        ],
+    ),
+    (
+        "ARC",
+        "arc",
+        [
+            "D27F46ED-2919-4CB8-BD25-9531F3C16534",
+            "7978A683-6316-4922-BBEE-38BFF5A2FECC",
+            "24B2D975-0F97-4521-AFA1-CD531E421B8D",
+            "FCA0598C-D880-4591-8C16-4EDA05C7347C",
+            "143A70BA-CBD3-4F06-919F-6C05683A78BC",
+            "94F9A9A1-9971-427A-A400-50CB297F0F35",
+        ],
+    ),
+    (
+        "ARM",
+        "arm",
+        [
+            "69DAD710-2CE4-4E3C-B16C-21A1D49ABED3",
+            "7D0359A3-02B3-4F0A-865C-654403E70625",
+            "7386CDF2-203C-47A9-A498-F2ECCE45A2D6",
+            "C215D751-7BCD-4649-BE90-6627490A4C05",
+            "42B0455F-EB11-491D-98D3-56145BA9D037",
+            "D7FF812F-37D1-4902-A810-D76BA57B975A",
+        ],
+    ),
+    (
+        "ARM-64",
+        "arm64",
+        [
+            "B921B045-1DF0-41C3-AF44-4C6F280D3FAE",
+            "B0E01050-EE5F-4390-949A-9101B17104E9",
+            "DF3300CE-D69F-4C92-978C-9BFB0F38D820",
+            "6E11A4E7-FBCA-4DED-B9E9-E1A512BB664E",
+            "6DB69DE6-29F4-4758-A7A5-962190F00CE3",
+            "C23CE4FF-44BD-4B00-B2D4-B41B3419E02A",
+        ],
+    ),
+    (
+        "IA-64",
+        "ia64",
+        [
+            "993D8D3D-F80E-4225-855A-9DAF8ED7EA97",
+            "4301D2A6-4E3B-4B2A-BB94-9E0B2C4225EA",
+            "86ED10D5-B607-45BB-8957-D350F23D0571",
+            "6A491E03-3BE7-4545-8E38-83320E0EA880",
+            "E98B36EE-32BA-4882-9B12-0CE14655F46A",
+            "8DE58BC2-2A43-460D-B14E-A76E4A17B47F",
+        ],
+    ),
+    (
+        "LoongArch-64",
+        "loongarch64",
+        [
+            "77055800-792C-4F94-B39A-98C91B762BB6",
+            "E611C702-575C-4CBE-9A46-434FA0BF7E3F",
+            "F3393B22-E9AF-4613-A948-9D3BFBD0C535",
+            "F46B2C26-59AE-48F0-9106-C50ED47F673D",
+            "5AFB67EB-ECC8-4F85-AE8E-AC1E7C50E7D0",
+            "B024F315-D330-444C-8461-44BBDE524E99",
+        ],
+    ),
+    (
+        "MIPS-32 LE",
+        "mips-le",
+        [
+            "37C58C8A-D913-4156-A25F-48B1B64E07F0",
+            "0F4868E9-9952-4706-979F-3ED3A473E947",
+            "D7D150D2-2A04-4A33-8F12-16651205FF7B",
+            "46B98D8D-B55C-4E8F-AAB3-37FCA7F80752",
+            "C919CC1F-4456-4EFF-918C-F75E94525CA5",
+            "3E23CA0B-A4BC-4B4E-8087-5AB6A26AA8A9",
+        ],
+    ),
+    (
+        "MIPS-64 LE",
+        "mips64-le",
+        [
+            "700BDA43-7A34-4507-B179-EEB93D7A7CA3",
+            "C97C1F32-BA06-40B4-9F22-236061B08AA8",
+            "16B417F8-3E06-4F57-8DD2-9B5232F41AA6",
+            "3C3D61FE-B5F3-414D-BB71-8739A694A4EF",
+            "904E58EF-5C65-4A31-9C57-6AF5FC7C5DE7",
+            "F2C2C7EE-ADCC-4351-B5C6-EE9816B66E16",
+        ],
+    ),
+    (
+        "PPC",
+        "ppc",
+        [
+            "1DE3F1EF-FA98-47B5-8DCD-4A860A654D78",
+            "7D14FEC5-CC71-415D-9D6C-06BF0B3C3EAF",
+            "98CFE649-1588-46DC-B2F0-ADD147424925",
+            "DF765D00-270E-49E5-BC75-F47BB2118B09",
+            "1B31B5AA-ADD9-463A-B2ED-BD467FC857E7",
+            "7007891D-D371-4A80-86A4-5CB875B9302E",
+        ],
+    ),
+    (
+        "PPC64",
+        "ppc64",
+        [
+            "912ADE1D-A839-4913-8964-A10EEE08FBD2",
+            "2C9739E2-F068-46B3-9FD0-01C5A9AFBCCA",
+            "9225A9A3-3C19-4D89-B4F6-EEFF88F17631",
+            "BDB528A5-A259-475F-A87D-DA53FA736A07",
+            "F5E2C20C-45B2-4FFA-BCE9-2A60737E1AAF",
+            "0B888863-D7F8-4D9E-9766-239FCE4D58AF",
+        ],
+    ),
+    (
+        "PPC64LE",
+        "ppc64-le",
+        [
+            "C31C45E6-3F39-412E-80FB-4809C4980599",
+            "15BB03AF-77E7-4D4A-B12B-C0D084F7491C",
+            "906BD944-4589-4AAE-A4E4-DD983917446A",
+            "EE2B9983-21E8-4153-86D9-B6901A54D1CE",
+            "D4A236E7-E873-4C07-BF1D-BF6CF7F1C3C6",
+            "C8BFBD1E-268E-4521-8BBA-BF314C399557",
+        ],
+    ),
+    (
+        "RISC-V-32",
+        "riscv32",
+        [
+            "60D5A7FE-8E7D-435C-B714-3DD8162144E1",
+            "B933FB22-5C3F-4F91-AF90-E2BB0FA50702",
+            "AE0253BE-1167-4007-AC68-43926C14C5DE",
+            "CB1EE4E3-8CD0-4136-A0A4-AA61A32E8730",
+            "3A112A75-8729-4380-B4CF-764D79934448",
+            "C3836A13-3137-45BA-B583-B16C50FE5EB4",
+        ],
+    ),
+    (
+        "RISC-V-64",
+        "riscv64",
+        [
+            "72EC70A6-CF74-40E6-BD49-4BDA08E8F224",
+            "BEAEC34B-8442-439B-A40B-984381ED097D",
+            "B6ED5582-440B-4209-B8DA-5FF7C419EA3D",
+            "8F1056BE-9B05-47C4-81D6-BE53128E5B54",
+            "EFE0F087-EA8D-4469-821A-4C2A96A8386A",
+            "D2F9000A-7A18-453F-B5CD-4D32F77A7B32",
+        ],
+    ),
+    (
+        "S390",
+        "s390",
+        [
+            "08A7ACEA-624C-4A20-91E8-6E0FA67D23F9",
+            "CD0F869B-D0FB-4CA0-B141-9EA87CC78D66",
+            "7AC63B47-B25C-463B-8DF8-B4A94E6C90E1",
+            "B663C618-E7BC-4D6D-90AA-11B756BB1797",
+            "3482388E-4254-435A-A241-766A065F9960",
+            "17440E4F-A8D0-467F-A46E-3912AE6EF2C5",
+        ],
+    ),
+    (
+        "S390X",
+        "s390x",
+        [
+            "5EEAD9A9-FE09-4A1E-A1D7-520D00531306",
+            "8A4F5770-50AA-4ED3-874A-99B710DB6FEA",
+            "B325BFBE-C7BE-4AB8-8357-139E652D2F6B",
+            "31741CC4-1A2A-4111-A581-E00B447D2D06",
+            "C80187A5-73A3-491A-901A-017C3FA953E9",
+            "3F324816-667B-46AE-86EE-9B0C0C6C11B4",
+        ],
+    ),
+    (
+        "TILE-Gx",
+        "tilegx",
+        [
+            "C50CDD70-3862-4CC3-90E1-809A8C93EE2C",
+            "55497029-C7C1-44CC-AA39-815ED1558630",
+            "966061EC-28E4-4B2E-B4A5-1F0A825A1D84",
+            "2FB4BF56-07FA-42DA-8132-6B139F2026AE",
+            "B3671439-97B0-4A53-90F7-2D5A8F3AD47B",
+            "4EDE75E2-6CCC-4CC8-B9C7-70334B087510",
+        ],
+    ),
+];
+
+// Discoverable Partitions Specification types that aren't tied to an architecture: ESP, XBOOTLDR, swap,
+// /home, /srv, /var, /var/tmp and per user home
+const DISCOVERABLE_PARTITION_TYPES: &[&str] = &[
+    "C12A7328-F81F-11D2-BA4B-00A0C93EC93B",
+    "BC13C2FF-59E6-4262-A352-B275FD6F7172",
+    "0657FD6D-A4AB-43C4-84E5-0933C84B4F4F",
+    "933AC7E1-2EB4-4F13-B844-0E14E2AEF915",
+    "3B8F8425-20E0-4F3B-907F-1A25A76F98E8",
+    "4D21B016-B534-45C2-A9FB-5C16E091FD2D",
+    "7EC6F557-3BC5-4ACA-B293-16EF5DF639D1",
+    "773F91EF-66D4-49B5-BD83-D683BF40AD16",
+];
+
+/// A partition type known to the registry, identified by an MBR type byte or a GPT type GUID.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PartitionTypeCode {
+    Mbr(u8),
+    Gpt(Guid),
+}
+
+impl Display for PartitionTypeCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PartitionTypeCode::Mbr(code) => write!(f, "{:#04x}", code),
+            PartitionTypeCode::Gpt(guid) => write!(f, "{}", guid),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PartitionType {
+    pub code: PartitionTypeCode,
+    pub name: String,
+    pub os_family: String,
+    pub aliases: Vec<String>,
+}
+
+impl PartitionType {
+    fn new(code: PartitionTypeCode, name: &str, os_family: &str, aliases: &[&str]) -> Self {
+        Self {
+            code,
+            name: name.into(),
+            os_family: os_family.into(),
+            aliases: aliases.iter().map(|alias| alias.to_string()).collect(),
+        }
+    }
+
+    // Names match case insensitively, aliases exactly since single letter aliases differ by case.
+    fn matches(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name) || self.aliases.iter().any(|alias| alias == name)
+    }
+}
+
+#[derive(Debug)]
+pub struct PartitionTypeRegistry {
+    types: Vec<PartitionType>,
+}
+
+impl PartitionTypeRegistry {
+    fn builtin() -> Self {
+        let mut types: Vec<PartitionType> = MBR_PARTITION_TYPES
+            .iter()
+            .map(|(code, name, os_family, aliases)| {
+                PartitionType::new(PartitionTypeCode::Mbr(*code), name, os_family, aliases)
+            })
+            .collect();
+
+        let gpt_type = |guid: &str, name: &str, os_family: &str, aliases: &[&str]| {
+            let guid = guid
+                .parse::<Guid>()
+                .expect("Built-in partition type GUIDs are valid");
+            PartitionType::new(PartitionTypeCode::Gpt(guid), name, os_family, aliases)
+        };
+        for (guid, name, os_family, aliases) in GPT_PARTITION_TYPES {
+            types.push(gpt_type(guid, name, os_family, aliases));
+        }
+        for (architecture, suffix, guids) in DISCOVERABLE_PARTITION_ARCHITECTURES {
+            let kinds = [
+                ("root", "root", ""),
+                ("/usr", "usr", ""),
+                ("root verity", "root", "-verity"),
+                ("/usr verity", "usr", "-verity"),
+                ("root verity signature", "root", "-verity-sig"),
+                ("/usr verity signature", "usr", "-verity-sig"),
+            ];
+            for (guid, (kind, alias_prefix, alias_suffix)) in guids.iter().zip(kinds) {
+                let name = format!("Linux {} ({})", kind, architecture);
+                let alias = format!("{}-{}{}", alias_prefix, suffix, alias_suffix);
+                types.push(gpt_type(guid, &name, "Linux", &[&alias]));
+            }
+        }
+        Self { types }
+    }
+
+    /// Adds the partition types of a CSV file with the columns `scheme,code,name,os_family,aliases`, `scheme`
+    /// being `mbr` or `gpt` and `aliases` a space separated list. User types take precedence over built-in ones.
+    pub fn load_csv(&mut self, path: &Path) -> io::Result<()> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .comment(Some(b'#'))
+            .from_path(path)
+            .map_err(|error| invalid(error.to_string()))?;
+
+        let mut user_types = Vec::new();
+        for record in reader.records() {
+            let record = record.map_err(|error| invalid(error.to_string()))?;
+            let field = |index: usize| record.get(index).unwrap_or("");
+            let code = match field(0).to_ascii_lowercase().as_str() {
+                "mbr" => PartitionTypeCode::Mbr(
+                    u8::from_str_radix(field(1).trim_start_matches("0x"), 16)
+                        .map_err(|_| invalid(format!("Invalid MBR type code `{}`", field(1))))?,
+                ),
+                "gpt" => PartitionTypeCode::Gpt(
+                    field(1)
+                        .parse::<Guid>()
+                        .map_err(|error| invalid(error.to_string()))?,
+                ),
+                scheme => return Err(invalid(format!("Unknown partition scheme `{}`", scheme))),
+            };
+            let aliases: Vec<&str> = field(4).split_whitespace().collect();
+            user_types.push(PartitionType::new(code, field(2), field(3), &aliases));
+        }
+        user_types.append(&mut self.types);
+        self.types = user_types;
+        Ok(())
+    }
+
+    pub fn lookup(&self, code: &PartitionTypeCode) -> Option<&PartitionType> {
+        self.types
+            .iter()
+            .find(|partition_type| partition_type.code == *code)
+    }
+
+    /// Finds the MBR type byte for a name, an alias or a hexadecimal code (`83`, `0x83`). Names and aliases are
+    /// tried first so that sfdisk's `E` shortcut is the extended partition type rather than 0x0e.
+    pub fn find_mbr(&self, name: &str) -> Option<u8> {
+        let name = name.trim();
+        let found = self
+            .types
+            .iter()
+            .find_map(|partition_type| match partition_type.code {
+                PartitionTypeCode::Mbr(code) if partition_type.matches(name) => Some(code),
+                _ => None,
+            });
+        let code = name.strip_prefix("0x").unwrap_or(name);
+        found.or_else(|| match code.len() {
+            1 | 2 => u8::from_str_radix(code, 16).ok(),
+            _ => None,
+        })
+    }
+
+    /// Finds the GPT type GUID for a GUID, a name or an alias.
+    pub fn find_gpt(&self, name: &str) -> Option<Guid> {
+        let name = name.trim();
+        if let Ok(guid) = name.parse::<Guid>() {
+            return Some(guid);
+        }
+        self.types
+            .iter()
+            .find_map(|partition_type| match partition_type.code {
+                PartitionTypeCode::Gpt(guid) if partition_type.matches(name) => Some(guid),
+                _ => None,
+            })
+    }
+}
+
+/// Whether the type is defined by the Discoverable Partitions Specification, whose partitions
+/// use attribute bits 59, 60 and 63.
+pub fn is_discoverable_partition_type(guid: &Guid) -> bool {
+    let guid = guid.to_string();
+    DISCOVERABLE_PARTITION_TYPES.contains(&guid.as_str())
+        || DISCOVERABLE_PARTITION_ARCHITECTURES
+            .iter()
+            .any(|(_, _, guids)| guids.contains(&guid.as_str()))
+}
+
+static REGISTRY: OnceLock<PartitionTypeRegistry> = OnceLock::new();
+
+/// Sets up the registry with the built-in types and the types of the optional user supplied CSV file.
+/// Must be called before the first lookup for the user types to be used.
+pub fn init_registry(user_types: Option<&Path>) -> io::Result<()> {
+    let mut registry = PartitionTypeRegistry::builtin();
+    if let Some(path) = user_types {
+        registry.load_csv(path)?;
+    }
+    let _ = REGISTRY.set(registry);
+    Ok(())
+}
+
+pub fn registry() -> &'static PartitionTypeRegistry {
+    REGISTRY.get_or_init(PartitionTypeRegistry::builtin)
+}
+
+pub fn mbr_type_name(code: u8) -> String {
+    registry()
+        .lookup(&PartitionTypeCode::Mbr(code))
+        .map(|partition_type| partition_type.name.clone())
+        .unwrap_or_else(|| UNKNOWN_PARTITION_TYPE.into())
+}
+
+pub fn gpt_type_name(guid: &Guid) -> String {
+    registry()
+        .lookup(&PartitionTypeCode::Gpt(*guid))
+        .map(|partition_type| partition_type.name.clone())
+        .unwrap_or_else(|| UNKNOWN_PARTITION_TYPE.into())
+}
+
+pub fn display_partition_types() {
+    let mut table = Table::new();
+    table.add_row(row!["Scheme", "Code", "Name", "OS", "Aliases"]);
+    for partition_type in registry().types.iter() {
+        let scheme = match partition_type.code {
+            PartitionTypeCode::Mbr(_) => "MBR",
+            PartitionTypeCode::Gpt(_) => "GPT",
+        };
+        table.add_row(row![
+            scheme,
+            partition_type.code,
+            partition_type.name,
+            partition_type.os_family,
+            partition_type.aliases.join(", ")
+        ]);
+    }
+    table.printstd();
+}
+
+#[test]
+fn test_partition_type_registry() {
+    let registry = PartitionTypeRegistry::builtin();
+    assert_eq!(registry.find_mbr("0x83"), Some(0x83));
+    assert_eq!(registry.find_mbr("ef"), Some(0xef));
+    assert_eq!(registry.find_mbr("linux-extended"), Some(0x85));
+    assert_eq!(registry.find_mbr("E"), Some(0x05));
+    assert_eq!(registry.find_mbr("0e"), Some(0x0e));
+    assert_eq!(registry.find_mbr("Hidden W95 FAT32 (LBA)"), Some(0x1c));
+    assert_eq!(
+        registry.find_gpt("esp").map(|guid| guid.to_string()),
+        Some("C12A7328-F81F-11D2-BA4B-00A0C93EC93B".into())
+    );
+    assert_eq!(
+        registry
+            .find_gpt("root-x86-64")
+            .map(|guid| guid.to_string()),
+        Some("4F68BCE3-E8CD-4DB1-96E7-FBCAF984B709".into())
+    );
+    let usr_verity = registry
+        .find_gpt("usr-arm64-verity")
+        .and_then(|guid| registry.lookup(&PartitionTypeCode::Gpt(guid)))
+        .unwrap();
+    assert_eq!(usr_verity.name, "Linux /usr verity (ARM-64)");
+    assert!(registry.find_gpt("not-a-type").is_none());
+
+    // Every code and alias maps back to a single type
+    for partition_type in registry.types.iter() {
+        for alias in partition_type.aliases.iter() {
+            let found = match partition_type.code {
+                PartitionTypeCode::Mbr(_) => registry.find_mbr(alias).map(PartitionTypeCode::Mbr),
+                PartitionTypeCode::Gpt(_) => registry.find_gpt(alias).map(PartitionTypeCode::Gpt),
+            };
+            assert_eq!(found, Some(partition_type.code), "alias `{}`", alias);
+        }
+    }
+}
+
+#[test]
+fn test_partition_type_registry_user_types() {
+    let path = std::env::temp_dir().join(format!("parttable_types_{}.csv", std::process::id()));
+    std::fs::write(
+        &path,
+        "scheme,code,name,os_family,aliases\n\
+         mbr,0x83,Linux native,Linux,native\n\
+         gpt,11111111-2222-3333-4444-555555555555,Test data,Test,test-data t\n",
+    )
+    .unwrap();
+    let mut registry = PartitionTypeRegistry::builtin();
+    registry.load_csv(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(
+        registry.lookup(&PartitionTypeCode::Mbr(0x83)).unwrap().name,
+        "Linux native"
+    );
+    assert_eq!(registry.find_mbr("native"), Some(0x83));
+    let guid = registry.find_gpt("t").unwrap();
+    assert_eq!(guid.to_string(), "11111111-2222-3333-4444-555555555555");
+    assert_eq!(
+        registry
+            .lookup(&PartitionTypeCode::Gpt(guid))
+            .unwrap()
+            .os_family,
+        "Test"
+    );
+}
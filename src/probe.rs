@@ -0,0 +1,406 @@
+use crate::{
+    apm::{is_apm_disk, parse_apm},
+    bytestream::{ByteStream, SECTOR_SIZE},
+    gpt::{
+        parse_gpt_entries, parse_validated_gpt_header, valid_invalid, GptHeaderCopy, GPT_SIGNATURE,
+    },
+    mbr::{parse_mbr, BOOT_SIGNATURE, GPT_PARTITION_TYPE},
+};
+use prettytable::{row, Table};
+use std::{fmt::Display, io, path::Path};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PartitionScheme {
+    Apm,
+    Mbr,
+    Gpt,
+}
+
+impl Display for PartitionScheme {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PartitionScheme::Apm => write!(f, "APM"),
+            PartitionScheme::Mbr => write!(f, "MBR"),
+            PartitionScheme::Gpt => write!(f, "GPT"),
+        }
+    }
+}
+
+/// Sectors a partitioning scheme claims, either for a partition or for its own structures.
+#[derive(Debug, Clone)]
+struct Region {
+    name: String,
+    starting_lba: u64,
+    ending_lba: u64,
+    metadata: bool,
+}
+
+/// What probing the image for one partitioning scheme found.
+#[derive(Debug)]
+pub struct SchemeProbe {
+    pub scheme: PartitionScheme,
+    pub present: bool,
+    pub valid: bool,
+    // Whether the scheme describes partitions of its own, a protective MBR only shields the GPT
+    pub independent: bool,
+    // What was found, or why the scheme is invalid
+    pub details: String,
+    regions: Vec<Region>,
+}
+
+impl SchemeProbe {
+    fn new(scheme: PartitionScheme) -> Self {
+        Self {
+            scheme,
+            present: false,
+            valid: false,
+            independent: false,
+            details: String::new(),
+            regions: Vec::new(),
+        }
+    }
+}
+
+/// Every partitioning scheme of an image and the places where they disagree.
+#[derive(Debug)]
+pub struct DiskProbe {
+    pub schemes: Vec<SchemeProbe>,
+    pub conflicts: Vec<String>,
+}
+
+impl DiskProbe {
+    pub fn is_present(&self, scheme: PartitionScheme) -> bool {
+        self.schemes
+            .iter()
+            .any(|probe| probe.scheme == scheme && probe.present)
+    }
+
+    /// Number of schemes describing a layout of their own, more than one on hybrid images.
+    pub fn independent_schemes(&self) -> usize {
+        self.schemes
+            .iter()
+            .filter(|probe| probe.independent)
+            .count()
+    }
+}
+
+fn probe_apm(path: &Path) -> io::Result<SchemeProbe> {
+    let mut probe = SchemeProbe::new(PartitionScheme::Apm);
+    probe.present = is_apm_disk(path)?;
+    if !probe.present {
+        return Ok(probe);
+    }
+    match parse_apm(path) {
+        Ok(map) => {
+            let sectors_per_block = map.sectors_per_block();
+            probe.valid = map.warnings.is_empty();
+            probe.independent = true;
+            probe.details = format!(
+                "{} entries of {} byte blocks",
+                map.partitions.len(),
+                map.block_size
+            );
+            if let Some(warning) = map.warnings.first() {
+                probe.details = format!("{}, {}", probe.details, warning);
+            }
+            // The driver descriptor map shares sector 0 with the MBR on hybrid images, so only the map
+            // entries are claimed
+            probe.regions.push(Region {
+                name: "APM partition map entries".into(),
+                starting_lba: sectors_per_block,
+                ending_lba: (map.partitions.len() as u64 + 1) * sectors_per_block - 1,
+                metadata: true,
+            });
+            for (index, partition) in map.partitions.iter().enumerate() {
+                if partition.is_free()
+                    || partition.is_partition_map()
+                    || partition.size_in_sectors() == 0
+                {
+                    continue;
+                }
+                let starting_lba = partition.starting_lba() as u64 * sectors_per_block;
+                probe.regions.push(Region {
+                    name: format!(
+                        "APM partition {} ({})",
+                        index + 1,
+                        partition.partition_type()
+                    ),
+                    starting_lba,
+                    ending_lba: starting_lba
+                        + partition.size_in_sectors() as u64 * sectors_per_block
+                        - 1,
+                    metadata: false,
+                });
+            }
+        }
+        Err(error) => probe.details = error.to_string(),
+    }
+    Ok(probe)
+}
+
+fn probe_mbr(path: &Path) -> io::Result<SchemeProbe> {
+    let mut probe = SchemeProbe::new(PartitionScheme::Mbr);
+    let sector = ByteStream::new(path, SECTOR_SIZE, 0)?.read_raw(SECTOR_SIZE)?;
+    probe.present = sector[510..] == BOOT_SIGNATURE;
+    if !probe.present {
+        return Ok(probe);
+    }
+    match parse_mbr(path) {
+        Ok(root) => {
+            let partitions = root.partitions();
+            probe.valid = true;
+            probe.independent = !root.is_gpt() || root.is_hybrid();
+            probe.details = if root.is_hybrid() {
+                "Hybrid MBR".into()
+            } else if root.is_gpt() {
+                "Protective MBR".into()
+            } else {
+                format!("{} partitions", partitions.len())
+            };
+            for partition in partitions.iter().filter(|partition| {
+                !partition.is_extended() && partition.partition_type != GPT_PARTITION_TYPE
+            }) {
+                probe.regions.push(Region {
+                    name: format!("MBR partition {}", partition.number),
+                    starting_lba: partition.starting_lba,
+                    ending_lba: partition.ending_lba(),
+                    metadata: false,
+                });
+            }
+        }
+        Err(error) => probe.details = error.to_string(),
+    }
+    Ok(probe)
+}
+
+fn probe_gpt(path: &Path) -> io::Result<SchemeProbe> {
+    let mut probe = SchemeProbe::new(PartitionScheme::Gpt);
+    let validated = match parse_validated_gpt_header(path) {
+        Ok(validated) => validated,
+        Err(error) => {
+            // An unreadable header is reported like an invalid one instead of ending the probe
+            probe.present = ByteStream::new(path, SECTOR_SIZE, 1)
+                .and_then(|mut stream| stream.read_raw(SECTOR_SIZE))
+                .map(|sector| sector.starts_with(GPT_SIGNATURE.as_bytes()))
+                .unwrap_or(false);
+            if probe.present {
+                probe.details = error.to_string();
+            }
+            return Ok(probe);
+        }
+    };
+    let header = &validated.header;
+    probe.present = true;
+    let entries = match parse_gpt_entries(path, header, 0) {
+        Ok(entries) => entries,
+        Err(error) => {
+            probe.details = format!("{}, {}", validated.describe(), error);
+            return Ok(probe);
+        }
+    };
+    probe.valid = validated.copy == GptHeaderCopy::Primary;
+    probe.independent = true;
+    probe.details = format!("{}, {} partitions", validated.describe(), entries.len());
+
+    let entry_sectors = header.partition_entry_sectors();
+    if validated.copy == GptHeaderCopy::Primary {
+        probe.regions.push(Region {
+            name: "Primary GPT header".into(),
+            starting_lba: 1,
+            ending_lba: 1,
+            metadata: true,
+        });
+        let start = header.starting_lba_of_partition_entries();
+        probe.regions.push(Region {
+            name: "Primary GPT entry array".into(),
+            starting_lba: start,
+            ending_lba: start + entry_sectors.max(1) - 1,
+            metadata: true,
+        });
+    }
+    let backup_lba = header.current_lba().max(header.backup_lba());
+    probe.regions.push(Region {
+        name: "Backup GPT header and entry array".into(),
+        starting_lba: backup_lba.saturating_sub(entry_sectors),
+        ending_lba: backup_lba,
+        metadata: true,
+    });
+    for (index, entry) in entries.iter() {
+        probe.regions.push(Region {
+            name: format!("GPT partition {}", index + 1),
+            starting_lba: entry.starting_lba(),
+            ending_lba: entry.ending_lba(),
+            metadata: false,
+        });
+    }
+    Ok(probe)
+}
+
+/// Regions of different schemes sharing sectors. Partitions covering exactly the same sectors describe
+/// the same data and are fine, which is how hybrid images mirror their partitions.
+fn find_conflicts(schemes: &[SchemeProbe]) -> Vec<String> {
+    let mut conflicts = Vec::new();
+    for (index, first) in schemes.iter().enumerate() {
+        for second in schemes.iter().skip(index + 1) {
+            for a in first.regions.iter() {
+                for b in second.regions.iter() {
+                    let overlapping =
+                        a.starting_lba <= b.ending_lba && b.starting_lba <= a.ending_lba;
+                    let mirrored = !a.metadata
+                        && !b.metadata
+                        && a.starting_lba == b.starting_lba
+                        && a.ending_lba == b.ending_lba;
+                    if overlapping && !mirrored {
+                        conflicts.push(format!(
+                            "{} (LBA {}-{}) overlaps {} (LBA {}-{})",
+                            a.name,
+                            a.starting_lba,
+                            a.ending_lba,
+                            b.name,
+                            b.starting_lba,
+                            b.ending_lba
+                        ));
+                    }
+                }
+            }
+        }
+    }
+    conflicts
+}
+
+/// Probes the image for an APM, an MBR and a GPT independently of each other, as isohybrid and installer
+/// images carry all of them at once.
+pub fn probe_schemes(path: &Path) -> io::Result<DiskProbe> {
+    let schemes = vec![probe_apm(path)?, probe_mbr(path)?, probe_gpt(path)?];
+    let conflicts = find_conflicts(&schemes);
+    Ok(DiskProbe { schemes, conflicts })
+}
+
+pub fn display_disk_probe(probe: &DiskProbe) {
+    let mut table = Table::new();
+    table.add_row(row!["Scheme", "Present", "Valid", "Details"]);
+    for scheme in probe.schemes.iter() {
+        match scheme.present {
+            true => table.add_row(row![
+                scheme.scheme,
+                "Yes",
+                valid_invalid(scheme.valid),
+                scheme.details
+            ]),
+            false => table.add_row(row![scheme.scheme, "No", "", scheme.details]),
+        };
+    }
+    table.printstd();
+    for conflict in probe.conflicts.iter() {
+        println!("Conflict: {}", conflict);
+    }
+}
+
+#[test]
+fn test_probe_schemes() {
+    use crate::{
+        apm_edit::{create_apm, edit_apm, ApmEdit},
+        mbr::{write_mbr, MbrPartition},
+    };
+
+    // An isohybrid style image: a CD style APM and an MBR sharing sector 0, both describing the
+    // partition at LBA 2048-4095 and the MBR adding a second one overlapping it
+    let path = crate::bytestream::blank_test_image("probe", 8192);
+    create_apm(&path, 2048, 4).unwrap();
+    edit_apm(
+        &path,
+        &ApmEdit::Add {
+            starting_block: Some(512),
+            size: Some(2048),
+            partition_type: "Apple_HFS".into(),
+            name: "CD".into(),
+        },
+    )
+    .unwrap();
+    let partition = |number: u32, starting_lba: u64| MbrPartition {
+        number,
+        bootable: false,
+        partition_type: 0x83,
+        starting_lba,
+        num_sectors: 2048,
+    };
+    write_mbr(&path, None, &[partition(1, 2048), partition(2, 3072)]).unwrap();
+
+    let probe = probe_schemes(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let summary: Vec<(PartitionScheme, bool, bool)> = probe
+        .schemes
+        .iter()
+        .map(|scheme| (scheme.scheme, scheme.present, scheme.valid))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (PartitionScheme::Apm, true, true),
+            (PartitionScheme::Mbr, true, true),
+            (PartitionScheme::Gpt, false, false),
+        ]
+    );
+    assert_eq!(probe.independent_schemes(), 2);
+    assert_eq!(
+        probe.conflicts,
+        vec![
+            "APM partition 3 (Apple_HFS) (LBA 2048-4095) overlaps MBR partition 2 (LBA 3072-5119)"
+        ]
+    );
+}
+
+#[test]
+fn test_probe_invalid_gpt_entries() {
+    use crate::{bytestream::write_sectors_to_file, checksum::crc32};
+
+    // A GPT with 0 byte entries whose CRC32s are consistent next to a valid MBR
+    let path = crate::gpt::write_test_gpt("probe_gpt_entries", 8192);
+    let mut header = ByteStream::new(&path, SECTOR_SIZE, 1)
+        .unwrap()
+        .read_raw(SECTOR_SIZE)
+        .unwrap();
+    header[84..88].copy_from_slice(&0u32.to_le_bytes());
+    header[88..92].copy_from_slice(&crc32(&[]).to_le_bytes());
+    header[16..20].copy_from_slice(&[0; 4]);
+    let header_crc32 = crc32(&header[..92]);
+    header[16..20].copy_from_slice(&header_crc32.to_le_bytes());
+    write_sectors_to_file(&path, 1, &header).unwrap();
+
+    let probe = probe_schemes(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let gpt = &probe.schemes[2];
+    assert!(gpt.present);
+    assert!(!gpt.valid);
+    assert!(gpt
+        .details
+        .ends_with("Partition entry size of 0 bytes is smaller than 128 bytes"));
+    assert!(probe.schemes[1].present);
+}
+
+#[test]
+fn test_probe_damaged_gpt_headers() {
+    use crate::bytestream::write_sectors_to_file;
+
+    // Non UTF-8 garbage over the primary header leaves the backup in use
+    let path = crate::gpt::write_test_gpt("probe_gpt_headers", 8192);
+    write_sectors_to_file(&path, 1, &[0xFF, 0xFE, 0x80, 0xC3, 0x28, 0xA0, 0xA1, 0xE2]).unwrap();
+    let gpt = probe_schemes(&path).unwrap().schemes.remove(2);
+    assert!(gpt.present);
+    assert!(!gpt.valid);
+    assert!(gpt
+        .details
+        .starts_with("Primary GPT header is invalid (signature is not `EFI PART`), using backup"));
+
+    // With both copies damaged the signature still shows the GPT is there
+    let mut garbage = GPT_SIGNATURE.as_bytes().to_vec();
+    garbage.resize(SECTOR_SIZE, 0xFF);
+    write_sectors_to_file(&path, 1, &garbage).unwrap();
+    write_sectors_to_file(&path, 8191, &garbage).unwrap();
+    let probe = probe_schemes(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(probe.schemes[1].present);
+    assert!(probe.schemes[2].present);
+    assert!(!probe.schemes[2].valid);
+    assert!(!probe.schemes[2].details.is_empty());
+}
@@ -0,0 +1,310 @@
+use crate::{
+    bytestream::{image_size_in_sectors, write_sectors_to_file, ByteStream, SECTOR_SIZE},
+    gpt::{gpt_sectors, verify_gpt, GptHeader, GptHeaderCopy, MINIMUM_PARTITION_ENTRY_SIZE},
+    mbr::{parse_mbr, protective_mbr_sector, GPT_PARTITION_TYPE},
+};
+use std::{fs, io, path::Path};
+
+// Offset of the ending LBA inside a partition entry, after the type and unique GUIDs and the starting LBA
+const ENTRY_ENDING_LBA_OFFSET: usize = 40;
+
+/// Sectors about to be written by a repair.
+#[derive(Debug)]
+pub struct SectorWrite {
+    pub description: &'static str,
+    pub lba: u64,
+    pub bytes: Vec<u8>,
+}
+
+/// Everything a GPT repair writes, rebuilt from a single intact copy of the GPT.
+#[derive(Debug)]
+pub struct RepairPlan {
+    pub source: GptHeaderCopy,
+    pub old_headers: Vec<GptHeader>,
+    pub new_headers: Vec<GptHeader>,
+    pub writes: Vec<SectorWrite>,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Plans rebuilding both copies of the GPT from the `source` copy, or from whichever copy is intact when
+/// `source` is `None`. The backup is placed at the last LBA of the image, which relocates it when the image
+/// has grown, and the last usable LBA follows it. A plain protective MBR is resized to match.
+pub fn plan_gpt_repair(path: &Path, source: Option<GptHeaderCopy>) -> io::Result<RepairPlan> {
+    let verification = verify_gpt(path)?;
+    let source = match source {
+        Some(source) => source,
+        None if verification.primary.problem().is_none() => GptHeaderCopy::Primary,
+        None if verification.backup.problem().is_none() => GptHeaderCopy::Backup,
+        None => {
+            return Err(invalid(
+                "Neither the primary nor the backup GPT is intact".into(),
+            ))
+        }
+    };
+    let copy = match source {
+        GptHeaderCopy::Primary => &verification.primary,
+        GptHeaderCopy::Backup => &verification.backup,
+    };
+    if let Some(problem) = copy.problem() {
+        return Err(invalid(format!(
+            "The {} GPT can't be used to repair from: {}",
+            source, problem
+        )));
+    }
+    // Entries must hold at least the fields read below
+    if copy.header.size_single_partition_entry() < MINIMUM_PARTITION_ENTRY_SIZE {
+        return Err(invalid(format!(
+            "The {} GPT can't be used to repair from: partition entry size of {} bytes is smaller than {} bytes",
+            source,
+            copy.header.size_single_partition_entry(),
+            MINIMUM_PARTITION_ENTRY_SIZE
+        )));
+    }
+    let entry_array = copy.entry_array.clone().unwrap_or_default();
+
+    let last_lba = verification.last_lba;
+    let entry_sectors = copy.header.partition_entry_sectors();
+    if 2 + entry_sectors > copy.header.first_usable_lba() {
+        return Err(invalid(format!(
+            "Partition entry array of {} sectors does not fit before the first usable LBA {}",
+            entry_sectors,
+            copy.header.first_usable_lba()
+        )));
+    }
+    let last_usable_lba = last_lba.saturating_sub(entry_sectors + 1);
+    let entry_size = copy.header.size_single_partition_entry() as usize;
+    if let Some(ending_lba) = entry_array
+        .chunks(entry_size)
+        .filter(|entry| entry[..16].iter().any(|byte| *byte != 0))
+        .map(|entry| {
+            let mut ending_lba = [0u8; 8];
+            ending_lba
+                .copy_from_slice(&entry[ENTRY_ENDING_LBA_OFFSET..ENTRY_ENDING_LBA_OFFSET + 8]);
+            u64::from_le_bytes(ending_lba)
+        })
+        .max()
+        .filter(|ending_lba| *ending_lba > last_usable_lba)
+    {
+        return Err(invalid(format!(
+            "A partition ends at LBA {} past the end of the usable area {} of this image",
+            ending_lba, last_usable_lba
+        )));
+    }
+
+    let mut primary = copy.header.to_primary(last_lba, last_usable_lba);
+    primary.update_crc32(&entry_array);
+    let backup = primary.to_backup();
+
+    let descriptions = [
+        "Primary GPT header",
+        "Primary partition entry array",
+        "Backup GPT header",
+        "Backup partition entry array",
+    ];
+    let mut writes: Vec<SectorWrite> = gpt_sectors(&primary, &entry_array)
+        .into_iter()
+        .zip(descriptions)
+        .map(|((lba, bytes), description)| SectorWrite {
+            description,
+            lba,
+            bytes,
+        })
+        .collect();
+
+    // Only a protective MBR is resized, a hybrid MBR describes partitions and is left alone
+    let partitions = parse_mbr(path)?.partitions();
+    if let [partition] = partitions.as_slice() {
+        if partition.partition_type == GPT_PARTITION_TYPE && partition.starting_lba == 1 {
+            let sector = protective_mbr_sector(path, image_size_in_sectors(path)?)?;
+            writes.insert(
+                0,
+                SectorWrite {
+                    description: "Protective MBR",
+                    lba: 0,
+                    bytes: sector,
+                },
+            );
+        }
+    }
+
+    Ok(RepairPlan {
+        source,
+        old_headers: vec![verification.primary.header, verification.backup.header],
+        new_headers: vec![primary, backup],
+        writes,
+    })
+}
+
+fn read_current(path: &Path, write: &SectorWrite) -> io::Result<Vec<u8>> {
+    let sectors = write.bytes.len().div_ceil(SECTOR_SIZE);
+    let mut bytes = ByteStream::new(path, SECTOR_SIZE, 0)?
+        .read_raw_sectors_from_file(write.lba as usize, sectors)?;
+    bytes.truncate(write.bytes.len());
+    Ok(bytes)
+}
+
+/// Prints what a repair would change: the header fields that differ and how many sectors of each region change.
+pub fn display_repair_plan(path: &Path, plan: &RepairPlan) -> io::Result<()> {
+    println!("Repairing from the {} GPT\n", plan.source);
+    let copies = ["Primary", "Backup"];
+    for ((old, new), copy) in plan
+        .old_headers
+        .iter()
+        .zip(plan.new_headers.iter())
+        .zip(copies)
+    {
+        for ((name, old_value), (_, new_value)) in old.fields().into_iter().zip(new.fields()) {
+            if old_value != new_value {
+                println!("{} {}: {} -> {}", copy, name, old_value, new_value);
+            }
+        }
+    }
+    println!();
+
+    for write in plan.writes.iter() {
+        let current = read_current(path, write)?;
+        let changed = current
+            .chunks(SECTOR_SIZE)
+            .zip(write.bytes.chunks(SECTOR_SIZE))
+            .filter(|(current, new)| current != new)
+            .count();
+        let sectors = write.bytes.len().div_ceil(SECTOR_SIZE);
+        let end = write.lba + sectors as u64 - 1;
+        if changed == 0 {
+            println!(
+                "{} (LBA {}-{}): unchanged",
+                write.description, write.lba, end
+            );
+        } else {
+            println!(
+                "{} (LBA {}-{}): {} of {} sectors change",
+                write.description, write.lba, end, changed, sectors
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Saves the sectors a repair overwrites. Every region is stored as its LBA and byte length, both little
+/// endian u64, followed by the bytes currently on disk.
+pub fn save_overwritten_sectors(
+    path: &Path,
+    plan: &RepairPlan,
+    save_path: &Path,
+) -> io::Result<()> {
+    let mut saved = Vec::new();
+    for write in plan.writes.iter() {
+        let current = read_current(path, write)?;
+        saved.extend_from_slice(&write.lba.to_le_bytes());
+        saved.extend_from_slice(&(current.len() as u64).to_le_bytes());
+        saved.extend_from_slice(&current);
+    }
+    fs::write(save_path, saved)
+}
+
+pub fn apply_repair_plan(path: &Path, plan: &RepairPlan) -> io::Result<()> {
+    for write in plan.writes.iter() {
+        write_sectors_to_file(path, write.lba, &write.bytes)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_repair_grown_image() {
+    let path = crate::gpt::write_test_gpt("repair_grown", 8192);
+    let file = fs::OpenOptions::new().write(true).open(&path).unwrap();
+    file.set_len(16384 * SECTOR_SIZE as u64).unwrap();
+
+    let plan = plan_gpt_repair(&path, None).unwrap();
+    assert_eq!(plan.source, GptHeaderCopy::Primary);
+    assert!(plan
+        .writes
+        .iter()
+        .any(|write| write.description == "Backup GPT header" && write.lba == 16383));
+    assert_eq!(plan.new_headers[0].last_usable_lba(), 16350);
+
+    let save_path = path.with_extension("bak");
+    save_overwritten_sectors(&path, &plan, &save_path).unwrap();
+    let saved = fs::read(&save_path).unwrap();
+    fs::remove_file(&save_path).unwrap();
+    // Protective MBR, both headers and both 32 sector entry arrays, each with a 16 byte prefix
+    assert_eq!(saved.len(), 5 * 16 + 3 * SECTOR_SIZE + 2 * 32 * SECTOR_SIZE);
+
+    apply_repair_plan(&path, &plan).unwrap();
+    let verification = verify_gpt(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(verification.issues.is_empty(), "{:?}", verification.issues);
+}
+
+#[test]
+fn test_repair_from_backup() {
+    let path = crate::gpt::write_test_gpt("repair_backup", 8192);
+    write_sectors_to_file(&path, 1, &[0u8; SECTOR_SIZE]).unwrap();
+    assert!(plan_gpt_repair(&path, Some(GptHeaderCopy::Primary)).is_err());
+
+    let plan = plan_gpt_repair(&path, None).unwrap();
+    assert_eq!(plan.source, GptHeaderCopy::Backup);
+    apply_repair_plan(&path, &plan).unwrap();
+    let verification = verify_gpt(&path).unwrap();
+    let partitions = crate::gpt::parse_gpt(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(verification.issues.is_empty(), "{:?}", verification.issues);
+    assert_eq!(partitions[0].1.name(), "data");
+}
+
+#[test]
+fn test_repair_over_garbage_backup() {
+    let path = crate::gpt::write_test_gpt("repair_garbage", 8192);
+    // The backup's partition entry LBA is garbage, the primary is intact
+    let mut sector = ByteStream::new(&path, SECTOR_SIZE, 8191)
+        .unwrap()
+        .read_raw(SECTOR_SIZE)
+        .unwrap();
+    sector[72..80].copy_from_slice(&[0xFF; 8]);
+    write_sectors_to_file(&path, 8191, &sector).unwrap();
+
+    let plan = plan_gpt_repair(&path, Some(GptHeaderCopy::Primary)).unwrap();
+    apply_repair_plan(&path, &plan).unwrap();
+    let verification = verify_gpt(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(verification.issues.is_empty(), "{:?}", verification.issues);
+}
+
+#[test]
+fn test_repair_rejects_small_entries() {
+    let path = crate::gpt::write_test_gpt("repair_small_entries", 8192);
+    // A primary with 0 byte entries whose CRC32s are consistent
+    let mut header = ByteStream::new(&path, SECTOR_SIZE, 1)
+        .unwrap()
+        .read_raw(SECTOR_SIZE)
+        .unwrap();
+    header[84..88].copy_from_slice(&0u32.to_le_bytes());
+    header[88..92].copy_from_slice(&crate::checksum::crc32(&[]).to_le_bytes());
+    header[16..20].copy_from_slice(&[0; 4]);
+    let header_crc32 = crate::checksum::crc32(&header[..92]);
+    header[16..20].copy_from_slice(&header_crc32.to_le_bytes());
+    write_sectors_to_file(&path, 1, &header).unwrap();
+
+    let error = plan_gpt_repair(&path, Some(GptHeaderCopy::Primary)).unwrap_err();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(
+        error.to_string(),
+        "The primary GPT can't be used to repair from: partition entry size of 0 bytes is smaller than 128 bytes"
+    );
+}
+
+#[test]
+fn test_repair_over_non_ascii_primary() {
+    let path = crate::gpt::write_test_gpt("repair_non_ascii", 8192);
+    write_sectors_to_file(&path, 1, &[0xFF, 0xFE, 0x80, 0xC3, 0x28, 0xA0, 0xA1, 0xE2]).unwrap();
+
+    let plan = plan_gpt_repair(&path, None).unwrap();
+    assert_eq!(plan.source, GptHeaderCopy::Backup);
+    apply_repair_plan(&path, &plan).unwrap();
+    let verification = verify_gpt(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(verification.issues.is_empty(), "{:?}", verification.issues);
+}
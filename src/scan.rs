@@ -0,0 +1,303 @@
+use crate::{
+    bytestream::{image_size_in_sectors, SECTOR_SIZE},
+    gpt::{
+        display_gpt, parse_gpt, parse_gpt_entries, parse_validated_gpt_header, valid_invalid,
+        GptCopy, GptHeaderCopy, GptPartitionTableEntry, GPT_SIGNATURE,
+    },
+    mbr::{parse_mbr, GPT_PARTITION_TYPE},
+};
+use prettytable::{row, Table};
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{self, BufReader, Read},
+    path::Path,
+};
+
+// Sectors read at once while looking for signatures
+const SCAN_CHUNK_SECTORS: usize = 2048;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GptCandidateRole {
+    /// The GPT of the image, protected by its MBR
+    InUse,
+    /// A GPT of the image the MBR doesn't point to anymore, or a stale copy left behind when the image was resized
+    Orphaned,
+    /// The GPT of a disk stored inside a partition, such as a VM disk
+    Nested,
+}
+
+impl Display for GptCandidateRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GptCandidateRole::InUse => write!(f, "In use"),
+            GptCandidateRole::Orphaned => write!(f, "Orphaned"),
+            GptCandidateRole::Nested => write!(f, "Nested"),
+        }
+    }
+}
+
+/// A GPT header found while scanning the image, with the partition entries it points to.
+#[derive(Debug)]
+pub struct GptCandidate {
+    pub lba: u64,
+    // LBA 0 of the disk the header belongs to, the LBAs in the header and the entries are relative to it
+    pub disk_lba: u64,
+    pub copy: GptHeaderCopy,
+    pub role: GptCandidateRole,
+    // The partition of the image holding a nested GPT
+    pub container: Option<String>,
+    pub gpt: GptCopy,
+    // Empty unless the header CRC32 is valid
+    pub entries: Vec<(usize, GptPartitionTableEntry)>,
+}
+
+/// Every LBA of the image starting with the `EFI PART` signature.
+fn find_signatures(path: &Path) -> io::Result<Vec<u64>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut buffer = vec![0u8; SCAN_CHUNK_SECTORS * SECTOR_SIZE];
+    let mut lbas = Vec::new();
+    let mut lba = 0;
+    loop {
+        // Fill the whole chunk unless the end of the image is reached
+        let mut length = 0;
+        while length < buffer.len() {
+            match reader.read(&mut buffer[length..])? {
+                0 => break,
+                read => length += read,
+            }
+        }
+        for sector in buffer[..length].chunks_exact(SECTOR_SIZE) {
+            if sector.starts_with(GPT_SIGNATURE.as_bytes()) {
+                lbas.push(lba);
+            }
+            lba += 1;
+        }
+        if length < buffer.len() {
+            return Ok(lbas);
+        }
+    }
+}
+
+/// Names the partition of the image's own partition table that holds `lba`.
+fn find_container(path: &Path, gpt_in_use: bool, lba: u64) -> Option<String> {
+    if gpt_in_use {
+        return parse_gpt(path)
+            .ok()?
+            .into_iter()
+            .find_map(|(index, entry)| {
+                (entry.starting_lba() <= lba && lba <= entry.ending_lba())
+                    .then(|| format!("GPT partition {}", index + 1))
+            });
+    }
+    parse_mbr(path)
+        .ok()?
+        .partitions()
+        .into_iter()
+        .find(|partition| {
+            !partition.is_extended()
+                && partition.partition_type != GPT_PARTITION_TYPE
+                && partition.starting_lba <= lba
+                && lba <= partition.ending_lba()
+        })
+        .map(|partition| format!("MBR partition {}", partition.number))
+}
+
+/// Scans every LBA of the image for GPT headers and loads the entry arrays of those with a valid header CRC32.
+/// A header whose own `current_lba` differs from where it was found belongs to a disk starting
+/// `current_lba` sectors earlier, which makes it a nested GPT. Headers of the image itself are in use when
+/// the MBR protects a GPT and they are the copies it is read from, and orphaned otherwise.
+pub fn scan_for_gpt(path: &Path) -> io::Result<Vec<GptCandidate>> {
+    let gpt_in_use = parse_mbr(path).map(|mbr| mbr.is_gpt()).unwrap_or(false);
+    // Without an intact copy of the image's own GPT every header found is orphaned or nested
+    let in_use_lbas = match parse_validated_gpt_header(path).ok() {
+        Some(validated) if gpt_in_use => {
+            vec![
+                validated.header.current_lba(),
+                validated.header.backup_lba(),
+            ]
+        }
+        _ => Vec::new(),
+    };
+    let last_lba = image_size_in_sectors(path)?.saturating_sub(1);
+
+    let mut candidates = Vec::new();
+    for lba in find_signatures(path)? {
+        // A header too damaged to read is skipped rather than ending the scan
+        let probe = match GptCopy::read(path, lba) {
+            Ok(probe) => probe,
+            Err(_) => continue,
+        };
+        let current_lba = probe.header.current_lba();
+        let disk_lba = match lba.checked_sub(current_lba) {
+            Some(disk_lba) if current_lba <= last_lba => disk_lba,
+            _ => 0,
+        };
+        let gpt = match disk_lba {
+            0 => probe,
+            _ => match GptCopy::read_at(path, lba, disk_lba) {
+                Ok(gpt) => gpt,
+                Err(_) => continue,
+            },
+        };
+        let copy = if gpt.header.current_lba() < gpt.header.backup_lba() {
+            GptHeaderCopy::Primary
+        } else {
+            GptHeaderCopy::Backup
+        };
+        let role = if disk_lba != 0 {
+            GptCandidateRole::Nested
+        } else if in_use_lbas.contains(&lba) {
+            GptCandidateRole::InUse
+        } else {
+            GptCandidateRole::Orphaned
+        };
+        let container = match role {
+            GptCandidateRole::Nested => find_container(path, gpt_in_use, disk_lba),
+            _ => None,
+        };
+        let entries = match gpt.header_crc32_valid && gpt.entry_array.is_some() {
+            true => parse_gpt_entries(path, &gpt.header, disk_lba).unwrap_or_default(),
+            false => Vec::new(),
+        };
+        candidates.push(GptCandidate {
+            lba,
+            disk_lba,
+            copy,
+            role,
+            container,
+            gpt,
+            entries,
+        });
+    }
+    Ok(candidates)
+}
+
+pub fn display_gpt_candidates(candidates: &[GptCandidate]) {
+    if candidates.is_empty() {
+        println!("No GPT headers found");
+        return;
+    }
+    let mut table = Table::new();
+    table.add_row(row![
+        "LBA",
+        "Disk LBA",
+        "Copy",
+        "Role",
+        "Disk GUID",
+        "Header CRC32",
+        "Entries CRC32",
+        "Partitions"
+    ]);
+    for candidate in candidates {
+        let role = match &candidate.container {
+            Some(container) => format!("{} in {}", candidate.role, container),
+            None => candidate.role.to_string(),
+        };
+        table.add_row(row![
+            candidate.lba,
+            candidate.disk_lba,
+            candidate.copy,
+            role,
+            candidate.gpt.header.disk_guid(),
+            valid_invalid(candidate.gpt.header_crc32_valid),
+            valid_invalid(candidate.gpt.entry_array_crc32_valid),
+            candidate.entries.len()
+        ]);
+    }
+    table.printstd();
+
+    for candidate in candidates
+        .iter()
+        .filter(|candidate| !candidate.entries.is_empty())
+    {
+        println!(
+            "\n{} {} GPT at LBA {}",
+            candidate.role, candidate.copy, candidate.lba
+        );
+        if candidate.disk_lba != 0 {
+            println!("LBAs are relative to LBA {}", candidate.disk_lba);
+        }
+        display_gpt(candidate.entries.clone());
+    }
+}
+
+#[test]
+fn test_scan_for_gpt() {
+    use crate::{bytestream::write_sectors_to_file, guid::Guid};
+
+    // A 2048 sector disk stored in partition 1 (LBA 2048-4095) of the outer image
+    let path = crate::gpt::write_test_gpt("scan", 8192);
+    let inner_path = crate::bytestream::blank_test_image("scan_inner", 2048);
+    crate::gpt::write_new_gpt(
+        &inner_path,
+        Guid::new_random().unwrap(),
+        None,
+        None,
+        128,
+        &[],
+    )
+    .unwrap();
+    let inner = std::fs::read(&inner_path).unwrap();
+    std::fs::remove_file(&inner_path).unwrap();
+    write_sectors_to_file(&path, 2048, &inner).unwrap();
+
+    let summary =
+        |candidates: Vec<GptCandidate>| -> Vec<(u64, u64, GptHeaderCopy, GptCandidateRole)> {
+            candidates
+                .iter()
+                .map(|candidate| {
+                    (
+                        candidate.lba,
+                        candidate.disk_lba,
+                        candidate.copy,
+                        candidate.role,
+                    )
+                })
+                .collect()
+        };
+    let candidates = scan_for_gpt(&path).unwrap();
+    assert_eq!(candidates[1].container.as_deref(), Some("GPT partition 1"));
+    assert_eq!(candidates[2].container.as_deref(), Some("GPT partition 1"));
+    assert_eq!(candidates[0].entries.len(), 1);
+    assert_eq!(
+        summary(candidates),
+        vec![
+            (1, 0, GptHeaderCopy::Primary, GptCandidateRole::InUse),
+            (2049, 2048, GptHeaderCopy::Primary, GptCandidateRole::Nested),
+            (4095, 2048, GptHeaderCopy::Backup, GptCandidateRole::Nested),
+            (8191, 0, GptHeaderCopy::Backup, GptCandidateRole::InUse),
+        ]
+    );
+
+    // Repartitioning with MBR leaves the GPT behind
+    write_sectors_to_file(&path, 0, &[0u8; SECTOR_SIZE]).unwrap();
+    let candidates = scan_for_gpt(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(candidates[0].role, GptCandidateRole::Orphaned);
+    assert_eq!(candidates[3].role, GptCandidateRole::Orphaned);
+    assert!(candidates[0].gpt.problem().is_none());
+}
+
+#[test]
+fn test_scan_for_gpt_damaged_primary() {
+    use crate::bytestream::write_sectors_to_file;
+
+    let path = crate::gpt::write_test_gpt("scan_damaged", 8192);
+    write_sectors_to_file(&path, 1, &[0xFF, 0xFE, 0x80, 0xC3, 0x28, 0xA0, 0xA1, 0xE2]).unwrap();
+    let candidates = scan_for_gpt(&path).unwrap();
+    assert_eq!(candidates.len(), 1);
+    assert_eq!(candidates[0].lba, 8191);
+    assert_eq!(candidates[0].role, GptCandidateRole::InUse);
+    assert_eq!(candidates[0].entries.len(), 1);
+
+    // A signature followed by garbage is listed with its broken CRC32
+    let mut garbage = GPT_SIGNATURE.as_bytes().to_vec();
+    garbage.resize(SECTOR_SIZE, 0xFF);
+    write_sectors_to_file(&path, 1, &garbage).unwrap();
+    let candidates = scan_for_gpt(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(candidates.len(), 2);
+    assert!(!candidates[0].gpt.header_crc32_valid);
+    assert!(candidates[0].entries.is_empty());
+}
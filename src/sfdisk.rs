@@ -0,0 +1,511 @@
+use crate::{
+    gpt::{
+        parse_gpt, parse_validated_gpt_header, write_new_gpt, GptHeader, GptPartitionTableEntry,
+        DEFAULT_NUMBER_PARTITION_ENTRIES, GPT_TYPE_SPECIFIC_ATTRIBUTES_START,
+    },
+    guid::Guid,
+    mbr::{parse_mbr, read_disk_signature, write_mbr, MbrPartition},
+    partition_types::registry,
+};
+use std::{fmt::Write, io, path::Path, str::FromStr};
+
+// https://man7.org/linux/man-pages/man8/sfdisk.8.html
+const SFDISK_GPT_ATTRIBUTE_NAMES: [(u32, &str); 3] = [
+    (0, "RequiredPartition"),
+    (1, "NoBlockIOProtocol"),
+    (2, "LegacyBIOSBootable"),
+];
+
+fn invalid_script(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Partition device names follow the kernel convention, a `p` separates the number from names ending in a digit.
+fn partition_device_name(device: &str, number: u32) -> String {
+    if device.ends_with(|c: char| c.is_ascii_digit()) {
+        format!("{}p{}", device, number)
+    } else {
+        format!("{}{}", device, number)
+    }
+}
+
+fn format_gpt_attributes(attributes: u64) -> String {
+    let mut names: Vec<String> = Vec::new();
+    let mut type_specific: Vec<String> = Vec::new();
+    for bit in 0..64 {
+        if attributes & (1 << bit) == 0 {
+            continue;
+        }
+        match SFDISK_GPT_ATTRIBUTE_NAMES
+            .iter()
+            .find(|(named_bit, _)| *named_bit == bit)
+        {
+            Some((_, name)) => names.push(name.to_string()),
+            // Bits 48-63 are reserved for the partition type and printed as `GUID:<bit>`
+            None if bit >= GPT_TYPE_SPECIFIC_ATTRIBUTES_START => {
+                type_specific.push(bit.to_string())
+            }
+            None => names.push(bit.to_string()),
+        }
+    }
+    if !type_specific.is_empty() {
+        names.push(format!("GUID:{}", type_specific.join(",")));
+    }
+    names.join(" ")
+}
+
+pub fn parse_gpt_attributes(attributes: &str) -> io::Result<u64> {
+    let invalid = || invalid_script(format!("Invalid GPT attributes `{}`", attributes));
+    let parse_bit = |bit: &str| -> io::Result<u64> {
+        match bit.trim().parse::<u32>() {
+            Ok(bit) if bit < 64 => Ok(1 << bit),
+            _ => Err(invalid()),
+        }
+    };
+
+    let mut flags = 0;
+    for attribute in attributes.split(|c: char| c.is_whitespace() || c == ',') {
+        if attribute.is_empty() {
+            continue;
+        }
+        if let Some((bit, _)) = SFDISK_GPT_ATTRIBUTE_NAMES
+            .iter()
+            .find(|(_, name)| *name == attribute)
+        {
+            flags |= 1u64 << bit;
+        } else if let Some(bits) = attribute.strip_prefix("GUID:") {
+            for bit in bits.split(',') {
+                flags |= parse_bit(bit)?;
+            }
+        } else {
+            flags |= parse_bit(attribute)?;
+        }
+    }
+    Ok(flags)
+}
+
+fn dump_mbr(device: &str, disk_signature: u32, partitions: &[MbrPartition]) -> String {
+    let mut dump = String::new();
+    let _ = writeln!(dump, "label: dos");
+    let _ = writeln!(dump, "label-id: {:#010x}", disk_signature);
+    let _ = writeln!(dump, "device: {}", device);
+    let _ = writeln!(dump, "unit: sectors");
+    let _ = writeln!(dump, "sector-size: 512");
+    let _ = writeln!(dump);
+    for partition in partitions {
+        let _ = writeln!(
+            dump,
+            "{} : start={:>12}, size={:>12}, type={:x}{}",
+            partition_device_name(device, partition.number),
+            partition.starting_lba,
+            partition.num_sectors,
+            partition.partition_type,
+            if partition.bootable { ", bootable" } else { "" }
+        );
+    }
+    dump
+}
+
+fn dump_gpt(
+    device: &str,
+    header: &GptHeader,
+    entries: &[(usize, GptPartitionTableEntry)],
+) -> String {
+    let mut dump = String::new();
+    let _ = writeln!(dump, "label: gpt");
+    let _ = writeln!(dump, "label-id: {}", header.disk_guid());
+    let _ = writeln!(dump, "device: {}", device);
+    let _ = writeln!(dump, "unit: sectors");
+    let _ = writeln!(dump, "first-lba: {}", header.first_usable_lba());
+    let _ = writeln!(dump, "last-lba: {}", header.last_usable_lba());
+    if header.number_partition_entries() != DEFAULT_NUMBER_PARTITION_ENTRIES {
+        let _ = writeln!(dump, "table-length: {}", header.number_partition_entries());
+    }
+    let _ = writeln!(dump, "sector-size: 512");
+    let _ = writeln!(dump);
+    for (index, entry) in entries {
+        let _ = write!(
+            dump,
+            "{} : start={:>12}, size={:>12}, type={}, uuid={}",
+            partition_device_name(device, *index as u32 + 1),
+            entry.starting_lba(),
+            entry.ending_lba() - entry.starting_lba() + 1,
+            entry.partition_type(),
+            entry.unique_partition_guid()
+        );
+        let name = entry.name();
+        if !name.is_empty() {
+            let _ = write!(dump, ", name=\"{}\"", name);
+        }
+        if entry.attributes() != 0 {
+            let _ = write!(
+                dump,
+                ", attrs=\"{}\"",
+                format_gpt_attributes(entry.attributes())
+            );
+        }
+        let _ = writeln!(dump);
+    }
+    dump
+}
+
+/// Dumps the MBR or GPT of the image in the format of `sfdisk --dump`, `device` is the name used in the script.
+pub fn dump_partition_table(path: &Path, device: &str) -> io::Result<String> {
+    let mbr = parse_mbr(path)?;
+    if mbr.is_gpt() {
+        let header = parse_validated_gpt_header(path)?.header;
+        let entries = parse_gpt(path)?;
+        Ok(dump_gpt(device, &header, &entries))
+    } else {
+        Ok(dump_mbr(
+            device,
+            read_disk_signature(path)?,
+            &mbr.partitions(),
+        ))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SfdiskLabel {
+    Dos,
+    Gpt,
+}
+
+#[derive(Debug, Default)]
+pub struct SfdiskPartition {
+    number: Option<u32>,
+    start: Option<u64>,
+    size: Option<u64>,
+    partition_type: Option<String>,
+    uuid: Option<Guid>,
+    name: Option<String>,
+    attributes: Option<String>,
+    bootable: bool,
+}
+
+#[derive(Debug)]
+pub struct SfdiskScript {
+    label: SfdiskLabel,
+    label_id: Option<String>,
+    first_lba: Option<u64>,
+    last_lba: Option<u64>,
+    table_length: Option<u32>,
+    partitions: Vec<SfdiskPartition>,
+}
+
+// Splits `fields` on commas that are not inside a quoted value.
+pub fn split_fields(fields: &str) -> Vec<&str> {
+    let mut result = Vec::new();
+    let mut in_quotes = false;
+    let mut field_start = 0;
+    for (index, c) in fields.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => {
+                result.push(fields[field_start..index].trim());
+                field_start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    result.push(fields[field_start..].trim());
+    result
+        .into_iter()
+        .filter(|field| !field.is_empty())
+        .collect()
+}
+
+fn parse_number<T: FromStr>(key: &str, value: &str) -> io::Result<T> {
+    value
+        .trim()
+        .parse::<T>()
+        .map_err(|_| invalid_script(format!("Invalid value `{}` for `{}`", value, key)))
+}
+
+fn parse_partition_line(line: &str) -> io::Result<SfdiskPartition> {
+    let mut partition = SfdiskPartition::default();
+    let fields = match line.split_once(" : ") {
+        Some((device, fields)) => {
+            let digits: String = device
+                .trim()
+                .chars()
+                .rev()
+                .take_while(|c| c.is_ascii_digit())
+                .collect();
+            if !digits.is_empty() {
+                partition.number = Some(parse_number(
+                    "device",
+                    &digits.chars().rev().collect::<String>(),
+                )?);
+            }
+            fields
+        }
+        None => line,
+    };
+
+    for field in split_fields(fields) {
+        let (key, value) = match field.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim().trim_matches('"')),
+            None => (field, ""),
+        };
+        match key {
+            "start" => partition.start = Some(parse_number(key, value)?),
+            "size" => partition.size = Some(parse_number(key, value)?),
+            "type" | "Id" => partition.partition_type = Some(value.into()),
+            "uuid" => {
+                partition.uuid = Some(
+                    value
+                        .parse::<Guid>()
+                        .map_err(|error| invalid_script(error.to_string()))?,
+                )
+            }
+            "name" => partition.name = Some(value.into()),
+            "attrs" => partition.attributes = Some(value.into()),
+            "bootable" => partition.bootable = true,
+            _ => return Err(invalid_script(format!("Unknown partition field `{}`", key))),
+        }
+    }
+    Ok(partition)
+}
+
+impl FromStr for SfdiskScript {
+    type Err = io::Error;
+
+    fn from_str(script: &str) -> Result<Self, Self::Err> {
+        let mut result = Self {
+            label: SfdiskLabel::Dos,
+            label_id: None,
+            first_lba: None,
+            last_lba: None,
+            table_length: None,
+            partitions: Vec::new(),
+        };
+
+        for line in script.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            // Only partition lines contain `key=value` fields
+            if line.contains('=') {
+                result.partitions.push(parse_partition_line(line)?);
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once(':')
+                .ok_or_else(|| invalid_script(format!("Invalid line `{}`", line)))?;
+            let value = value.trim();
+            match key.trim() {
+                "label" => {
+                    result.label = match value {
+                        "dos" => SfdiskLabel::Dos,
+                        "gpt" => SfdiskLabel::Gpt,
+                        _ => return Err(invalid_script(format!("Unsupported label `{}`", value))),
+                    }
+                }
+                "label-id" => result.label_id = Some(value.into()),
+                "first-lba" => result.first_lba = Some(parse_number(key, value)?),
+                "last-lba" => result.last_lba = Some(parse_number(key, value)?),
+                "table-length" => result.table_length = Some(parse_number(key, value)?),
+                "unit" if value != "sectors" => {
+                    return Err(invalid_script(format!("Unsupported unit `{}`", value)))
+                }
+                "sector-size" if value != "512" => {
+                    return Err(invalid_script(format!(
+                        "Unsupported sector size `{}`",
+                        value
+                    )))
+                }
+                // The device of the dump is irrelevant when applying the script
+                "device" | "unit" | "sector-size" => {}
+                _ => return Err(invalid_script(format!("Unknown header `{}`", key))),
+            }
+        }
+        Ok(result)
+    }
+}
+
+impl SfdiskPartition {
+    fn range(&self) -> io::Result<(u64, u64)> {
+        match (self.start, self.size) {
+            (Some(start), Some(size)) if size > 0 => Ok((start, size)),
+            _ => Err(invalid_script(
+                "Every partition needs a `start` and a non-zero `size` in sectors".into(),
+            )),
+        }
+    }
+}
+
+fn lookup_mbr_type(partition_type: &str) -> io::Result<u8> {
+    registry()
+        .find_mbr(partition_type)
+        .ok_or_else(|| invalid_script(format!("Unknown MBR partition type `{}`", partition_type)))
+}
+
+fn lookup_gpt_type(partition_type: &str) -> io::Result<Guid> {
+    registry()
+        .find_gpt(partition_type)
+        .ok_or_else(|| invalid_script(format!("Unknown GPT partition type `{}`", partition_type)))
+}
+
+fn apply_dos_script(path: &Path, script: &SfdiskScript) -> io::Result<()> {
+    let disk_signature = match &script.label_id {
+        Some(label_id) => Some(
+            u32::from_str_radix(label_id.trim_start_matches("0x"), 16)
+                .map_err(|_| invalid_script(format!("Invalid DOS label-id `{}`", label_id)))?,
+        ),
+        None => None,
+    };
+
+    let mut partitions: Vec<MbrPartition> = Vec::new();
+    let mut next_primary = 1;
+    let mut next_logical = 5;
+    for partition in script.partitions.iter() {
+        let (starting_lba, num_sectors) = partition.range()?;
+        let partition_type = lookup_mbr_type(partition.partition_type.as_deref().unwrap_or("L"))?;
+        // Unnumbered partitions inside an extended partition become logical partitions
+        let inside_extended = partitions.iter().any(|extended| {
+            extended.is_extended()
+                && starting_lba > extended.starting_lba
+                && starting_lba <= extended.ending_lba()
+        });
+        let number = match partition.number {
+            Some(number) => number,
+            None if inside_extended => next_logical,
+            None => next_primary,
+        };
+        if number == 0 || partitions.iter().any(|existing| existing.number == number) {
+            return Err(invalid_script(format!(
+                "Invalid or duplicate partition number {}",
+                number
+            )));
+        }
+        if number <= 4 {
+            next_primary = number + 1;
+        } else {
+            next_logical = number + 1;
+        }
+        if next_primary > 5 {
+            return Err(invalid_script(
+                "A DOS label holds at most 4 primary partitions".into(),
+            ));
+        }
+        partitions.push(MbrPartition {
+            number,
+            bootable: partition.bootable,
+            partition_type,
+            starting_lba,
+            num_sectors,
+        });
+    }
+    write_mbr(path, disk_signature, &partitions)
+}
+
+fn apply_gpt_script(path: &Path, script: &SfdiskScript) -> io::Result<()> {
+    let disk_guid = match &script.label_id {
+        Some(label_id) => label_id
+            .parse::<Guid>()
+            .map_err(|error| invalid_script(error.to_string()))?,
+        None => Guid::new_random()?,
+    };
+
+    let mut entries: Vec<(usize, GptPartitionTableEntry)> = Vec::new();
+    let mut next_number = 1;
+    for partition in script.partitions.iter() {
+        let (starting_lba, num_sectors) = partition.range()?;
+        let number = partition.number.unwrap_or(next_number);
+        if number == 0
+            || entries
+                .iter()
+                .any(|(index, _)| *index + 1 == number as usize)
+        {
+            return Err(invalid_script(format!(
+                "Invalid or duplicate partition number {}",
+                number
+            )));
+        }
+        next_number = number + 1;
+
+        let partition_type = lookup_gpt_type(partition.partition_type.as_deref().unwrap_or("L"))?;
+        let unique_partition_guid = match partition.uuid {
+            Some(uuid) => uuid,
+            None => Guid::new_random()?,
+        };
+        let attributes = match &partition.attributes {
+            Some(attributes) => parse_gpt_attributes(attributes)?,
+            None => 0,
+        };
+        entries.push((
+            number as usize - 1,
+            GptPartitionTableEntry::new(
+                partition_type,
+                unique_partition_guid,
+                starting_lba,
+                starting_lba + num_sectors - 1,
+                attributes,
+                partition.name.as_deref().unwrap_or(""),
+            ),
+        ));
+    }
+
+    write_new_gpt(
+        path,
+        disk_guid,
+        script.first_lba,
+        script.last_lba,
+        script
+            .table_length
+            .unwrap_or(DEFAULT_NUMBER_PARTITION_ENTRIES),
+        &entries,
+    )
+}
+
+/// Writes the partition table described by an sfdisk script to the image.
+pub fn apply_sfdisk_script(path: &Path, script: &SfdiskScript) -> io::Result<()> {
+    match script.label {
+        SfdiskLabel::Dos => apply_dos_script(path, script),
+        SfdiskLabel::Gpt => apply_gpt_script(path, script),
+    }
+}
+
+#[test]
+fn test_sfdisk_dos_round_trip() {
+    let script = "\
+label: dos
+label-id: 0x1a2b3c4d
+device: test.dd
+unit: sectors
+sector-size: 512
+
+test.dd1 : start=        2048, size=        2048, type=83, bootable
+test.dd2 : start=        4096, size=        4096, type=5
+test.dd5 : start=        4160, size=        1024, type=7
+test.dd6 : start=        5248, size=        2944, type=82
+";
+    let path = crate::bytestream::blank_test_image("sfdisk_dos", 8192);
+    apply_sfdisk_script(&path, &script.parse::<SfdiskScript>().unwrap()).unwrap();
+    let dump = dump_partition_table(&path, "test.dd").unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(dump, script);
+}
+
+#[test]
+fn test_sfdisk_gpt_round_trip() {
+    let script = "\
+label: gpt
+label-id: 88981628-4F29-4224-A409-E247B756F0D4
+device: test.dd
+unit: sectors
+first-lba: 34
+last-lba: 8158
+sector-size: 512
+
+test.dd1 : start=        2048, size=        2048, type=C12A7328-F81F-11D2-BA4B-00A0C93EC93B, uuid=0B2B2FC3-69D1-4C3D-8E4C-2E0C1D0F3A11, name=\"EFI, system\", attrs=\"RequiredPartition GUID:60,63\"
+test.dd2 : start=        4096, size=        4063, type=0FC63DAF-8483-4772-8E79-3D69D8477DE4, uuid=6E0F5A9B-3C1D-4B8E-9F2A-7D4C5B6A8E90
+";
+    let path = crate::bytestream::blank_test_image("sfdisk_gpt", 8192);
+    apply_sfdisk_script(&path, &script.parse::<SfdiskScript>().unwrap()).unwrap();
+    let dump = dump_partition_table(&path, "test.dd").unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(dump, script);
+}